@@ -0,0 +1,91 @@
+//! Allocations and time spent parsing a large playlist into the owned [`MediaPlaylist`] and
+//! the borrowed [`MediaPlaylistRef`]. Run with `cargo bench --bench allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use disney_hls_parser::{MediaPlaylist, MediaPlaylistRef};
+
+// Counts every allocation going through the global allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const SEGMENTS: usize = 10_000;
+const RUNS: u32 = 20;
+
+// An event playlist with a discontinuity every 100 segments and a key rotation every 1000.
+fn playlist() -> String {
+    let mut source = String::from("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n");
+    for index in 0..SEGMENTS {
+        if index % 1000 == 0 {
+            source.push_str(&format!("#EXT-X-KEY:METHOD=AES-128,URI=\"https://keys.example/{}\"\n", index));
+        }
+        if index % 100 == 0 && index > 0 {
+            source.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        source.push_str(&format!("#EXTINF:6.006,\nhttps://cdn.example/event/1080p/segment_{:06}.ts\n", index));
+    }
+    source.push_str("#EXT-X-ENDLIST\n");
+    source
+}
+
+// Allocations, bytes and mean time of one run of `parse`.
+fn measure(parse: impl Fn()) -> (usize, usize, Duration) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    parse();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+
+    let started = Instant::now();
+    for _ in 0..RUNS {
+        parse();
+    }
+    (allocations, bytes, started.elapsed() / RUNS)
+}
+
+fn main() {
+    let source = playlist();
+    println!("{} segments, {} bytes of playlist\n", SEGMENTS, source.len());
+    println!("{:<24}{:>12}{:>14}{:>12}", "parser", "allocations", "bytes", "time");
+
+    let results = [
+        ("MediaPlaylist", measure(|| drop(black_box(MediaPlaylist::parse_ext_m3u(&source).unwrap())))),
+        ("MediaPlaylistRef", measure(|| drop(black_box(MediaPlaylistRef::parse(&source).unwrap())))),
+    ];
+    for (name, (allocations, bytes, time)) in &results {
+        println!("{:<24}{:>12}{:>14}{:>12.2?}", name, allocations, bytes, time);
+    }
+
+    let (owned, borrowed) = (results[0].1, results[1].1);
+    println!(
+        "\nMediaPlaylistRef makes {:.1}x fewer allocations and allocates {:.1}x fewer bytes",
+        owned.0 as f64 / borrowed.0 as f64,
+        owned.1 as f64 / borrowed.1 as f64
+    );
+}
@@ -0,0 +1,184 @@
+//! Async parsing with tokio, behind the `async` cargo feature.
+//!
+//! [`SegmentStream`] reads a playlist from an [`AsyncBufRead`] and yields every media segment as
+//! soon as its URI line has arrived, so segments can be scheduled for download while the rest of
+//! the playlist is still coming in. It wraps a [`StreamingParser`], so the playlist it finally
+//! gives is exactly the one [`MediaPlaylist::parse_ext_m3u`] would.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use anyhow::{anyhow, Result};
+use futures_core::Stream;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::media_playlist::{MediaPlaylist, MediaSegment};
+use crate::streaming::{ParseEvent, StreamingParser};
+
+/// A stream of the media segments of a playlist as it is read. Each segment is yielded as soon
+/// as its URI line is complete, and [`finish`][SegmentStream::finish] then gives the whole
+/// playlist.
+///
+/// The stream yields an error, and then ends, if reading fails or the playlist is invalid.
+#[derive(Debug)]
+pub struct SegmentStream<R> {
+    reader: R,
+    parser: StreamingParser,
+    // segments parsed but not yet yielded
+    ready: VecDeque<MediaSegment>,
+    // whether the last byte read was a line terminator, the last line may have none
+    at_line_start: bool,
+    read_any: bool,
+    done: bool,
+    failed: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> SegmentStream<R> {
+    pub fn new(reader: R) -> Self {
+        SegmentStream {
+            reader,
+            parser: StreamingParser::new(),
+            ready: VecDeque::new(),
+            at_line_start: true,
+            read_any: false,
+            done: false,
+            failed: false,
+        }
+    }
+
+    /// Reads whatever is left of the playlist and builds it, including the segments already
+    /// yielded by the stream.
+    pub async fn finish(mut self) -> Result<MediaPlaylist> {
+        if self.failed {
+            return Err(anyhow!("The playlist could not be read"));
+        }
+        while !self.done {
+            let buffer = self.reader.fill_buf().await?;
+            if buffer.is_empty() {
+                break;
+            }
+            let length = buffer.len();
+            self.at_line_start = buffer.ends_with(b"\n");
+            self.parser.feed(buffer)?;
+            self.reader.consume(length);
+        }
+        self.parser.finish()
+    }
+
+    // Feeds the next chunk of input to the parser, queuing the segments it completes. Returns
+    // false at the end of the input.
+    fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool>> {
+        let buffer = ready!(Pin::new(&mut self.reader).poll_fill_buf(cx))?;
+        let length = buffer.len();
+        let events = if length == 0 {
+            if self.at_line_start || !self.read_any {
+                return Poll::Ready(Ok(false));
+            }
+            // terminate the last line so its segment is reported too
+            self.at_line_start = true;
+            self.parser.feed(b"\n")?
+        } else {
+            self.read_any = true;
+            self.at_line_start = buffer.ends_with(b"\n");
+            let events = self.parser.feed(buffer)?;
+            Pin::new(&mut self.reader).consume(length);
+            events
+        };
+        self.ready.extend(events.into_iter().filter_map(|event| match event {
+            ParseEvent::Segment { segment, .. } => Some(*segment),
+            ParseEvent::Tag(_) => None,
+        }));
+        Poll::Ready(Ok(true))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for SegmentStream<R> {
+    type Item = Result<MediaSegment>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(segment) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(segment)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            match ready!(this.poll_read(cx)) {
+                Ok(true) => {}
+                Ok(false) => this.done = true,
+                Err(err) => {
+                    this.done = true;
+                    this.failed = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+}
+
+impl MediaPlaylist {
+    /// Parses a playlist from `reader` as its bytes arrive, see [`SegmentStream`] to get the
+    /// segments before the whole playlist has been read.
+    pub async fn from_async_reader(reader: impl AsyncBufRead + Unpin) -> Result<Self> {
+        SegmentStream::new(reader).finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    const PLAYLIST: &str = indoc::indoc! {"
+        #EXTM3U
+        #EXT-X-VERSION:3
+        #EXT-X-TARGETDURATION:10
+        #EXTINF:9.5,
+        a.ts
+        #EXT-X-DISCONTINUITY
+        #EXTINF:4,
+        b.ts
+        #EXT-X-ENDLIST
+    "};
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn parses_from_an_async_reader() {
+        let expected = MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap();
+        assert_eq!(MediaPlaylist::from_async_reader(PLAYLIST.as_bytes()).await.unwrap(), expected);
+        // one byte at a time, without a line terminator at the end
+        let reader = BufReader::with_capacity(1, PLAYLIST.trim_end().as_bytes());
+        assert_eq!(MediaPlaylist::from_async_reader(reader).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn yields_segments_before_the_playlist_is_complete() {
+        let (mut writer, reader) = tokio::io::duplex(1024);
+        let mut segments = SegmentStream::new(BufReader::new(reader));
+
+        let (head, tail) = PLAYLIST.split_at(PLAYLIST.find("#EXT-X-DISCONTINUITY").unwrap());
+        writer.write_all(head.as_bytes()).await.unwrap();
+        assert_eq!(next(&mut segments).await.unwrap().unwrap().url(), "a.ts");
+
+        writer.write_all(tail.trim_end().as_bytes()).await.unwrap();
+        drop(writer);
+        assert_eq!(next(&mut segments).await.unwrap().unwrap().url(), "b.ts");
+        assert!(next(&mut segments).await.is_none());
+        assert!(segments.finish().await.unwrap().ended());
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_playlists() {
+        let mut segments = SegmentStream::new(&b"#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\n\xff.ts\n"[..]);
+        assert!(next(&mut segments).await.unwrap().is_err());
+        assert!(next(&mut segments).await.is_none());
+        assert!(segments.finish().await.is_err());
+        assert!(MediaPlaylist::from_async_reader(&b""[..]).await.is_err());
+    }
+}
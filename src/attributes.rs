@@ -0,0 +1,86 @@
+//! Parsing of tag attribute lists, e.g. `TIME-OFFSET=-12.5,PRECISE=YES`. See
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-4.2>.
+
+use anyhow::{anyhow, Result};
+
+/// The `NAME=value` pairs of an attribute list, in order. Quoted-string values are stored
+/// without their quotes, and also as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AttributeList<'a> {
+    // name, value and value as written
+    attributes: Vec<(&'a str, &'a str, &'a str)>,
+}
+
+impl<'a> AttributeList<'a> {
+    pub(crate) fn parse(list: &'a str) -> Result<Self> {
+        let mut attributes = Vec::new();
+        let mut rest = list.trim();
+        while !rest.is_empty() {
+            let (name, after_name) = rest
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Attribute without value in '{}'", list))?;
+
+            // a quoted string may contain commas, so it ends at the closing quote
+            let (value, raw, after_value) = match after_name.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted
+                        .find('"')
+                        .ok_or_else(|| anyhow!("Unterminated quoted string in '{}'", list))?;
+                    (&quoted[..end], &after_name[..end + 2], &quoted[end + 1..])
+                }
+                None => {
+                    let (value, after_value) = after_name.split_at(after_name.find(',').unwrap_or(after_name.len()));
+                    (value, value, after_value)
+                }
+            };
+
+            attributes.push((name.trim(), value, raw));
+            rest = match after_value.trim_start().strip_prefix(',') {
+                Some(next) => next.trim_start(),
+                None if after_value.trim().is_empty() => "",
+                None => return Err(anyhow!("Expected ',' after attribute {} in '{}'", name, list)),
+            };
+        }
+        Ok(AttributeList { attributes })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _, _)| *attribute == name)
+            .map(|(_, value, _)| *value)
+    }
+
+    /// Every attribute with its value as written, quotes included.
+    pub(crate) fn raw(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.attributes.iter().map(|(name, _, raw)| (*name, *raw))
+    }
+
+    /// Like [`get`][AttributeList::get] but fails when the attribute is missing.
+    pub(crate) fn required(&self, name: &str) -> Result<&'a str> {
+        self.get(name).ok_or_else(|| anyhow!("Missing attribute {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_and_plain_values() {
+        let list = AttributeList::parse(r#"METHOD=AES-128,URI="key?a=1,b=2",IV=0x1F"#).unwrap();
+        assert_eq!(list.get("METHOD"), Some("AES-128"));
+        assert_eq!(list.get("URI"), Some("key?a=1,b=2"));
+        assert_eq!(list.get("IV"), Some("0x1F"));
+        assert_eq!(list.get("KEYFORMAT"), None);
+        assert!(list.required("KEYFORMAT").is_err());
+        assert_eq!(list.raw().nth(1), Some(("URI", r#""key?a=1,b=2""#)));
+    }
+
+    #[test]
+    fn rejects_malformed_lists() {
+        assert!(AttributeList::parse("PRECISE").is_err());
+        assert!(AttributeList::parse(r#"URI="open"#).is_err());
+        assert!(AttributeList::parse(r#"URI="a"B=1"#).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Zero-copy parsing: [`MediaPlaylistRef`] borrows the segment URIs from the playlist text
+//! instead of copying each of them into a `String`.
+//!
+//! Parsing follows exactly the rules of [`MediaPlaylist::parse_ext_m3u`] (both run the same
+//! line parser), so [`MediaPlaylistRef::to_owned`] gives the same playlist. Keys and maps, which
+//! are shared by many segments, are stored once per tag and referenced by index.
+
+use core::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{
+    ByteRange, DateRange, Key, LineParser, Map, MediaPlaylist, MediaSegment, OwnedSegments, PlaylistType, SegmentLine,
+    SegmentStore, ServerControl, StartPoint,
+};
+
+/// A media playlist borrowing from the text it was parsed from. See
+/// [`MediaPlaylistRef::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlaylistRef<'a> {
+    pub(crate) ended: bool,
+    pub(crate) segments: Vec<MediaSegmentRef<'a>>,
+    pub(crate) target_duration: Duration,
+    pub(crate) version: u64,
+    pub(crate) media_sequence: u64,
+    pub(crate) discontinuity_sequence: u64,
+    pub(crate) start: Option<StartPoint>,
+    pub(crate) server_control: Option<ServerControl>,
+    pub(crate) part_target: Option<Duration>,
+    pub(crate) pending_parts: usize,
+    pub(crate) playlist_type: Option<PlaylistType>,
+    pub(crate) date_ranges: Vec<DateRange>,
+    pub(crate) keys: Vec<Key>,
+    pub(crate) maps: Vec<Map>,
+}
+
+/// A media segment of a [`MediaPlaylistRef`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaSegmentRef<'a> {
+    pub duration: Duration,
+    pub url: &'a str,
+    pub program_date_time: Option<DateTime<FixedOffset>>,
+    /// Discontinuity sequence number of the segment, a new discontinuity group starts whenever
+    /// it changes.
+    pub discontinuity_sequence: u64,
+    /// Index in [`MediaPlaylistRef::keys`] of the decryption key of the segment.
+    pub key: Option<usize>,
+    /// Index in [`MediaPlaylistRef::maps`] of the media initialization section of the segment.
+    pub map: Option<usize>,
+    /// See [`MediaSegment::byte_range`].
+    pub byte_range: Option<ByteRange>,
+    /// See [`MediaSegment::gap`].
+    pub gap: bool,
+}
+
+// Segments, keys and maps of a `MediaPlaylistRef` while parsing.
+#[derive(Debug, Default)]
+struct BorrowedSegments<'a> {
+    segments: Vec<MediaSegmentRef<'a>>,
+    keys: Vec<Key>,
+    maps: Vec<Map>,
+}
+
+// Index of `value` in `values`, which only grows when `value` differs from the last one: keys and
+// maps apply to every segment until the next tag.
+fn intern<T: Clone + PartialEq>(values: &mut Vec<T>, value: Option<&T>) -> Option<usize> {
+    let value = value?;
+    if values.last() != Some(value) {
+        values.push(value.clone());
+    }
+    Some(values.len() - 1)
+}
+
+impl<'a> SegmentStore<'a> for BorrowedSegments<'a> {
+    fn push(&mut self, segment: SegmentLine<'a, '_>) {
+        let key = intern(&mut self.keys, segment.key);
+        let map = intern(&mut self.maps, segment.map);
+        self.segments.push(MediaSegmentRef {
+            duration: segment.duration,
+            url: segment.url,
+            program_date_time: segment.program_date_time,
+            discontinuity_sequence: segment.discontinuity_sequence,
+            key,
+            map,
+            byte_range: segment.byte_range,
+            gap: segment.gap,
+        });
+    }
+}
+
+impl<'a> MediaPlaylistRef<'a> {
+    /// Parses `file` like [`MediaPlaylist::parse_ext_m3u`], borrowing the segment URIs from it.
+    pub fn parse(file: &'a str) -> Result<Self> {
+        let mut lines = file.lines();
+        if lines.next().unwrap_or_default() != "#EXTM3U" {
+            return Err(anyhow!("Missing #EXTM3U header"));
+        }
+
+        let mut parser = LineParser::new(BorrowedSegments::default());
+        for line in lines {
+            parser.line(line)?;
+        }
+
+        let version = parser.version.ok_or_else(|| anyhow!("Missing #EXT-X-VERSION"))?;
+        Ok(MediaPlaylistRef {
+            ended: parser.ended,
+            segments: parser.store.segments,
+            target_duration: parser.target_duration,
+            version,
+            media_sequence: parser.media_sequence,
+            discontinuity_sequence: parser.discontinuity_sequence,
+            start: parser.start,
+            server_control: parser.server_control,
+            part_target: parser.part_target,
+            pending_parts: parser.pending_parts,
+            playlist_type: parser.playlist_type,
+            date_ranges: parser.date_ranges,
+            keys: parser.store.keys,
+            maps: parser.store.maps,
+        })
+    }
+
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    pub fn segments(&self) -> &[MediaSegmentRef<'a>] {
+        &self.segments
+    }
+
+    pub fn target_duration(&self) -> Duration {
+        self.target_duration
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn media_sequence(&self) -> u64 {
+        self.media_sequence
+    }
+
+    pub fn discontinuity_sequence(&self) -> u64 {
+        self.discontinuity_sequence
+    }
+
+    pub fn start(&self) -> Option<StartPoint> {
+        self.start
+    }
+
+    pub fn server_control(&self) -> Option<ServerControl> {
+        self.server_control
+    }
+
+    pub fn part_target(&self) -> Option<Duration> {
+        self.part_target
+    }
+
+    pub fn pending_parts(&self) -> usize {
+        self.pending_parts
+    }
+
+    pub fn playlist_type(&self) -> Option<PlaylistType> {
+        self.playlist_type
+    }
+
+    pub fn date_ranges(&self) -> &[DateRange] {
+        &self.date_ranges
+    }
+
+    /// Every #EXT-X-KEY in effect for some segment, in playlist order. Consecutive segments share
+    /// an entry, but one coming back after another is listed again.
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// Every #EXT-X-MAP in effect for some segment, in playlist order. Consecutive segments share
+    /// an entry, but one coming back after another is listed again.
+    pub fn maps(&self) -> &[Map] {
+        &self.maps
+    }
+
+    /// Decryption key of `segment`.
+    pub fn key(&self, segment: &MediaSegmentRef) -> Option<&Key> {
+        segment.key.map(|index| &self.keys[index])
+    }
+
+    /// Media initialization section of `segment`.
+    pub fn map(&self, segment: &MediaSegmentRef) -> Option<&Map> {
+        segment.map.map(|index| &self.maps[index])
+    }
+
+    /// Copies everything into an owned [`MediaPlaylist`], the same as parsing the text with
+    /// [`MediaPlaylist::parse_ext_m3u`].
+    pub fn to_owned(&self) -> MediaPlaylist {
+        let mut owned = OwnedSegments { segments: Vec::with_capacity(self.segments.len()), ..Default::default() };
+        for segment in &self.segments {
+            let copy = MediaSegment {
+                duration: segment.duration,
+                url: segment.url.to_string(),
+                program_date_time: segment.program_date_time,
+                key: self.key(segment).cloned(),
+                map: self.map(segment).cloned(),
+                byte_range: segment.byte_range,
+                gap: segment.gap,
+                resolved_url: None,
+            };
+            owned.push_sequenced(copy, segment.discontinuity_sequence);
+        }
+
+        let mut playlist = MediaPlaylist {
+            ended: self.ended,
+            segments: owned.segments,
+            target_duration: self.target_duration,
+            version: self.version,
+            media_sequence: self.media_sequence,
+            discontinuity_sequence: self.discontinuity_sequence,
+            start: self.start,
+            server_control: self.server_control,
+            part_target: self.part_target,
+            pending_parts: self.pending_parts,
+            playlist_type: self.playlist_type,
+            date_ranges: self.date_ranges.clone(),
+            discontinuity: owned.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        playlist.reindex();
+        playlist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_the_owned_playlist() {
+        let source = indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:6
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:3
+            #EXT-X-DISCONTINUITY-SEQUENCE:1
+            #EXT-X-KEY:METHOD=AES-128,URI="k1.bin"
+            #EXT-X-MAP:URI="init.mp4"
+            #EXT-X-PROGRAM-DATE-TIME:2020-01-01T12:00:00.000Z
+            #EXTINF:9.009,
+            a.m4s
+            #EXTINF:9.009,
+            b.m4s
+            #EXT-X-DISCONTINUITY
+            #EXT-X-KEY:METHOD=NONE
+            #EXTINF:4,
+            c.m4s
+            #EXT-X-KEY:METHOD=AES-128,URI="k2.bin"
+            #EXTINF:4,
+            d.m4s
+            #EXT-X-ENDLIST
+        "#};
+        let playlist = MediaPlaylistRef::parse(source).unwrap();
+
+        assert_eq!(playlist.segments().len(), 4);
+        assert_eq!(playlist.keys().len(), 2);
+        assert_eq!(playlist.maps().len(), 1);
+        let c = &playlist.segments()[2];
+        assert_eq!((c.url, c.discontinuity_sequence, c.key), ("c.m4s", 2, None));
+        assert_eq!(playlist.key(&playlist.segments()[3]).unwrap().uri.as_deref(), Some("k2.bin"));
+        // borrowed, not copied
+        assert!(source.as_bytes().as_ptr_range().contains(&c.url.as_ptr()));
+
+        assert_eq!(playlist.to_owned(), MediaPlaylist::parse_ext_m3u(source).unwrap());
+    }
+
+    #[test]
+    fn round_trips_byte_ranges_and_changing_maps() {
+        let source = indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:6
+            #EXT-X-TARGETDURATION:4
+            #EXT-X-DISCONTINUITY-SEQUENCE:5
+            #EXT-X-DISCONTINUITY
+            #EXT-X-MAP:URI="main.mp4",BYTERANGE="720@0"
+            #EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://asset",KEYFORMAT="com.apple.streamingkeydelivery"
+            #EXTINF:4,
+            #EXT-X-BYTERANGE:1000@720
+            main.mp4
+            #EXTINF:4,
+            #EXT-X-BYTERANGE:1200
+            main.mp4
+            #EXT-X-DISCONTINUITY
+            #EXT-X-MAP:URI="ad.mp4",BYTERANGE="600@0"
+            #EXT-X-KEY:METHOD=NONE
+            #EXT-X-GAP
+            #EXTINF:2,
+            #EXT-X-BYTERANGE:800@600
+            ad.mp4
+            #EXT-X-ENDLIST
+        "#};
+        let playlist = MediaPlaylistRef::parse(source).unwrap();
+        assert_eq!(playlist.maps().len(), 2);
+        let owned = playlist.to_owned();
+        assert_eq!(owned, MediaPlaylist::parse_ext_m3u(source).unwrap());
+
+        let ranges: Vec<_> = owned.segments.iter().map(|segment| segment.byte_range.map(|range| range.length)).collect();
+        assert_eq!(ranges, [Some(1000), Some(1200), Some(800)]);
+        // the offset left out continues the previous range
+        assert_eq!(owned.segments[1].byte_range.unwrap().offset, Some(1720));
+        assert_eq!(owned.segments[2].map.as_ref().unwrap().byte_range.unwrap().offset, Some(0));
+        let groups: Vec<_> = owned.discontinuity.iter().map(|group| group.discontinuity_sequence).collect();
+        assert_eq!(groups, [6, 7]);
+        // and back through the writer
+        assert_eq!(MediaPlaylistRef::parse(&owned.to_string()).unwrap().to_owned(), owned);
+    }
+}
@@ -0,0 +1,260 @@
+//! Cutting a time range out of a media playlist without re-encoding.
+
+use core::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{DateRange, MediaPlaylist, OwnedSegments, PlaylistType, StartPoint};
+
+/// A playlist cut by [`MediaPlaylist::clip_with_offsets`], with the position of the requested
+/// range inside the first and last segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub playlist: MediaPlaylist,
+    /// How far into the first segment the requested range starts.
+    pub start_offset: Duration,
+    /// How far into the last segment the requested range ends.
+    pub end_offset: Duration,
+}
+
+impl MediaPlaylist {
+    /// Returns a VOD playlist with only the segments overlapping `start..end`. See
+    /// [`clip_with_offsets`][MediaPlaylist::clip_with_offsets].
+    pub fn clip(&self, start: Duration, end: Duration) -> Result<MediaPlaylist> {
+        self.clip_with_offsets(start, end).map(|clip| clip.playlist)
+    }
+
+    /// Returns a VOD playlist with only the segments overlapping `start..end`, where times are
+    /// measured from the beginning of the playlist.
+    ///
+    /// Segments keep their media sequence and discontinuity sequence numbers, so the
+    /// EXT-X-DISCONTINUITY tags inside the range stay where they were. The first segment gets an
+    /// EXT-X-PROGRAM-DATE-TIME extrapolated from the last one before it in its discontinuity
+    /// group, only the EXT-X-DATERANGEs overlapping the range are kept, and EXT-X-START is
+    /// moved to `start` when the range begins in the middle of a segment, or kept (relative to
+    /// the clip) when it points inside the range.
+    pub fn clip_with_offsets(&self, start: Duration, end: Duration) -> Result<Clip> {
+        if start >= end {
+            return Err(anyhow!("Clip start {:?} is not before its end {:?}", start, end));
+        }
+
+        let mut clipped = OwnedSegments::default();
+        let mut media_sequence = self.media_sequence;
+        let mut clip_start = Duration::ZERO;
+        let mut start_offset = Duration::ZERO;
+        let mut end_offset = Duration::ZERO;
+        // last EXT-X-PROGRAM-DATE-TIME seen in the current discontinuity group and the playlist
+        // time it belongs to, the clock is not continuous across a discontinuity
+        let mut anchor = None;
+        let mut anchor_group = None;
+        // wall clock interval of each kept segment which has a date
+        let mut dated = Vec::new();
+
+        let mut time = Duration::ZERO;
+        for (index, (group, segment)) in self
+            .discontinuity_groups()
+            .flat_map(|group| group.discontinuity_segments().iter().map(move |segment| (group, segment)))
+            .enumerate()
+        {
+            let segment_start = time;
+            time += segment.duration;
+            if anchor_group != Some(group.discontinuity_sequence) {
+                anchor = None;
+                anchor_group = Some(group.discontinuity_sequence);
+            }
+            if let Some(program_date_time) = segment.program_date_time {
+                anchor = Some((program_date_time, segment_start));
+            }
+            if time <= start || segment_start >= end {
+                continue;
+            }
+
+            let mut segment = segment.clone();
+            let date = match anchor {
+                Some((program_date_time, at)) => {
+                    Some(program_date_time + chrono::Duration::from_std(segment_start - at)?)
+                }
+                None => None,
+            };
+            if let Some(date) = date {
+                dated.push((date, date + chrono::Duration::from_std(segment.duration)?));
+            }
+            if clipped.segments.is_empty() {
+                media_sequence = self.media_sequence + index as u64;
+                clip_start = segment_start;
+                start_offset = start.saturating_sub(segment_start);
+                segment.program_date_time = date;
+            }
+            end_offset = (end - segment_start).min(segment.duration);
+
+            clipped.push_sequenced(segment, group.discontinuity_sequence);
+        }
+
+        let discontinuity_sequence = clipped
+            .discontinuity
+            .first()
+            .map(|group| group.discontinuity_sequence)
+            .ok_or_else(|| anyhow!("No segment between {:?} and {:?}", start, end))?;
+        let clip_duration: Duration = clipped.segments.iter().map(|segment| segment.duration).sum();
+
+        let start_point = if start_offset > Duration::ZERO {
+            Some(StartPoint { time_offset: start_offset.as_secs_f64(), precise: true })
+        } else {
+            self.start.and_then(|point| {
+                // negative offsets count from the end of the original playlist
+                let absolute = match point.time_offset {
+                    offset if offset < 0.0 => time.as_secs_f64() + offset,
+                    offset => offset,
+                };
+                let time_offset = absolute - clip_start.as_secs_f64();
+                (0.0..clip_duration.as_secs_f64())
+                    .contains(&time_offset)
+                    .then_some(StartPoint { time_offset, ..point })
+            })
+        };
+
+        let mut playlist = MediaPlaylist {
+            ended: true,
+            segments: clipped.segments,
+            target_duration: self.target_duration,
+            version: self.version,
+            media_sequence,
+            discontinuity_sequence,
+            start: start_point,
+            // a clip is a finished presentation, there is nothing to reload
+            server_control: None,
+            part_target: None,
+            pending_parts: 0,
+            playlist_type: Some(PlaylistType::Vod),
+            date_ranges: self
+                .date_ranges
+                .iter()
+                .filter(|date_range| dated.iter().any(|&(from, to)| overlaps(date_range, from, to)))
+                .cloned()
+                .collect(),
+            discontinuity: clipped.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        playlist.reindex();
+
+        Ok(Clip {
+            playlist,
+            start_offset,
+            end_offset,
+        })
+    }
+}
+
+// Whether the date range covers some of `from..to`, a range without an end lasting until the
+// end of the presentation.
+fn overlaps(date_range: &DateRange, from: DateTime<FixedOffset>, to: DateTime<FixedOffset>) -> bool {
+    let range_end = date_range.end_date.or_else(|| {
+        let duration = date_range.duration.or(date_range.planned_duration)?;
+        Some(date_range.start_date + chrono::Duration::from_std(duration).ok()?)
+    });
+    date_range.start_date < to
+        && (date_range.start_date >= from || range_end.is_none_or(|range_end| range_end > from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> MediaPlaylist {
+        MediaPlaylist::parse_ext_m3u(indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:100
+            #EXT-X-START:TIME-OFFSET=25
+            #EXT-X-PROGRAM-DATE-TIME:2020-01-01T00:00:00.000+00:00
+            #EXTINF:10,
+            a.ts
+            #EXTINF:10,
+            b.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:10,
+            c.ts
+            #EXTINF:10,
+            d.ts
+            #EXT-X-ENDLIST
+        "})
+        .expect("playlist should parse")
+    }
+
+    #[test]
+    fn keeps_overlapping_segments() {
+        let clip = playlist().clip_with_offsets(Duration::from_secs(15), Duration::from_secs(32)).unwrap();
+        let urls: Vec<_> = clip.playlist.segments.iter().map(|segment| segment.url.as_str()).collect();
+        assert_eq!(urls, vec!["b.ts", "c.ts", "d.ts"]);
+        assert_eq!(clip.start_offset, Duration::from_secs(5));
+        assert_eq!(clip.end_offset, Duration::from_secs(2));
+
+        let playlist = &clip.playlist;
+        assert_eq!(playlist.media_sequence, 101);
+        assert_eq!(playlist.discontinuity_sequence, 0);
+        assert_eq!(playlist.discontinuity.len(), 2);
+        assert_eq!(playlist.discontinuity[1].discontinuity_sequence, 1);
+        assert_eq!(playlist.discontinuity[1].discontinuity_duration, Duration::from_secs(20));
+        assert_eq!(playlist.start, Some(StartPoint { time_offset: 5.0, precise: true }));
+        assert_eq!(
+            playlist.segments[0].program_date_time.unwrap().to_rfc3339(),
+            "2020-01-01T00:00:10+00:00"
+        );
+        assert!(playlist.ended);
+    }
+
+    #[test]
+    fn moves_start_point() {
+        let clipped = playlist().clip(Duration::from_secs(20), Duration::from_secs(40)).unwrap();
+        assert_eq!(clipped.start, Some(StartPoint { time_offset: 5.0, precise: false }));
+        // the discontinuity is now the first segment, so no tag is needed
+        assert_eq!(clipped.discontinuity_sequence, 1);
+        assert!(!clipped.to_string().contains("#EXT-X-DISCONTINUITY\n"));
+
+        let clipped = playlist().clip(Duration::ZERO, Duration::from_secs(10)).unwrap();
+        assert_eq!(clipped.start, None);
+    }
+
+    #[test]
+    fn rejects_empty_ranges() {
+        assert!(playlist().clip(Duration::from_secs(5), Duration::from_secs(5)).is_err());
+        assert!(playlist().clip(Duration::from_secs(50), Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn does_not_date_across_a_discontinuity() {
+        let clip = playlist().clip_with_offsets(Duration::from_secs(25), Duration::from_secs(40)).unwrap();
+        assert_eq!(clip.playlist.segments[0].url, "c.ts");
+        assert_eq!(clip.playlist.segments[0].program_date_time, None);
+        assert_eq!(clip.playlist.playlist_type, Some(PlaylistType::Vod));
+    }
+
+    #[test]
+    fn keeps_overlapping_date_ranges() {
+        let playlist = MediaPlaylist::parse_ext_m3u(indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-PLAYLIST-TYPE:EVENT
+            #EXT-X-DATERANGE:ID="early",START-DATE="2020-01-01T00:00:00Z",DURATION=10
+            #EXT-X-DATERANGE:ID="middle",START-DATE="2020-01-01T00:00:12Z",DURATION=4
+            #EXT-X-DATERANGE:ID="open",START-DATE="2020-01-01T00:00:05Z"
+            #EXT-X-DATERANGE:ID="late",START-DATE="2020-01-01T00:00:20Z"
+            #EXT-X-PROGRAM-DATE-TIME:2020-01-01T00:00:00.000+00:00
+            #EXTINF:10,
+            a.ts
+            #EXTINF:10,
+            b.ts
+            #EXTINF:10,
+            c.ts
+        "#})
+        .unwrap();
+        let clipped = playlist.clip(Duration::from_secs(10), Duration::from_secs(20)).unwrap();
+        let ids: Vec<_> = clipped.date_ranges.iter().map(|date_range| date_range.id.as_str()).collect();
+        assert_eq!(ids, ["middle", "open"]);
+        assert_eq!(clipped.playlist_type, Some(PlaylistType::Vod));
+    }
+}
@@ -0,0 +1,226 @@
+//! Stitching several media playlists into one, e.g. pre-roll + content + post-roll.
+
+use core::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{MediaPlaylist, OwnedSegments};
+use crate::uri;
+
+impl MediaPlaylist {
+    /// Joins `playlists` one after the other with an EXT-X-DISCONTINUITY at each seam.
+    ///
+    /// Every discontinuity group of the inputs becomes a group of the result with the same
+    /// segments and `discontinuity_duration`. The target duration and version are the largest
+    /// of the inputs, and the result only has an EXT-X-ENDLIST if every input has one. Segment
+    /// URLs are copied as is, see [`concat_with_base`][MediaPlaylist::concat_with_base] when the
+    /// inputs live in different directories.
+    ///
+    /// Fails when a segment without EXT-X-MAP would follow one with it, e.g. a TS input after an
+    /// fMP4 one: the written playlist would give the TS segment the map of the fMP4 one.
+    pub fn concat(playlists: &[MediaPlaylist]) -> Result<MediaPlaylist> {
+        let first = playlists.first().ok_or_else(|| anyhow!("Nothing to concatenate"))?;
+        let last = playlists.last().unwrap_or(first);
+
+        let mut joined = OwnedSegments::default();
+        for playlist in playlists {
+            for group in playlist.discontinuity_groups() {
+                // keep the numbering of the first input, then one step per seam and per group so
+                // a leading EXT-X-DISCONTINUITY of a later input doesn't add a second tag
+                let discontinuity_sequence = match joined.discontinuity.last() {
+                    Some(previous) => previous.discontinuity_sequence + 1,
+                    None => group.discontinuity_sequence,
+                };
+                for (index, segment) in group.discontinuity_segments().iter().enumerate() {
+                    joined.push_segment(segment.clone(), discontinuity_sequence, index == 0);
+                }
+            }
+        }
+        let OwnedSegments { segments, discontinuity } = joined;
+        // an EXT-X-MAP applies until the next one, no tag takes it back
+        if let Some(pair) = segments.windows(2).find(|pair| pair[0].map.is_some() && pair[1].map.is_none()) {
+            bail!(
+                "Segment {} has no EXT-X-MAP but follows {} which has one, a playlist can't express that",
+                pair[1].url,
+                pair[0].url
+            );
+        }
+
+        // EXTINF rounded to the nearest integer must not exceed the target duration
+        let longest_segment = segments
+            .iter()
+            .map(|segment| Duration::from_secs(segment.duration.as_secs_f64().round() as u64))
+            .max()
+            .unwrap_or_default();
+        let target_duration = playlists
+            .iter()
+            .map(|playlist| playlist.target_duration)
+            .max()
+            .unwrap_or_default()
+            .max(longest_segment);
+
+        let mut joined = MediaPlaylist {
+            ended: playlists.iter().all(|playlist| playlist.ended),
+            segments,
+            target_duration,
+            version: playlists.iter().map(|playlist| playlist.version).max().unwrap_or(first.version),
+            media_sequence: first.media_sequence,
+            discontinuity_sequence: first.discontinuity_sequence,
+            start: first.start,
+            // partial segments and reloads are about the end of the joined playlist
+            server_control: last.server_control,
+            part_target: last.part_target,
+            pending_parts: last.pending_parts,
+            playlist_type: last.playlist_type,
+            date_ranges: playlists.iter().flat_map(|playlist| playlist.date_ranges.iter().cloned()).collect(),
+            discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        joined.reindex();
+        Ok(joined)
+    }
+
+    /// Same as [`concat`][MediaPlaylist::concat] for playlists given with their location (URL
+    /// or path). Relative segment, EXT-X-MAP and EXT-X-KEY URLs are rewritten so they stay
+    /// valid from a playlist stored at `base`.
+    pub fn concat_with_base(parts: &[(&str, MediaPlaylist)], base: &str) -> Result<MediaPlaylist> {
+        let rebased: Vec<MediaPlaylist> = parts
+            .iter()
+            .map(|(location, playlist)| {
+                let mut playlist = playlist.clone();
+                let rebase = |url: &mut String| *url = uri::relative(base, &uri::resolve(location, url));
+                for segment in &mut playlist.segments {
+                    rebase(&mut segment.url);
+                    // resolved against the location of the input, not of the result
+                    segment.resolved_url = None;
+                    if let Some(key) = &mut segment.key {
+                        // key URIs may be data: or a DRM scheme (skd:, ...) which aren't locations
+                        if let Some(key_uri) = key.uri.as_mut().filter(|key_uri| is_location(key_uri)) {
+                            rebase(key_uri);
+                        }
+                        key.resolved_uri = None;
+                    }
+                    if let Some(map) = &mut segment.map {
+                        rebase(&mut map.uri);
+                        map.resolved_uri = None;
+                    }
+                }
+                playlist
+            })
+            .collect();
+        Self::concat(&rebased)
+    }
+}
+
+// Whether `reference` is a path or an HTTP(S) URL, as opposed to e.g. a `data:` URI.
+fn is_location(reference: &str) -> bool {
+    match reference.split_once(':') {
+        Some((scheme, _)) if !scheme.contains('/') => {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> MediaPlaylist {
+        MediaPlaylist::parse_ext_m3u(source).expect("playlist should parse")
+    }
+
+    fn pre_roll() -> MediaPlaylist {
+        parse("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"skd://ad\"\n#EXTINF:5.5,\nad1.ts\n#EXTINF:4.5,\nad2.ts\n#EXT-X-ENDLIST\n")
+    }
+
+    fn content() -> MediaPlaylist {
+        parse(indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:4
+            #EXT-X-TARGETDURATION:10
+            #EXTINF:10.000,
+            main1.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:10.250,
+            main2.ts
+            #EXT-X-ENDLIST
+        "})
+    }
+
+    #[test]
+    fn joins_with_discontinuities() {
+        let joined = MediaPlaylist::concat(&[pre_roll(), content(), pre_roll()]).unwrap();
+        assert_eq!(joined.segments.len(), 6);
+        assert_eq!(joined.version, 4);
+        assert_eq!(joined.target_duration, Duration::from_secs(10));
+        assert!(joined.ended);
+
+        let durations: Vec<_> = joined.discontinuity.iter().map(|group| group.discontinuity_duration).collect();
+        assert_eq!(
+            durations,
+            vec![
+                Duration::from_millis(10000),
+                Duration::from_millis(10000),
+                Duration::from_millis(10250),
+                Duration::from_millis(10000),
+            ]
+        );
+        let sequences: Vec<_> = joined.discontinuity.iter().map(|group| group.discontinuity_sequence).collect();
+        assert_eq!(sequences, vec![0, 1, 2, 3]);
+
+        // the seams survive serialization
+        let text = joined.to_string();
+        assert_eq!(text.matches("#EXT-X-DISCONTINUITY\n").count(), 3);
+        assert_eq!(parse(&text), joined);
+    }
+
+    #[test]
+    fn rebases_urls() {
+        let mut content = parse(indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:6
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MAP:URI="init.mp4"
+            #EXT-X-KEY:METHOD=AES-128,URI="../keys/1.key"
+            #EXTINF:10.000,
+            main1.mp4
+            #EXT-X-DISCONTINUITY
+            #EXTINF:10.000,
+            main2.mp4
+            #EXT-X-ENDLIST
+        "#});
+        content.resolve_uris("https://cdn.example/show/hd/index.m3u8");
+        let joined = MediaPlaylist::concat_with_base(
+            &[("https://cdn.example/ads/pre.m3u8", pre_roll()), ("https://cdn.example/show/hd/index.m3u8", content)],
+            "https://cdn.example/show/joined.m3u8",
+        )
+        .unwrap();
+        let urls: Vec<_> = joined.segments.iter().map(|segment| segment.url.as_str()).collect();
+        assert_eq!(urls, vec!["../ads/ad1.ts", "../ads/ad2.ts", "hd/main1.mp4", "hd/main2.mp4"]);
+        let keys: Vec<_> = joined.segments.iter().map(|segment| segment.key.as_ref().unwrap().uri.as_deref()).collect();
+        assert_eq!(keys, vec![Some("skd://ad"), Some("skd://ad"), Some("keys/1.key"), Some("keys/1.key")]);
+        assert_eq!(joined.segments[3].map.as_ref().unwrap().uri, "hd/init.mp4");
+        assert!(joined.segments.iter().all(|segment| segment.resolved_uri().is_none()));
+        assert_eq!(joined.segments[3].map.as_ref().unwrap().resolved_uri, None);
+        assert_eq!(joined.segments[3].key.as_ref().unwrap().resolved_uri, None);
+        assert_eq!(joined.discontinuity_group(2).unwrap().discontinuity_segments()[0].url, "hd/main2.mp4");
+    }
+
+    #[test]
+    fn refuses_segments_without_map_after_a_map() {
+        let fmp4 = parse("#EXTM3U\n#EXT-X-VERSION:6\n#EXT-X-TARGETDURATION:4\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:4,\nseg0.m4s\n");
+        let ts = parse("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nseg1.ts\n");
+        let error = MediaPlaylist::concat(&[fmp4.clone(), ts.clone()]).unwrap_err();
+        assert!(error.to_string().starts_with("Segment seg1.ts has no EXT-X-MAP but follows seg0.m4s"), "{}", error);
+
+        let joined = MediaPlaylist::concat(&[ts, fmp4]).unwrap();
+        assert_eq!(parse(&joined.to_string()), joined);
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        assert!(MediaPlaylist::concat(&[]).is_err());
+    }
+}
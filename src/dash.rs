@@ -0,0 +1,575 @@
+//! Conversion of DASH presentations to HLS.
+//!
+//! [`parse_mpd`] reads a static MPEG-DASH manifest (ISO/IEC 23009-1) and gives a media playlist
+//! for each Representation. Segments are listed by a SegmentTemplate, with a SegmentTimeline or
+//! a fixed duration, or by a SegmentList, set at any level from the Period down to the
+//! Representation. Each Period becomes a discontinuity group, so a Representation must be in
+//! every Period, with the same `id`, and the groups of its playlist line up with the Periods.
+//!
+//! Segment URLs are resolved against the BaseURL elements, and stay relative when these are.
+//! `mediaRange` becomes the byte range of a segment, and Initialization its EXT-X-MAP.
+
+use core::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use roxmltree::{Document, Node};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{ByteRange, Map, MediaPlaylist, MediaSegment, OwnedSegments, PlaylistType};
+use crate::uri;
+
+/// The most segments read for a Representation in a Period, so a repeat count or a duration
+/// way off can't exhaust the memory.
+pub const MAX_SEGMENTS: u64 = 1_000_000;
+
+/// A Representation of an MPD with its media playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashRepresentation {
+    pub id: String,
+    /// `video`, `audio` or `text`, from the AdaptationSet or the MIME type.
+    pub content_type: Option<String>,
+    pub mime_type: Option<String>,
+    /// Bits per second.
+    pub bandwidth: u64,
+    pub codecs: Option<String>,
+    /// Width and height in pixels.
+    pub resolution: Option<(u64, u64)>,
+    pub language: Option<String>,
+    pub playlist: MediaPlaylist,
+}
+
+/// Parses a static MPD into a [`DashRepresentation`] for each Representation, in the order of
+/// the first Period. See the [module documentation][crate::dash].
+pub fn parse_mpd(mpd: &str) -> Result<Vec<DashRepresentation>> {
+    let document = Document::parse(mpd).context("invalid MPD")?;
+    let root = document.root_element();
+    if !root.has_tag_name("MPD") {
+        bail!("expecting an MPD element, found {}", root.tag_name().name());
+    }
+    if root.attribute("type") == Some("dynamic") {
+        bail!("only static MPDs can be converted");
+    }
+    let presentation_duration = root.attribute("mediaPresentationDuration").map(parse_duration).transpose()?;
+    let base = base_url(None, root);
+
+    let periods: Vec<Node> = children(root, "Period").collect();
+    if periods.is_empty() {
+        bail!("the MPD has no Period");
+    }
+    let mut representations: Vec<(DashRepresentation, OwnedSegments)> = Vec::new();
+    let mut start = Duration::ZERO;
+    for (index, period) in periods.iter().enumerate() {
+        start = period.attribute("start").map(parse_duration).transpose()?.unwrap_or(start);
+        let end = match (period.attribute("duration"), periods.get(index + 1).and_then(|next| next.attribute("start"))) {
+            (Some(duration), _) => Some(
+                start
+                    .checked_add(parse_duration(duration)?)
+                    .ok_or_else(|| anyhow!("Period {} ends past the largest duration", index))?,
+            ),
+            (None, Some(next_start)) => Some(parse_duration(next_start)?),
+            (None, None) if index + 1 == periods.len() => presentation_duration,
+            (None, None) => None,
+        };
+        let period_duration = end.map(|end| end.saturating_sub(start));
+        let period_base = base_url(base.clone(), *period);
+
+        let mut in_period = vec![false; representations.len()];
+        for adaptation_set in children(*period, "AdaptationSet") {
+            let set_base = base_url(period_base.clone(), adaptation_set);
+            for node in children(adaptation_set, "Representation") {
+                let representation = representation(adaptation_set, node)?;
+                let levels = [node, adaptation_set, *period];
+                let segments = segments(&levels, &representation, base_url(set_base.clone(), node), period_duration)?;
+
+                if segments.is_empty() {
+                    bail!("Representation {} has no segment in Period {}", representation.id, index);
+                }
+                let position = match representations.iter().position(|(known, _)| known.id == representation.id) {
+                    Some(position) if in_period[position] => {
+                        bail!("Representation {} is twice in Period {}", representation.id, index)
+                    }
+                    Some(position) => position,
+                    None if index == 0 => {
+                        representations.push((representation, OwnedSegments::default()));
+                        in_period.push(false);
+                        representations.len() - 1
+                    }
+                    None => bail!("Representation {} is not in the first Period", representation.id),
+                };
+                in_period[position] = true;
+                for (number, segment) in segments.into_iter().enumerate() {
+                    representations[position].1.push_segment(segment, index as u64, number == 0);
+                }
+            }
+        }
+        if let Some(missing) = in_period.iter().position(|seen| !seen) {
+            bail!("Representation {} is not in Period {}", representations[missing].0.id, index);
+        }
+        start = end.unwrap_or(start);
+    }
+
+    Ok(representations
+        .into_iter()
+        .map(|(representation, segments)| DashRepresentation { playlist: playlist(segments), ..representation })
+        .collect())
+}
+
+/// Same as [`parse_mpd`] for an MPD loaded from `base`: every segment and initialization URI is
+/// also resolved against it.
+pub fn parse_mpd_with_base(mpd: &str, base: &str) -> Result<Vec<DashRepresentation>> {
+    let mut representations = parse_mpd(mpd)?;
+    for representation in &mut representations {
+        representation.playlist.resolve_uris(base);
+    }
+    Ok(representations)
+}
+
+// The attributes of a Representation, some of which can be set on its AdaptationSet instead.
+fn representation(adaptation_set: Node, node: Node) -> Result<DashRepresentation> {
+    let attribute = |name: &'static str| node.attribute(name).or_else(|| adaptation_set.attribute(name));
+    let id = node.attribute("id").ok_or_else(|| anyhow!("Representation without id"))?.to_string();
+    let number = |name: &'static str| {
+        attribute(name)
+            .map(|value| value.parse::<u64>())
+            .transpose()
+            .map_err(|_| anyhow!("Representation {}: {} expecting an integer", id, name))
+    };
+    let bandwidth = number("bandwidth")?.unwrap_or_default();
+    let resolution = number("width")?.zip(number("height")?);
+    let mime_type = attribute("mimeType").map(str::to_string);
+    let content_type = adaptation_set
+        .attribute("contentType")
+        .map(str::to_string)
+        .or_else(|| mime_type.as_deref().and_then(|mime_type| mime_type.split_once('/')).map(|(kind, _)| kind.to_string()));
+    Ok(DashRepresentation {
+        id,
+        content_type,
+        mime_type,
+        bandwidth,
+        codecs: attribute("codecs").map(str::to_string),
+        resolution,
+        language: adaptation_set.attribute("lang").map(str::to_string),
+        // filled in once every Period is read
+        playlist: playlist(OwnedSegments::default()),
+    })
+}
+
+// The segments of a Representation in a Period. `levels` are the Representation, AdaptationSet
+// and Period elements, where the segment information is looked up in this order.
+fn segments(
+    levels: &[Node],
+    representation: &DashRepresentation,
+    base: Option<String>,
+    period_duration: Option<Duration>,
+) -> Result<Vec<MediaSegment>> {
+    let id = &representation.id;
+    let templates: Vec<Node> = levels.iter().filter_map(|level| child(*level, "SegmentTemplate")).collect();
+    let lists: Vec<Node> = levels.iter().filter_map(|level| child(*level, "SegmentList")).collect();
+    let information = if templates.is_empty() { &lists } else { &templates };
+    // the SegmentURL elements of the innermost SegmentList which has some
+    let urls: Vec<Node> = lists
+        .iter()
+        .map(|list| children(*list, "SegmentURL").collect::<Vec<_>>())
+        .find(|urls| !urls.is_empty())
+        .unwrap_or_default();
+    if information.is_empty() {
+        bail!("Representation {}: only SegmentTemplate and SegmentList are supported", id);
+    }
+
+    let attribute = |name: &'static str| information.iter().find_map(|element| element.attribute(name));
+    let number = |name: &'static str, default: u64| {
+        attribute(name)
+            .map_or(Ok(default), str::parse)
+            .map_err(|_| anyhow!("Representation {}: {} expecting an integer", id, name))
+    };
+    let timescale = number("timescale", 1)?.max(1);
+    let start_number = number("startNumber", 1)?;
+    let time_offset = number("presentationTimeOffset", 0)?;
+    let period_end = period_duration.map(|duration| {
+        let ticks = duration.as_nanos().saturating_mul(timescale as u128) / 1_000_000_000;
+        time_offset.saturating_add(u64::try_from(ticks).unwrap_or(u64::MAX))
+    });
+    let ticks = |ticks: u64| {
+        u64::try_from(ticks as u128 * 1_000_000_000 / timescale as u128)
+            .map(Duration::from_nanos)
+            .map_err(|_| anyhow!("Representation {}: time {} is past the largest duration", id, ticks))
+    };
+
+    // start time and duration of every segment, in timescale units
+    let times = match information.iter().find_map(|element| child(*element, "SegmentTimeline")) {
+        Some(timeline) => {
+            timeline_times(timeline, time_offset, period_end).with_context(|| format!("Representation {}", id))?
+        }
+        None => {
+            let duration = number("duration", 0)?;
+            let count = match (templates.is_empty(), period_end) {
+                (true, _) => urls.len() as u64,
+                (false, Some(end)) if duration > 0 => (end - time_offset).div_ceil(duration),
+                _ => bail!("Representation {}: no SegmentTimeline, and no duration of segments or of the Period", id),
+            };
+            if count > MAX_SEGMENTS {
+                bail!("Representation {}: more than {} segments in a Period", id, MAX_SEGMENTS);
+            }
+            if duration == 0 {
+                bail!("Representation {}: no SegmentTimeline nor duration of segments", id);
+            }
+            (0..count)
+                .map(|index| {
+                    let time = time_offset + index * duration;
+                    (time, period_end.map_or(duration, |end| duration.min(end.saturating_sub(time))))
+                })
+                .collect()
+        }
+    };
+
+    let locate = |reference: &str| match &base {
+        Some(base) => uri::resolve(base, reference),
+        None => reference.to_string(),
+    };
+    let initialization = information.iter().find_map(|element| child(*element, "Initialization"));
+    let map = match (attribute("initialization").filter(|_| !templates.is_empty()), initialization) {
+        (Some(template), _) => Some(Map {
+            uri: locate(&substitute(template, representation, None, None)?),
+            byte_range: None,
+            resolved_uri: None,
+        }),
+        (None, Some(initialization)) => Some(Map {
+            uri: match initialization.attribute("sourceURL") {
+                Some(source) => locate(source),
+                None => base.clone().ok_or_else(|| anyhow!("Representation {}: Initialization without URL", id))?,
+            },
+            byte_range: initialization.attribute("range").map(parse_range).transpose()?,
+            resolved_uri: None,
+        }),
+        (None, None) => None,
+    };
+    let segment = |(time, duration): (u64, u64), url: String, byte_range: Option<ByteRange>| {
+        let end = time.checked_add(duration).ok_or_else(|| anyhow!("Representation {}: time overflow", id))?;
+        Ok(MediaSegment {
+            duration: ticks(end)?
+                .checked_sub(ticks(time)?)
+                .ok_or_else(|| anyhow!("Representation {}: segment ending before it starts", id))?,
+            url,
+            map: map.clone(),
+            byte_range,
+            ..Default::default()
+        })
+    };
+
+    if templates.is_empty() {
+        if times.len() < urls.len() {
+            bail!("Representation {}: {} SegmentURL for {} durations", id, urls.len(), times.len());
+        }
+        urls.into_iter()
+            .zip(times)
+            .map(|(url, time)| {
+                let media = match url.attribute("media") {
+                    Some(media) => locate(media),
+                    None => base.clone().ok_or_else(|| anyhow!("Representation {}: SegmentURL without URL", id))?,
+                };
+                segment(time, media, url.attribute("mediaRange").map(parse_range).transpose()?)
+            })
+            .collect()
+    } else {
+        let media = attribute("media").ok_or_else(|| anyhow!("Representation {}: SegmentTemplate without media", id))?;
+        (start_number..)
+            .zip(times)
+            .map(|(number, time)| {
+                let url = substitute(media, representation, Some(number), Some(time.0))?;
+                segment(time, locate(&url), None)
+            })
+            .collect()
+    }
+}
+
+// The start time and duration of the segments of a SegmentTimeline, where a negative repeat
+// count goes on until the next S element or the end of the Period.
+fn timeline_times(timeline: Node, time_offset: u64, period_end: Option<u64>) -> Result<Vec<(u64, u64)>> {
+    let entries: Vec<Node> = children(timeline, "S").collect();
+    let mut times = Vec::new();
+    let mut time = time_offset;
+    for (index, entry) in entries.iter().enumerate() {
+        let value = |name: &str| {
+            entry.attribute(name).map(str::parse::<i64>).transpose().map_err(|_| anyhow!("S: {} expecting an integer", name))
+        };
+        time = value("t")?.map_or(time, |t| t.max(0) as u64);
+        let duration = value("d")?.filter(|&d| d > 0).ok_or_else(|| anyhow!("S without duration"))? as u64;
+        let repeat = value("r")?.unwrap_or(0);
+        let count = if repeat >= 0 {
+            repeat as u64 + 1
+        } else {
+            let next = entries.get(index + 1).and_then(|next| next.attribute("t")).map(str::parse::<u64>).transpose()?;
+            let end = next.or(period_end).ok_or_else(|| anyhow!("S repeated until the end of a Period without duration"))?;
+            end.saturating_sub(time).div_ceil(duration)
+        };
+        if count > MAX_SEGMENTS - times.len() as u64 {
+            bail!("more than {} segments in a Period", MAX_SEGMENTS);
+        }
+        for _ in 0..count {
+            let end = time.checked_add(duration).ok_or_else(|| anyhow!("S: time overflow"))?;
+            times.push((time, duration));
+            time = end;
+        }
+    }
+    Ok(times)
+}
+
+// Fills in the identifiers of a SegmentTemplate, e.g. `$RepresentationID$/$Number%05d$.m4s`.
+fn substitute(
+    template: &str,
+    representation: &DashRepresentation,
+    number: Option<u64>,
+    time: Option<u64>,
+) -> Result<String> {
+    let mut url = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        url.push_str(&rest[..start]);
+        let (identifier, after) = rest[start + 1..]
+            .split_once('$')
+            .ok_or_else(|| anyhow!("unterminated identifier in template {}", template))?;
+        let (name, format) = identifier.split_once('%').map_or((identifier, None), |(name, format)| (name, Some(format)));
+        let value = match name {
+            "" => "$".to_string(),
+            "RepresentationID" => representation.id.clone(),
+            "Bandwidth" | "Number" | "Time" => {
+                let value = match name {
+                    "Bandwidth" => Some(representation.bandwidth),
+                    "Number" => number,
+                    _ => time,
+                }
+                .ok_or_else(|| anyhow!("${}$ can't be used in {}", name, template))?;
+                let width = match format {
+                    Some(format) => format
+                        .strip_suffix('d')
+                        .and_then(|width| width.parse::<usize>().ok())
+                        .ok_or_else(|| anyhow!("invalid format {} in template {}", format, template))?,
+                    None => 0,
+                };
+                format!("{:0width$}", value, width = width)
+            }
+            _ => bail!("unknown identifier ${}$ in template {}", identifier, template),
+        };
+        url.push_str(&value);
+        rest = after;
+    }
+    url.push_str(rest);
+    Ok(url)
+}
+
+// The media playlist of the segments of a Representation.
+fn playlist(segments: OwnedSegments) -> MediaPlaylist {
+    let longest = segments.segments.iter().map(|segment| segment.duration).max().unwrap_or_default();
+    let version = if segments.segments.iter().any(|segment| segment.map.is_some()) {
+        6
+    } else if segments.segments.iter().any(|segment| segment.byte_range.is_some()) {
+        4
+    } else {
+        3
+    };
+    let mut playlist = MediaPlaylist {
+        ended: true,
+        segments: segments.segments,
+        // EXTINF durations rounded to the nearest integer must not be over the target duration
+        target_duration: Duration::from_secs((longest.as_secs_f64().round() as u64).max(1)),
+        version,
+        media_sequence: 0,
+        discontinuity_sequence: 0,
+        start: None,
+        server_control: None,
+        part_target: None,
+        pending_parts: 0,
+        playlist_type: Some(PlaylistType::Vod),
+        date_ranges: Vec::new(),
+        discontinuity: segments.discontinuity,
+        time_index: TimeIndex::default(),
+    };
+    playlist.reindex();
+    playlist
+}
+
+// The base URL of the children of `node`, from its BaseURL element if it has one.
+fn base_url(base: Option<String>, node: Node) -> Option<String> {
+    match child(node, "BaseURL").and_then(|element| element.text()).map(str::trim) {
+        Some(reference) => Some(match base {
+            Some(base) => uri::resolve(&base, reference),
+            None => reference.to_string(),
+        }),
+        None => base,
+    }
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+// Parses `first-last` as in mediaRange.
+fn parse_range(value: &str) -> Result<ByteRange> {
+    let (first, last) = value.split_once('-').ok_or_else(|| anyhow!("invalid byte range {}", value))?;
+    let (first, last): (u64, u64) = (first.trim().parse()?, last.trim().parse()?);
+    if last < first {
+        bail!("invalid byte range {}", value);
+    }
+    Ok(ByteRange { length: last - first + 1, offset: Some(first) })
+}
+
+// Parses an xs:duration, e.g. `PT1H2M3.5S`. Years and months have no fixed length and are
+// refused, and so are negative durations.
+fn parse_duration(value: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration {}", value);
+    let rest = value.trim().strip_prefix('P').ok_or_else(invalid)?;
+    let (days, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let mut seconds = 0.0;
+    for (part, units) in [(days, &[('D', 86400.0)][..]), (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)][..])] {
+        let mut rest = part;
+        for (designator, length) in units {
+            if let Some((number, after)) = rest.split_once(*designator) {
+                let number = number.parse::<f64>().ok().filter(|number| number.is_finite() && *number >= 0.0);
+                seconds += number.ok_or_else(invalid)? * length;
+                rest = after;
+            }
+        }
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MPD: &str = indoc::indoc! {r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT15S">
+          <BaseURL>https://cdn.example/show/</BaseURL>
+          <Period id="main" start="PT0S">
+            <AdaptationSet contentType="video" mimeType="video/mp4" codecs="avc1.64001f">
+              <SegmentTemplate timescale="90000" media="$RepresentationID$/$Number%03d$.m4s" initialization="$RepresentationID$/init.mp4" startNumber="7">
+                <SegmentTimeline>
+                  <S t="0" d="360000" r="1"/>
+                  <S d="180000"/>
+                </SegmentTimeline>
+              </SegmentTemplate>
+              <Representation id="720p" bandwidth="2000000" width="1280" height="720"/>
+            </AdaptationSet>
+            <AdaptationSet contentType="audio" lang="en">
+              <Representation id="en" mimeType="audio/mp4" bandwidth="128000">
+                <BaseURL>audio/</BaseURL>
+                <SegmentList timescale="1000" duration="5000">
+                  <Initialization sourceURL="en.mp4" range="0-799"/>
+                  <SegmentURL media="en.mp4" mediaRange="800-1799"/>
+                  <SegmentURL media="en.mp4" mediaRange="1800-2499"/>
+                </SegmentList>
+              </Representation>
+            </AdaptationSet>
+          </Period>
+          <Period id="ad" duration="PT5S">
+            <BaseURL>ad/</BaseURL>
+            <SegmentTemplate media="$RepresentationID$-$Time$.ts" duration="2"/>
+            <AdaptationSet mimeType="video/mp2t">
+              <Representation id="720p" bandwidth="2000000"/>
+            </AdaptationSet>
+            <AdaptationSet mimeType="audio/mp2t">
+              <Representation id="en" bandwidth="128000"/>
+            </AdaptationSet>
+          </Period>
+        </MPD>
+    "#};
+
+    #[test]
+    fn makes_a_playlist_per_representation_with_a_group_per_period() {
+        let representations = parse_mpd(MPD).unwrap();
+        let ids: Vec<_> = representations.iter().map(|representation| representation.id.as_str()).collect();
+        assert_eq!(ids, ["720p", "en"]);
+        let video = &representations[0];
+        assert_eq!(video.content_type.as_deref(), Some("video"));
+        assert_eq!((video.bandwidth, video.codecs.as_deref(), video.resolution), (2000000, Some("avc1.64001f"), Some((1280, 720))));
+        assert_eq!(representations[1].language.as_deref(), Some("en"));
+
+        assert_eq!(
+            video.playlist.to_string(),
+            indoc::indoc! {r#"
+                #EXTM3U
+                #EXT-X-VERSION:6
+                #EXT-X-TARGETDURATION:4
+                #EXT-X-MEDIA-SEQUENCE:0
+                #EXT-X-PLAYLIST-TYPE:VOD
+                #EXT-X-MAP:URI="https://cdn.example/show/720p/init.mp4"
+                #EXTINF:4.000,
+                https://cdn.example/show/720p/007.m4s
+                #EXTINF:4.000,
+                https://cdn.example/show/720p/008.m4s
+                #EXTINF:2.000,
+                https://cdn.example/show/720p/009.m4s
+                #EXT-X-DISCONTINUITY
+                #EXTINF:2.000,
+                https://cdn.example/show/ad/720p-0.ts
+                #EXTINF:2.000,
+                https://cdn.example/show/ad/720p-2.ts
+                #EXTINF:1.000,
+                https://cdn.example/show/ad/720p-4.ts
+                #EXT-X-ENDLIST
+            "#}
+        );
+
+        let audio = &representations[1].playlist;
+        let groups: Vec<_> = audio.discontinuity().iter().map(|group| (group.segment_range(), group.discontinuity_sequence())).collect();
+        assert_eq!(groups, [(0..2, 0), (2..5, 1)]);
+        let first = &audio.segments()[0];
+        assert_eq!(first.url(), "https://cdn.example/show/audio/en.mp4");
+        assert_eq!(first.byte_range(), Some(ByteRange { length: 1000, offset: Some(800) }));
+        assert_eq!(first.map().unwrap().byte_range, Some(ByteRange { length: 800, offset: Some(0) }));
+        assert_eq!(audio.discontinuity()[0].discontinuity_duration(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn reads_back_the_mpd_of_a_master_playlist() {
+        let representations = parse_mpd(include_str!("../testdata/mpd/segment_list.mpd")).unwrap();
+        let video = &representations[0].playlist;
+        let urls: Vec<_> = video.segments().iter().map(|segment| segment.url()).collect();
+        assert_eq!(urls, ["video/seg_001.m4s", "video/seg_002.m4s", "video/seg_003.m4s", "video/seg_004.m4s"]);
+        assert_eq!(video.discontinuity().len(), 2);
+        assert_eq!(video.segments()[3].map().unwrap().uri, "video/ad-init.mp4");
+        assert_eq!(video.duration(), Duration::from_millis(14500));
+
+        let audio = &representations[1].playlist;
+        assert_eq!(audio.segments()[2].byte_range(), Some(ByteRange { length: 600, offset: Some(2800) }));
+    }
+
+    #[test]
+    fn refuses_what_has_no_media_playlist() {
+        assert!(parse_mpd(&MPD.replace(r#"type="static""#, r#"type="dynamic""#)).is_err());
+        assert!(parse_mpd(&MPD.replace(r#"<Representation id="en" bandwidth="128000"/>"#, "")).is_err());
+        assert!(parse_mpd(&MPD.replace("$Time$", "$Unknown$")).is_err());
+        assert_eq!(parse_duration("P1DT1H2M3.5S").unwrap(), Duration::from_secs_f64(90123.5));
+        for invalid in ["PT-5S", "PT1e400S", "PTNaNS", "P1e308DT1S"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn bounds_the_number_of_segments() {
+        let error = parse_mpd(&MPD.replace(r#"r="1""#, r#"r="1000000000000""#)).unwrap_err();
+        assert!(format!("{:#}", error).contains("more than 1000000 segments"), "{:#}", error);
+        let error = parse_mpd(&MPD.replace(r#"duration="PT5S""#, r#"duration="PT100000000S""#)).unwrap_err();
+        assert!(format!("{:#}", error).contains("more than 1000000 segments"), "{:#}", error);
+
+        let far = MPD.replace(
+            r#"<Period id="ad" duration="PT5S">"#,
+            r#"<Period id="ad" start="PT10000000000000000000S" duration="PT10000000000000000000S">"#,
+        );
+        let error = parse_mpd(&far).unwrap_err();
+        assert!(format!("{:#}", error).contains("ends past the largest duration"), "{:#}", error);
+        let late = MPD
+            .replace(r#"timescale="90000""#, r#"timescale="1""#)
+            .replace(r#"<S t="0" d="360000" r="1"/>"#, r#"<S t="18446744073" d="1"/>"#);
+        let error = parse_mpd(&late).unwrap_err();
+        assert!(format!("{:#}", error).contains("is past the largest duration"), "{:#}", error);
+    }
+}
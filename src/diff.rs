@@ -0,0 +1,391 @@
+//! Structural comparison of two media playlists.
+//!
+//! [`MediaPlaylist::diff`] lines the segments of both playlists up by media sequence number
+//! and lists what changed as typed [`Change`]s. The resulting [`PlaylistDiff`] renders as a
+//! unified diff through its `Display` implementation.
+
+use core::time::Duration;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use chrono::SecondsFormat;
+
+use crate::media_playlist::{DateRange, MediaPlaylist, MediaSegment};
+
+/// A single difference between two playlists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A playlist tag has a different value, e.g. `EXT-X-TARGETDURATION`. Values are as
+    /// written after the colon, empty for a tag without one (`EXT-X-ENDLIST`), and `None`
+    /// stands for a missing tag.
+    Header { tag: &'static str, old: Option<String>, new: Option<String> },
+    /// A segment only exists in the new playlist.
+    SegmentAdded { media_sequence: u64, segment: MediaSegment },
+    /// A segment only exists in the old playlist.
+    SegmentRemoved { media_sequence: u64, segment: MediaSegment },
+    /// The segment with this media sequence number has a different EXTINF duration.
+    DurationChanged { media_sequence: u64, old: Duration, new: Duration },
+    /// The segment with this media sequence number points to a different URL.
+    UrlChanged { media_sequence: u64, old: String, new: String },
+    /// A tag of the segment with this media sequence number has a different value, e.g.
+    /// `EXT-X-BYTERANGE`, with values as in [`Change::Header`].
+    SegmentTag { media_sequence: u64, tag: &'static str, old: Option<String>, new: Option<String> },
+    /// An EXT-X-DATERANGE only exists in the new playlist.
+    DateRangeAdded(DateRange),
+    /// An EXT-X-DATERANGE only exists in the old playlist.
+    DateRangeRemoved(DateRange),
+    /// The EXT-X-DATERANGE with the same ID has different attributes.
+    DateRangeChanged { old: DateRange, new: DateRange },
+    /// The new playlist has an EXT-X-DISCONTINUITY before this segment, the old one does not.
+    DiscontinuityAdded { media_sequence: u64 },
+    /// The old playlist has an EXT-X-DISCONTINUITY before this segment, the new one does not.
+    DiscontinuityRemoved { media_sequence: u64 },
+}
+
+/// Every [`Change`] between two playlists, see [`MediaPlaylist::diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PlaylistDiff {
+    pub changes: Vec<Change>,
+}
+
+impl PlaylistDiff {
+    /// Whether both playlists are structurally the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl MediaPlaylist {
+    /// Compares `self` (the old playlist) with `other` (the new one). Header changes come
+    /// first, then segment and discontinuity changes ordered by media sequence number.
+    pub fn diff(&self, other: &MediaPlaylist) -> PlaylistDiff {
+        let mut changes = Vec::new();
+
+        // the tags as the writer puts them at the top of the playlist
+        let headers = |playlist: &MediaPlaylist| {
+            [
+                ("EXT-X-VERSION", Some(playlist.version.to_string())),
+                ("EXT-X-TARGETDURATION", Some(playlist.target_duration.as_secs().to_string())),
+                ("EXT-X-MEDIA-SEQUENCE", Some(playlist.media_sequence.to_string())),
+                ("EXT-X-PLAYLIST-TYPE", value(playlist.playlist_type)),
+                (
+                    "EXT-X-DISCONTINUITY-SEQUENCE",
+                    value(Some(playlist.discontinuity_sequence).filter(|sequence| *sequence != 0)),
+                ),
+                ("EXT-X-START", value(playlist.start)),
+                ("EXT-X-SERVER-CONTROL", value(playlist.server_control)),
+                (
+                    "EXT-X-PART-INF",
+                    value(playlist.part_target.map(|target| format!("PART-TARGET={}", target.as_secs_f64()))),
+                ),
+                ("EXT-X-ENDLIST", playlist.ended.then(String::new)),
+            ]
+        };
+        for ((tag, old), (_, new)) in headers(self).into_iter().zip(headers(other)) {
+            if old != new {
+                changes.push(Change::Header { tag, old, new });
+            }
+        }
+
+        for old in &self.date_ranges {
+            match other.date_ranges.iter().find(|new| new.id == old.id) {
+                Some(new) if new != old => changes.push(Change::DateRangeChanged { old: old.clone(), new: new.clone() }),
+                Some(_) => {}
+                None => changes.push(Change::DateRangeRemoved(old.clone())),
+            }
+        }
+        for new in &other.date_ranges {
+            if !self.date_ranges.iter().any(|old| old.id == new.id) {
+                changes.push(Change::DateRangeAdded(new.clone()));
+            }
+        }
+
+        let old_boundaries = discontinuity_boundaries(self);
+        let new_boundaries = discontinuity_boundaries(other);
+        let old_range = self.media_sequence..self.media_sequence + self.segments.len() as u64;
+        let new_range = other.media_sequence..other.media_sequence + other.segments.len() as u64;
+        // only the numbers of actual segments, the sequence may jump far ahead between the two
+        let ranges = if old_range.start <= new_range.end && new_range.start <= old_range.end {
+            [old_range.start.min(new_range.start)..old_range.end.max(new_range.end), 0..0]
+        } else if old_range.start < new_range.start {
+            [old_range, new_range]
+        } else {
+            [new_range, old_range]
+        };
+
+        for media_sequence in ranges.into_iter().flatten() {
+            let old = segment_at(self, media_sequence);
+            let new = segment_at(other, media_sequence);
+
+            match (
+                old_boundaries.contains(&media_sequence),
+                new_boundaries.contains(&media_sequence),
+            ) {
+                (false, true) => changes.push(Change::DiscontinuityAdded { media_sequence }),
+                (true, false) => changes.push(Change::DiscontinuityRemoved { media_sequence }),
+                _ => {}
+            }
+
+            match (old, new) {
+                (Some(old), Some(new)) => {
+                    if old.duration != new.duration {
+                        changes.push(Change::DurationChanged {
+                            media_sequence,
+                            old: old.duration,
+                            new: new.duration,
+                        });
+                    }
+                    if old.url != new.url {
+                        changes.push(Change::UrlChanged {
+                            media_sequence,
+                            old: old.url.clone(),
+                            new: new.url.clone(),
+                        });
+                    }
+                    let tags = |segment: &MediaSegment| {
+                        [
+                            (
+                                "EXT-X-PROGRAM-DATE-TIME",
+                                value(segment.program_date_time.map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, false))),
+                            ),
+                            ("EXT-X-KEY", value(segment.key.as_ref())),
+                            ("EXT-X-MAP", value(segment.map.as_ref())),
+                            ("EXT-X-BYTERANGE", value(segment.byte_range)),
+                            ("EXT-X-GAP", segment.gap.then(String::new)),
+                        ]
+                    };
+                    for ((tag, old), (_, new)) in tags(old).into_iter().zip(tags(new)) {
+                        if old != new {
+                            changes.push(Change::SegmentTag { media_sequence, tag, old, new });
+                        }
+                    }
+                }
+                (Some(old), None) => changes.push(Change::SegmentRemoved { media_sequence, segment: old.clone() }),
+                (None, Some(new)) => changes.push(Change::SegmentAdded { media_sequence, segment: new.clone() }),
+                (None, None) => {}
+            }
+        }
+
+        PlaylistDiff { changes }
+    }
+}
+
+// The value of an optional tag as written after the colon.
+fn value<T: fmt::Display>(value: Option<T>) -> Option<String> {
+    value.map(|value| value.to_string())
+}
+
+// The tag line of a header or segment tag with `value`, see [`Change::Header`].
+fn tag_line(f: &mut fmt::Formatter<'_>, sign: char, tag: &str, value: &Option<String>) -> fmt::Result {
+    match value.as_deref() {
+        None => Ok(()),
+        Some("") => writeln!(f, "{}#{}", sign, tag),
+        Some(value) => writeln!(f, "{}#{}:{}", sign, tag, value),
+    }
+}
+
+fn segment_at(playlist: &MediaPlaylist, media_sequence: u64) -> Option<&MediaSegment> {
+    let index = media_sequence.checked_sub(playlist.media_sequence)?;
+    playlist.segments.get(usize::try_from(index).ok()?)
+}
+
+// Media sequence numbers of the segments directly preceded by an EXT-X-DISCONTINUITY tag.
+fn discontinuity_boundaries(playlist: &MediaPlaylist) -> BTreeSet<u64> {
+    let mut boundaries = BTreeSet::new();
+    let mut media_sequence = playlist.media_sequence;
+    for (index, group) in playlist.discontinuity.iter().enumerate() {
+        // the first group only starts with a discontinuity if the tag came before any segment
+        if index > 0 || group.discontinuity_sequence > playlist.discontinuity_sequence {
+            boundaries.insert(media_sequence);
+        }
+        media_sequence += group.segments.len() as u64;
+    }
+    boundaries
+}
+
+fn extinf(segment: &MediaSegment) -> String {
+    format!("#EXTINF:{:.3}, {}", segment.duration.as_secs_f64(), segment.url)
+}
+
+impl fmt::Display for PlaylistDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- old")?;
+        writeln!(f, "+++ new")?;
+        let mut hunk = None;
+        for change in &self.changes {
+            let media_sequence = match change {
+                Change::Header { .. }
+                | Change::DateRangeAdded(_)
+                | Change::DateRangeRemoved(_)
+                | Change::DateRangeChanged { .. } => None,
+                Change::SegmentAdded { media_sequence, .. }
+                | Change::SegmentRemoved { media_sequence, .. }
+                | Change::DurationChanged { media_sequence, .. }
+                | Change::UrlChanged { media_sequence, .. }
+                | Change::SegmentTag { media_sequence, .. }
+                | Change::DiscontinuityAdded { media_sequence }
+                | Change::DiscontinuityRemoved { media_sequence } => Some(*media_sequence),
+            };
+            // one hunk header per segment, like the line ranges of a unified diff
+            if media_sequence.is_some() && media_sequence != hunk {
+                writeln!(f, "@@ media sequence {} @@", media_sequence.unwrap_or_default())?;
+                hunk = media_sequence;
+            }
+
+            match change {
+                Change::Header { tag, old, new } | Change::SegmentTag { tag, old, new, .. } => {
+                    tag_line(f, '-', tag, old)?;
+                    tag_line(f, '+', tag, new)?;
+                }
+                Change::SegmentAdded { segment, .. } => writeln!(f, "+{}", extinf(segment))?,
+                Change::SegmentRemoved { segment, .. } => writeln!(f, "-{}", extinf(segment))?,
+                Change::DurationChanged { old, new, .. } => {
+                    writeln!(f, "-#EXTINF:{:.3},", old.as_secs_f64())?;
+                    writeln!(f, "+#EXTINF:{:.3},", new.as_secs_f64())?;
+                }
+                Change::DateRangeAdded(date_range) => writeln!(f, "+#EXT-X-DATERANGE:{}", date_range)?,
+                Change::DateRangeRemoved(date_range) => writeln!(f, "-#EXT-X-DATERANGE:{}", date_range)?,
+                Change::DateRangeChanged { old, new } => {
+                    writeln!(f, "-#EXT-X-DATERANGE:{}", old)?;
+                    writeln!(f, "+#EXT-X-DATERANGE:{}", new)?;
+                }
+                Change::UrlChanged { old, new, .. } => {
+                    writeln!(f, "-{}", old)?;
+                    writeln!(f, "+{}", new)?;
+                }
+                Change::DiscontinuityAdded { .. } => writeln!(f, "+#EXT-X-DISCONTINUITY")?,
+                Change::DiscontinuityRemoved { .. } => writeln!(f, "-#EXT-X-DISCONTINUITY")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: &str) -> MediaPlaylist {
+        MediaPlaylist::parse_ext_m3u(&format!("#EXTM3U\n#EXT-X-VERSION:3\n{}", body)).expect("playlist should parse")
+    }
+
+    #[test]
+    fn identical_playlists_have_no_changes() {
+        let playlist = parse("#EXT-X-TARGETDURATION:10\n#EXTINF:9,\na.ts\n#EXT-X-ENDLIST\n");
+        assert!(playlist.diff(&playlist.clone()).is_empty());
+    }
+
+    #[test]
+    fn lists_typed_changes() {
+        let old = parse(indoc::indoc! {"
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:1
+            #EXTINF:9,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:9,
+            b.ts
+            #EXTINF:9,
+            c.ts
+        "});
+        let new = parse(indoc::indoc! {"
+            #EXT-X-TARGETDURATION:12
+            #EXT-X-MEDIA-SEQUENCE:2
+            #EXTINF:9,
+            b.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:11,
+            c2.ts
+            #EXTINF:9,
+            d.ts
+            #EXT-X-ENDLIST
+        "});
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::Header { tag: "EXT-X-TARGETDURATION", old: Some("10".to_string()), new: Some("12".to_string()) },
+                Change::Header { tag: "EXT-X-MEDIA-SEQUENCE", old: Some("1".to_string()), new: Some("2".to_string()) },
+                Change::Header { tag: "EXT-X-ENDLIST", old: None, new: Some(String::new()) },
+                Change::SegmentRemoved {
+                    media_sequence: 1,
+                    segment: MediaSegment { duration: Duration::from_secs_f32(9.0), url: "a.ts".to_string(), ..Default::default() },
+                },
+                Change::DiscontinuityRemoved { media_sequence: 2 },
+                Change::DiscontinuityAdded { media_sequence: 3 },
+                Change::DurationChanged {
+                    media_sequence: 3,
+                    old: Duration::from_secs_f32(9.0),
+                    new: Duration::from_secs_f32(11.0),
+                },
+                Change::UrlChanged { media_sequence: 3, old: "c.ts".to_string(), new: "c2.ts".to_string() },
+                Change::SegmentAdded {
+                    media_sequence: 4,
+                    segment: MediaSegment { duration: Duration::from_secs_f32(9.0), url: "d.ts".to_string(), ..Default::default() },
+                },
+            ]
+        );
+
+        let rendered = diff.to_string();
+        assert!(rendered.starts_with("--- old\n+++ new\n-#EXT-X-TARGETDURATION:10\n+#EXT-X-TARGETDURATION:12\n"));
+        assert!(rendered.contains("+#EXT-X-MEDIA-SEQUENCE:2\n+#EXT-X-ENDLIST\n@@"));
+        assert!(rendered.contains("@@ media sequence 3 @@\n+#EXT-X-DISCONTINUITY\n-#EXTINF:9.000,\n+#EXTINF:11.000,\n-c.ts\n+c2.ts\n"));
+        assert!(rendered.ends_with("@@ media sequence 4 @@\n+#EXTINF:9.000, d.ts\n"));
+    }
+
+    #[test]
+    fn compares_segment_tags_and_date_ranges() {
+        let old = parse(indoc::indoc! {r#"
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-DATERANGE:ID="ad",START-DATE="2024-01-01T00:00:00Z",PLANNED-DURATION=30
+            #EXT-X-KEY:METHOD=AES-128,URI="k1"
+            #EXTINF:9,
+            #EXT-X-BYTERANGE:100@0
+            a.ts
+        "#});
+        let new = parse(indoc::indoc! {r#"
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-PLAYLIST-TYPE:EVENT
+            #EXT-X-START:TIME-OFFSET=5,PRECISE=YES
+            #EXT-X-DATERANGE:ID="ad",START-DATE="2024-01-01T00:00:00Z",DURATION=30
+            #EXT-X-KEY:METHOD=AES-128,URI="k2"
+            #EXT-X-GAP
+            #EXTINF:9,
+            #EXT-X-BYTERANGE:100@0
+            a.ts
+        "#});
+        assert_ne!(old, new);
+        let diff = old.diff(&new);
+        let summary: Vec<_> = diff
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::Header { tag, .. } | Change::SegmentTag { tag, .. } => *tag,
+                Change::DateRangeChanged { .. } => "EXT-X-DATERANGE",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(summary, ["EXT-X-PLAYLIST-TYPE", "EXT-X-START", "EXT-X-DATERANGE", "EXT-X-KEY", "EXT-X-GAP"]);
+        let rendered = diff.to_string();
+        assert!(rendered.contains("\n+#EXT-X-PLAYLIST-TYPE:EVENT\n+#EXT-X-START:TIME-OFFSET=5,PRECISE=YES\n"), "{}", rendered);
+        assert!(rendered.ends_with("+#EXT-X-GAP\n"), "{}", rendered);
+        assert!(diff.to_string().contains("-#EXT-X-KEY:METHOD=AES-128,URI=\"k1\"\n+#EXT-X-KEY:METHOD=AES-128,URI=\"k2\"\n"));
+    }
+
+    #[test]
+    fn skips_the_numbers_between_far_apart_windows() {
+        let old = parse("#EXT-X-TARGETDURATION:10\n#EXTINF:9,\na.ts\n");
+        let new = parse("#EXT-X-TARGETDURATION:10\n#EXT-X-MEDIA-SEQUENCE:1000000000000\n#EXTINF:9,\nz.ts\n");
+        let segments: Vec<_> = old
+            .diff(&new)
+            .changes
+            .into_iter()
+            .filter_map(|change| match change {
+                Change::SegmentRemoved { media_sequence, .. } | Change::SegmentAdded { media_sequence, .. } => Some(media_sequence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(segments, [0, 1_000_000_000_000]);
+    }
+}
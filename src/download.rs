@@ -0,0 +1,380 @@
+//! Downloading the resources of a media playlist, behind the `fetch` cargo feature.
+//!
+//! A [`Downloader`] saves every segment, media initialization section and key of a playlist
+//! into a directory, a few at a time, and writes a local copy of the playlist referring to the
+//! saved files. Segments and maps with a byte range are requested with a `Range` header and
+//! saved on their own, so the local playlist has no byte ranges.
+//!
+//! Each file is written to `<name>.part` first and renamed once complete, so an interrupted
+//! download resumes from where it stopped. Files already in the directory are kept, and the size
+//! of every file is checked against its byte range, or against the size announced by the server.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::fetch::{follow_redirects, Transport, UreqTransport};
+use crate::media_playlist::{ByteRange, MediaPlaylist};
+
+/// How a [`DownloadedFile`] got into the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Downloaded,
+    /// Completed from a `.part` file left by an interrupted download.
+    Resumed,
+    /// The file was already there, nothing was requested.
+    AlreadyPresent,
+}
+
+/// A resource saved by a [`Downloader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedFile {
+    /// URL the resource was requested from, before redirects.
+    pub url: String,
+    /// The part of the resource saved, `None` for all of it.
+    pub byte_range: Option<ByteRange>,
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub size: u64,
+    pub status: FileStatus,
+}
+
+/// The result of [`Downloader::download`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    /// The playlist referring to the saved files by their names.
+    pub playlist: MediaPlaylist,
+    /// Where `playlist` was written.
+    pub playlist_path: PathBuf,
+    /// Every saved resource, in playlist order.
+    pub files: Vec<DownloadedFile>,
+}
+
+// A resource to save, under `name` in the directory.
+#[derive(Debug)]
+struct Job {
+    url: String,
+    byte_range: Option<ByteRange>,
+    name: String,
+}
+
+/// Downloads the resources of a playlist, see the [module documentation][crate::download].
+#[derive(Debug, Clone)]
+pub struct Downloader<T = UreqTransport> {
+    transport: T,
+    concurrency: usize,
+    playlist_name: String,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downloader {
+    pub fn new() -> Self {
+        Self::with_transport(UreqTransport::default())
+    }
+}
+
+impl<T: Transport + Sync> Downloader<T> {
+    pub fn with_transport(transport: T) -> Self {
+        Downloader { transport, concurrency: 4, playlist_name: "index.m3u8".to_string() }
+    }
+
+    /// Sets how many files are downloaded at the same time, 4 by default.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the file name of the local playlist, `index.m3u8` by default.
+    pub fn with_playlist_name(mut self, playlist_name: &str) -> Self {
+        self.playlist_name = playlist_name.to_string();
+        self
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Saves the segments, maps and keys of `playlist` into `dir`, creating it if needed, and
+    /// writes the local playlist there.
+    ///
+    /// Resources are requested from their resolved URIs (see
+    /// [`MediaPlaylist::parse_ext_m3u_with_base`]), or their URIs as written when they were not
+    /// resolved. Keys are only saved when served over HTTP(S), others (e.g. `skd://`) are kept as
+    /// they are. A resource used by several segments is saved once.
+    pub fn download(&self, playlist: &MediaPlaylist, dir: impl AsRef<Path>) -> Result<Download> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+
+        let (playlist, jobs) = localize(playlist);
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..jobs.len()).map(|_| None).collect::<Vec<_>>());
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(jobs.len()) {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(index) else { break };
+                        let result = self.save(job, dir).with_context(|| format!("downloading {}", job.url));
+                        failed.fetch_or(result.is_err(), Ordering::Relaxed);
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                });
+            }
+        });
+        let files = results.into_inner().unwrap().into_iter().flatten().collect::<Result<Vec<_>>>()?;
+
+        let playlist_path = dir.join(&self.playlist_name);
+        fs::write(&playlist_path, playlist.to_string()).with_context(|| format!("writing {}", playlist_path.display()))?;
+        Ok(Download { playlist, playlist_path, files })
+    }
+
+    // Saves one resource, resuming from its `.part` file if there is one.
+    fn save(&self, job: &Job, dir: &Path) -> Result<DownloadedFile> {
+        let path = dir.join(&job.name);
+        let saved = |size, status| DownloadedFile { url: job.url.clone(), byte_range: job.byte_range, path: path.clone(), size, status };
+        let mut expected = job.byte_range.map(|range| range.length);
+        if let Ok(metadata) = fs::metadata(&path) {
+            if expected.is_none_or(|expected| metadata.len() == expected) {
+                return Ok(saved(metadata.len(), FileStatus::AlreadyPresent));
+            }
+        }
+
+        let partial = dir.join(format!("{}.part", job.name));
+        let mut resume_from = fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+        if expected.is_some_and(|expected| resume_from >= expected) {
+            resume_from = 0;
+        }
+        let offset = job.byte_range.map_or(0, |range| range.offset.unwrap_or(0));
+        let first = offset + resume_from;
+        let end = job
+            .byte_range
+            .map(|range| range.end().ok_or_else(|| anyhow!("byte range {} ends past the largest offset", range)))
+            .transpose()?;
+        let last = end.map(|end| end.saturating_sub(1));
+
+        let (_, response) = follow_redirects(&job.url, |url| match (job.byte_range, resume_from) {
+            (None, 0) => self.transport.get(url),
+            _ => self.transport.get_range(url, first, last),
+        })?;
+        let resumed = response.status == 206 && resume_from > 0;
+        let body = if response.status == 206 {
+            let content_range = response.header("content-range").and_then(content_range);
+            if let Some((start, total)) = content_range {
+                if start != first {
+                    return Err(anyhow!("asked for bytes from {}, got bytes from {}", first, start));
+                }
+                expected = expected.or(total);
+            }
+            response.body
+        } else {
+            // the whole resource
+            let mut body = response.body;
+            if let Some(end) = end {
+                if (body.len() as u64) < end {
+                    return Err(anyhow!("expected {} bytes at least, got {}", end, body.len()));
+                }
+                body.truncate(end as usize);
+                body.drain(..offset as usize);
+            }
+            body
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&partial)
+            .with_context(|| format!("opening {}", partial.display()))?;
+        file.write_all(&body).with_context(|| format!("writing {}", partial.display()))?;
+        drop(file);
+
+        let size = fs::metadata(&partial)?.len();
+        if let Some(expected) = expected {
+            if size != expected {
+                // a short file is kept to be resumed
+                if size > expected {
+                    fs::remove_file(&partial)?;
+                }
+                return Err(anyhow!("expected {} bytes, got {}", expected, size));
+            }
+        }
+        fs::rename(&partial, &path).with_context(|| format!("renaming {}", partial.display()))?;
+        Ok(saved(size, if resumed { FileStatus::Resumed } else { FileStatus::Downloaded }))
+    }
+}
+
+// The local copy of `playlist` and the resources it refers to.
+fn localize(playlist: &MediaPlaylist) -> (MediaPlaylist, Vec<Job>) {
+    let mut local = playlist.clone();
+    let mut jobs = Vec::new();
+    // file names by URL and range
+    let mut names: HashMap<(String, Option<(u64, u64)>), String> = HashMap::new();
+    let mut name = |url: String, byte_range: Option<ByteRange>, new_name: &dyn Fn() -> String| {
+        let range = byte_range.map(|range| (range.offset.unwrap_or(0), range.length));
+        names
+            .entry((url.clone(), range))
+            .or_insert_with(|| {
+                let name = new_name();
+                jobs.push(Job { url, byte_range, name: name.clone() });
+                name
+            })
+            .clone()
+    };
+
+    let (inits, keys) = (std::cell::Cell::new(0), std::cell::Cell::new(0));
+    for (index, segment) in local.segments.iter_mut().enumerate() {
+        if let Some(map) = &mut segment.map {
+            let url = map.resolved_uri.take().unwrap_or_else(|| map.uri.clone());
+            let extension = extension(&url, "mp4").to_string();
+            map.uri = name(url, map.byte_range.take(), &|| format!("init{}.{}", inits.replace(inits.get() + 1), extension));
+        }
+        if let Some(key) = &mut segment.key {
+            let url = key.resolved_uri.take().or_else(|| key.uri.clone());
+            if let Some(url) = url.filter(|url| url.starts_with("http://") || url.starts_with("https://")) {
+                let extension = extension(&url, "key").to_string();
+                key.uri = Some(name(url, None, &|| format!("key{}.{}", keys.replace(keys.get() + 1), extension)));
+            }
+        }
+        let url = segment.resolved_url.take().unwrap_or_else(|| segment.url.clone());
+        let extension = extension(&url, "ts").to_string();
+        let media_sequence = playlist.media_sequence + index as u64;
+        segment.url = name(url, segment.byte_range.take(), &|| format!("segment{}.{}", media_sequence, extension));
+    }
+    (local, jobs)
+}
+
+// Extension of the last path segment of `url`, or `default`.
+fn extension<'a>(url: &'a str, default: &'a str) -> &'a str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest.split_once('/').map_or("", |(_, path)| path));
+    match path.rsplit('/').next().and_then(|file| file.rsplit_once('.')) {
+        Some((_, extension)) if !extension.is_empty() && extension.bytes().all(|b| b.is_ascii_alphanumeric()) => extension,
+        _ => default,
+    }
+}
+
+// First byte and complete length from a `Content-Range: bytes <first>-<last>/<length>` value.
+fn content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let first = range.split_once('-')?.0.parse().ok()?;
+    Some((first, total.parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fetch::HttpResponse;
+    use crate::stub_server::{ok, StubServer};
+
+    const BYTE_RANGES: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:7
+        #EXT-X-TARGETDURATION:4
+        #EXT-X-MEDIA-SEQUENCE:10
+        #EXT-X-KEY:METHOD=AES-128,URI="/keys/1.bin"
+        #EXT-X-MAP:URI="main.mp4",BYTERANGE="10@0"
+        #EXTINF:4,
+        #EXT-X-BYTERANGE:8@10
+        main.mp4
+        #EXTINF:4,
+        #EXT-X-BYTERANGE:12
+        main.mp4
+        #EXT-X-ENDLIST
+    "#};
+
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hls-download-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn saves_byte_ranges_maps_and_keys_and_rewrites_the_playlist() {
+        let main: Vec<u8> = (0..30).collect();
+        let server = StubServer::start(vec![("/media/main.mp4", ok(main.clone(), &[])), ("/keys/1.bin", ok("0123456789abcdef", &[]))]);
+        let playlist = MediaPlaylist::parse_ext_m3u_with_base(BYTE_RANGES, &format!("{}/media/index.m3u8", server.url)).unwrap();
+        let dir = empty_dir("ranges");
+
+        let download = Downloader::new().with_concurrency(2).download(&playlist, &dir).unwrap();
+        let names: Vec<_> = download.files.iter().map(|file| file.path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["init0.mp4", "key0.bin", "segment10.mp4", "segment11.mp4"]);
+        assert!(download.files.iter().all(|file| file.status == FileStatus::Downloaded));
+        assert_eq!(fs::read(dir.join("init0.mp4")).unwrap(), &main[..10]);
+        assert_eq!(fs::read(dir.join("segment10.mp4")).unwrap(), &main[10..18]);
+        assert_eq!(fs::read(dir.join("segment11.mp4")).unwrap(), &main[18..]);
+        assert_eq!(fs::read(dir.join("key0.bin")).unwrap(), b"0123456789abcdef");
+
+        let mut ranges: Vec<_> = server.requests().into_iter().filter_map(|request| request.range).collect();
+        ranges.sort();
+        assert_eq!(ranges, ["bytes=0-9", "bytes=10-17", "bytes=18-29"]);
+
+        let written = fs::read_to_string(&download.playlist_path).unwrap();
+        assert!(!written.contains("BYTERANGE"), "{}", written);
+        assert!(written.contains("#EXT-X-KEY:METHOD=AES-128,URI=\"key0.bin\""), "{}", written);
+        assert_eq!(MediaPlaylist::parse_ext_m3u(&written).unwrap(), download.playlist);
+
+        // nothing left to do the second time
+        let again = Downloader::new().download(&playlist, &dir).unwrap();
+        assert!(again.files.iter().all(|file| file.status == FileStatus::AlreadyPresent));
+        assert_eq!(server.requests().len(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resumes_partial_files() {
+        let body: Vec<u8> = (0..20).collect();
+        let server = StubServer::start(vec![("/a.ts", ok(body.clone(), &[]))]);
+        let playlist = MediaPlaylist::parse_ext_m3u_with_base(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\na.ts\n#EXT-X-ENDLIST\n",
+            &format!("{}/index.m3u8", server.url),
+        )
+        .unwrap();
+        let dir = empty_dir("resume");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("segment0.ts.part"), &body[..5]).unwrap();
+
+        let download = Downloader::new().with_playlist_name("local.m3u8").download(&playlist, &dir).unwrap();
+        assert_eq!(download.files[0].status, FileStatus::Resumed);
+        assert_eq!(download.files[0].size, 20);
+        assert_eq!(fs::read(dir.join("segment0.ts")).unwrap(), body);
+        assert!(!dir.join("segment0.ts.part").exists());
+        assert_eq!(server.requests()[0].range.as_deref(), Some("bytes=5-"));
+        assert_eq!(download.playlist_path, dir.join("local.m3u8"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_sizes_against_byte_ranges() {
+        // a server ignoring Range headers, with a resource shorter than the playlist says
+        let transport = |_: &str| Ok(HttpResponse { status: 200, headers: Vec::new(), body: (0..25).collect() });
+        let playlist = MediaPlaylist::parse_ext_m3u(BYTE_RANGES.replace("/keys/1.bin", "skd://key").as_str()).unwrap();
+        let dir = empty_dir("sizes");
+
+        let error = Downloader::with_transport(transport).with_concurrency(1).download(&playlist, &dir).unwrap_err();
+        assert_eq!(format!("{:#}", error), "downloading main.mp4: expected 30 bytes at least, got 25");
+        // the ranges that could be sliced out were saved, the key was left alone
+        assert_eq!(fs::read(dir.join("segment10.mp4")).unwrap(), (10..18).collect::<Vec<u8>>());
+        assert!(!dir.join("index.m3u8").exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut overflowing = playlist.clone();
+        overflowing.segments[1].byte_range = Some(ByteRange { length: u64::MAX, offset: Some(1) });
+        let dir = empty_dir("overflow");
+        let error = Downloader::with_transport(transport).download(&overflowing, &dir).unwrap_err();
+        assert!(format!("{:#}", error).contains("ends past the largest offset"), "{:#}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,292 @@
+//! Fetching playlists over HTTP(S), behind the `fetch` cargo feature.
+//!
+//! A [`Fetcher`] downloads a playlist through a [`Transport`], follows redirects itself so the
+//! relative URIs of the playlist resolve against the URL it was finally served from, and keeps
+//! responses for as long as their `Cache-Control: max-age` (minus their `Age`) allows, see
+//! <https://datatracker.ietf.org/doc/html/rfc9111#section-4.2>.
+//!
+//! [`UreqTransport`] is the default transport; tests and applications with their own HTTP stack
+//! implement [`Transport`] instead.
+
+use core::time::Duration;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::master_playlist::MasterPlaylist;
+use crate::media_playlist::MediaPlaylist;
+use crate::uri;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// A response to a GET request, as given by a [`Transport`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Performs the HTTP requests of a [`Fetcher`].
+pub trait Transport {
+    /// Sends a GET request for `url`. Redirects must not be followed, the [`Fetcher`] does it,
+    /// and a status other than 2xx is not an error.
+    fn get(&self, url: &str) -> Result<HttpResponse>;
+
+    /// Sends a GET request for bytes `first..=last` of `url`, or `first..` when `last` is
+    /// `None`. The server may answer with the whole resource (200) rather than the range (206).
+    /// Sends a plain GET by default.
+    fn get_range(&self, url: &str, first: u64, last: Option<u64>) -> Result<HttpResponse> {
+        let _ = (first, last);
+        self.get(url)
+    }
+}
+
+impl<F: Fn(&str) -> Result<HttpResponse>> Transport for F {
+    fn get(&self, url: &str) -> Result<HttpResponse> {
+        self(url)
+    }
+}
+
+/// [`Transport`] over [ureq](https://docs.rs/ureq), for `http` and `https` URLs.
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+impl Default for UreqTransport {
+    fn default() -> Self {
+        let config = ureq::Agent::config_builder().max_redirects(0).http_status_as_error(false).build();
+        UreqTransport { agent: ureq::Agent::new_with_config(config) }
+    }
+}
+
+impl Transport for UreqTransport {
+    fn get(&self, url: &str) -> Result<HttpResponse> {
+        Self::response(self.agent.get(url).call()?)
+    }
+
+    fn get_range(&self, url: &str, first: u64, last: Option<u64>) -> Result<HttpResponse> {
+        let last = last.map(|last| last.to_string()).unwrap_or_default();
+        Self::response(self.agent.get(url).header("Range", format!("bytes={}-{}", first, last)).call()?)
+    }
+}
+
+impl UreqTransport {
+    fn response(mut response: ureq::http::Response<ureq::Body>) -> Result<HttpResponse> {
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: response.body_mut().read_to_vec()?,
+        })
+    }
+}
+
+/// A playlist returned by [`Fetcher`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fetched<T> {
+    pub playlist: T,
+    /// URL the playlist was served from, after redirects. Its URIs are resolved against it.
+    pub url: String,
+    /// How much longer the response may be reused, `None` if it may not be cached.
+    pub fresh_for: Option<Duration>,
+    /// Whether the playlist came from the cache of the fetcher rather than the network.
+    pub cached: bool,
+}
+
+// A cached response body.
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    url: String,
+    body: String,
+    expires: Instant,
+}
+
+/// Downloads playlists, see the [module documentation][crate::fetch].
+#[derive(Debug, Clone)]
+pub struct Fetcher<T = UreqTransport> {
+    transport: T,
+    // keyed by the requested URL, before redirects
+    cache: HashMap<String, CacheEntry>,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fetcher {
+    pub fn new() -> Self {
+        Self::with_transport(UreqTransport::default())
+    }
+}
+
+impl<T: Transport> Fetcher<T> {
+    pub fn with_transport(transport: T) -> Self {
+        Fetcher { transport, cache: HashMap::new() }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Downloads and parses the media playlist at `url`, see
+    /// [`MediaPlaylist::parse_ext_m3u_with_base`].
+    pub fn fetch_media(&mut self, url: &str) -> Result<Fetched<MediaPlaylist>> {
+        self.fetch(url, MediaPlaylist::parse_ext_m3u_with_base)
+    }
+
+    /// Downloads and parses the master playlist at `url`, see
+    /// [`MasterPlaylist::parse_ext_m3u_with_base`].
+    pub fn fetch_master(&mut self, url: &str) -> Result<Fetched<MasterPlaylist>> {
+        self.fetch(url, MasterPlaylist::parse_ext_m3u_with_base)
+    }
+
+    /// Forgets every cached response.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    fn fetch<P>(&mut self, url: &str, parse: impl Fn(&str, &str) -> Result<P>) -> Result<Fetched<P>> {
+        let now = Instant::now();
+        if let Some(entry) = self.cache.get(url).filter(|entry| entry.expires > now) {
+            return Ok(Fetched {
+                playlist: parse(&entry.body, &entry.url).with_context(|| format!("parsing {}", entry.url))?,
+                url: entry.url.clone(),
+                fresh_for: Some(entry.expires - now),
+                cached: true,
+            });
+        }
+        self.cache.remove(url);
+
+        let (final_url, response) = self.get_following_redirects(url)?;
+        let age = response.header("age").and_then(|age| age.trim().parse().ok()).unwrap_or(0);
+        let fresh_for = response.header("cache-control").and_then(|cache_control| freshness(cache_control, age));
+        let body = String::from_utf8(response.body).map_err(|_| anyhow!("{}: the playlist is not UTF-8", final_url))?;
+        let playlist = parse(&body, &final_url).with_context(|| format!("parsing {}", final_url))?;
+        if let Some(fresh_for) = fresh_for {
+            let entry = CacheEntry { url: final_url.clone(), body, expires: now + fresh_for };
+            self.cache.insert(url.to_string(), entry);
+        }
+        Ok(Fetched { playlist, url: final_url, fresh_for, cached: false })
+    }
+
+    fn get_following_redirects(&self, url: &str) -> Result<(String, HttpResponse)> {
+        follow_redirects(url, |url| self.transport.get(url))
+    }
+}
+
+// Sends the request with `send`, following up to MAX_REDIRECTS redirects. Returns the final URL
+// and its successful response.
+pub(crate) fn follow_redirects(url: &str, send: impl Fn(&str) -> Result<HttpResponse>) -> Result<(String, HttpResponse)> {
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let response = send(&url).with_context(|| format!("GET {}", url))?;
+        match response.status {
+            200..=299 => return Ok((url, response)),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response
+                    .header("location")
+                    .ok_or_else(|| anyhow!("GET {}: HTTP {} without a Location", url, response.status))?;
+                url = uri::resolve(&url, location);
+            }
+            status => return Err(anyhow!("GET {}: HTTP {}", url, status)),
+        }
+    }
+    Err(anyhow!("GET {}: more than {} redirects", url, MAX_REDIRECTS))
+}
+
+// How long a response with the given Cache-Control header value and Age (in seconds) stays
+// fresh, `None` if it must not be reused without revalidation.
+fn freshness(cache_control: &str, age: u64) -> Option<Duration> {
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+        match name.to_ascii_lowercase().as_str() {
+            "no-store" | "no-cache" => return None,
+            "max-age" => max_age = value.trim_matches('"').parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+    let remaining = max_age?.checked_sub(age)?;
+    (remaining > 0).then(|| Duration::from_secs(remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::stub_server::{ok, StubServer};
+
+    const MEDIA: &str = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nsegment0.ts\n";
+
+    #[test]
+    fn follows_redirects_and_resolves_against_the_final_url() {
+        let server = StubServer::start(vec![
+            ("/live.m3u8", HttpResponse { status: 302, headers: vec![("Location".into(), "/cdn/v1/index.m3u8".into())], body: Vec::new() }),
+            ("/cdn/v1/index.m3u8", ok(MEDIA, &[("Content-Type", "application/vnd.apple.mpegurl")])),
+        ]);
+        let mut fetcher = Fetcher::new();
+
+        let fetched = fetcher.fetch_media(&format!("{}/live.m3u8", server.url)).unwrap();
+        assert_eq!(fetched.url, format!("{}/cdn/v1/index.m3u8", server.url));
+        let expected = format!("{}/cdn/v1/segment0.ts", server.url);
+        assert_eq!(fetched.playlist.segments()[0].resolved_uri(), Some(expected.as_str()));
+        assert_eq!((fetched.fresh_for, fetched.cached), (None, false));
+
+        let error = fetcher.fetch_media(&format!("{}/missing.m3u8", server.url)).unwrap_err();
+        assert!(error.to_string().ends_with("HTTP 404"), "{}", error);
+    }
+
+    #[test]
+    fn honours_cache_control_and_age() {
+        let server = StubServer::start(vec![
+            ("/fresh.m3u8", ok(MEDIA, &[("Cache-Control", "public, max-age=60"), ("Age", "20")])),
+            ("/stale.m3u8", ok(MEDIA, &[("Cache-Control", "max-age=60"), ("Age", "60")])),
+            ("/no-store.m3u8", ok(MEDIA, &[("Cache-Control", "no-store, max-age=60")])),
+        ]);
+        let mut fetcher = Fetcher::new();
+
+        let first = fetcher.fetch_media(&format!("{}/fresh.m3u8", server.url)).unwrap();
+        assert!(first.fresh_for.unwrap() <= Duration::from_secs(40));
+        let second = fetcher.fetch_media(&format!("{}/fresh.m3u8", server.url)).unwrap();
+        assert!(second.cached);
+        assert_eq!(second.playlist, first.playlist);
+        assert_eq!(server.requests().len(), 1);
+
+        for path in ["/stale.m3u8", "/no-store.m3u8"] {
+            fetcher.fetch_media(&format!("{}{}", server.url, path)).unwrap();
+            assert!(!fetcher.fetch_media(&format!("{}{}", server.url, path)).unwrap().cached);
+        }
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[test]
+    fn accepts_custom_transports() {
+        let transport = |url: &str| match url {
+            "https://a.test/master.m3u8" => Ok(ok("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1000\nlow/index.m3u8\n", &[])),
+            _ => Err(anyhow!("unreachable")),
+        };
+        let mut fetcher = Fetcher::with_transport(transport);
+        let fetched = fetcher.fetch_master("https://a.test/master.m3u8").unwrap();
+        assert_eq!(fetched.playlist.variants()[0].resolved_uri.as_deref(), Some("https://a.test/low/index.m3u8"));
+        assert!(fetcher.fetch_master("https://b.test/master.m3u8").is_err());
+    }
+}
@@ -2,6 +2,18 @@
 //!
 //! This library follows [this specification][spec] to parse [ext-m3u][m3u] formatted data.
 //!
+//! # Cargo features
+//!
+//! - `serde`: `Serialize` and `Deserialize` for the playlist types, with a stable JSON shape
+//!   documented in `serialization.rs`.
+//! - `gzip`: [`MediaPlaylist::from_reader`] and [`MediaPlaylist::from_path`] decompress
+//!   gzip-compressed playlists.
+//! - `async`: [`MediaPlaylist::from_async_reader`] and [`SegmentStream`], parsing from a tokio
+//!   `AsyncBufRead` as the bytes arrive.
+//! - `fetch`: the [`fetch`] module, downloading playlists over HTTP(S), the [`poller`] module
+//!   reloading live playlists and the [`download`] module saving their segments locally.
+//! - `dash`: the [`dash`] module, reading DASH manifests into media playlists.
+//!
 //! [m3u]: https://en.wikipedia.org/wiki/M3U#Extended_M3U
 //! [spec]: https://datatracker.ietf.org/doc/html/rfc8216#section-4
 //! [wiki]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
@@ -10,5 +22,104 @@
 //include the media_playlist.rs file
 mod media_playlist;
 
+// master playlists (variant streams and renditions), see master_playlist.rs
+mod master_playlist;
+
+// spec conformance checks, see lint.rs
+pub mod lint;
+
+// merging of live playlist reloads, see live.rs
+pub mod live;
+
+// comparison of two playlists, see diff.rs
+pub mod diff;
+
+// writing a playlist back to ext-m3u text, see writer.rs
+mod writer;
+
+// joining several playlists, see concat.rs
+mod concat;
+
+// cutting a time range out of a playlist, see clip.rs
+mod clip;
+
+// seek lookups, see locate.rs
+mod locate;
+
+// presentation timeline of the discontinuity groups, see timeline.rs
+mod timeline;
+
+// RFC 3986 URI resolution, see uri.rs
+pub mod uri;
+
+// incremental parsing of chunked input, see streaming.rs
+mod streaming;
+
+// zero-copy parsing, see borrowed.rs
+mod borrowed;
+
+// parsing from readers and files, see reader.rs
+pub mod reader;
+
+// async parsing with tokio, see asynchronous.rs
+#[cfg(feature = "async")]
+mod asynchronous;
+
+// downloading playlists over HTTP, see fetch.rs
+#[cfg(feature = "fetch")]
+pub mod fetch;
+
+// reloading live playlists, see poller.rs
+#[cfg(feature = "fetch")]
+pub mod poller;
+
+// saving the resources of a playlist, see download.rs
+#[cfg(feature = "fetch")]
+pub mod download;
+
+// local HTTP origin for the tests, see stub_server.rs
+#[cfg(all(test, feature = "fetch"))]
+mod stub_server;
+
+// live playlists simulated from a VOD playlist, see simulator.rs
+pub mod simulator;
+
+// local HLS origin for development and tests, see serve.rs
+pub mod serve;
+
+// recording of live playlists into a VOD one, see record.rs
+pub mod record;
+
+// conversion to DASH manifests, see mpd.rs
+pub mod mpd;
+
+// conversion of DASH manifests to media playlists, see dash.rs
+#[cfg(feature = "dash")]
+pub mod dash;
+
+// MPEG-TS demuxing and checks of segment durations, see ts.rs
+pub mod ts;
+
+// low-latency reload URLs, see reload.rs
+mod reload;
+
+// tag attribute lists, see attributes.rs
+mod attributes;
+
+// serde support, see serialization.rs for the serialized shape
+#[cfg(feature = "serde")]
+mod serialization;
+
 //use the MediaPlaylist and MediaSegment structure in the media_playlist.
-pub use media_playlist::{MediaPlaylist, MediaSegment};
+pub use media_playlist::{ByteRange, DateRange, DiscontinuityGroup, DiscontinuitySegment, Key, Map, MediaPlaylist, MediaSegment, PlaylistType, ServerControl, StartPoint};
+pub use master_playlist::{MasterPlaylist, Rendition, VariantStream};
+pub use lint::{Diagnostic, Linter, Severity};
+pub use clip::Clip;
+pub use diff::{Change, PlaylistDiff};
+pub use locate::SegmentPosition;
+pub use borrowed::{MediaPlaylistRef, MediaSegmentRef};
+pub use streaming::{ParseEvent, StreamingParser};
+pub use reload::{DeltaUpdate, ReloadRequest};
+#[cfg(feature = "async")]
+pub use asynchronous::SegmentStream;
+pub use live::{LivePlaylistTracker, Reload, ReloadStatus, TrackedSegment};
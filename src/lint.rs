@@ -0,0 +1,656 @@
+//! Spec conformance checks for media playlists.
+//!
+//! The linter runs a catalogue of [RFC 8216][spec] MUST/SHOULD rules and reports every
+//! violation as a [`Diagnostic`]. [`Linter::lint_playlist`] checks a parsed [`MediaPlaylist`]:
+//! durations, versions and discontinuity sequence numbers. [`Linter::lint`] checks the playlist
+//! text, which adds the rules about tag ordering and repeated tags that the parsed model does
+//! not keep, and points each diagnostic at its line.
+//!
+//! [spec]: https://datatracker.ietf.org/doc/html/rfc8216
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::media_playlist::MediaPlaylist;
+
+/// How bad a rule violation is. MUST rules of the specification are reported as
+/// [`Severity::Error`], SHOULD rules as [`Severity::Warning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// One entry of the rule catalogue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// Stable identifier, e.g. `HLS003`. Used to enable/disable the rule.
+    pub id: &'static str,
+    pub severity: Severity,
+    /// Short description of the rule and the section of the specification it comes from.
+    pub description: &'static str,
+}
+
+/// A single rule violation found by the [`Linter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule_id: &'static str,
+    pub severity: Severity,
+    /// 1-based line number in the playlist the diagnostic points at, when linting text.
+    pub line: Option<usize>,
+    /// Media sequence number of the segment the diagnostic is about, if any.
+    pub media_sequence: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.media_sequence) {
+            (Some(line), _) => write!(f, "{}:", line)?,
+            (None, Some(media_sequence)) => write!(f, "segment {}:", media_sequence)?,
+            (None, None) => write!(f, "playlist:")?,
+        }
+        write!(f, "{} [{}] {}", self.severity, self.rule_id, self.message)
+    }
+}
+
+pub const MISSING_HEADER: &str = "HLS001";
+pub const MISSING_TARGET_DURATION: &str = "HLS002";
+pub const SEGMENT_EXCEEDS_TARGET_DURATION: &str = "HLS003";
+pub const DUPLICATE_TAG: &str = "HLS004";
+pub const MEDIA_SEQUENCE_AFTER_SEGMENT: &str = "HLS005";
+pub const DISCONTINUITY_SEQUENCE_MISPLACED: &str = "HLS006";
+pub const SEGMENT_AFTER_ENDLIST: &str = "HLS007";
+pub const EXTINF_WITHOUT_URI: &str = "HLS008";
+pub const URI_WITHOUT_EXTINF: &str = "HLS009";
+pub const INVALID_ATTRIBUTE_VALUE: &str = "HLS010";
+pub const VERSION_TOO_LOW: &str = "HLS011";
+pub const MISSING_VERSION: &str = "HLS012";
+pub const EMPTY_DISCONTINUITY: &str = "HLS013";
+pub const DISCONTINUITY_SEQUENCE_INCONSISTENT: &str = "HLS014";
+
+/// Every rule the linter knows about, in reporting order.
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: MISSING_HEADER,
+        severity: Severity::Error,
+        description: "The first line MUST be #EXTM3U (4.3.1.1)",
+    },
+    Rule {
+        id: MISSING_TARGET_DURATION,
+        severity: Severity::Error,
+        description: "EXT-X-TARGETDURATION is REQUIRED (4.3.3.1)",
+    },
+    Rule {
+        id: SEGMENT_EXCEEDS_TARGET_DURATION,
+        severity: Severity::Error,
+        description: "EXTINF rounded to the nearest integer MUST NOT exceed the target duration (4.3.3.1)",
+    },
+    Rule {
+        id: DUPLICATE_TAG,
+        severity: Severity::Error,
+        description: "Playlist tags MUST NOT appear more than once (4.3.3, 4.3.5)",
+    },
+    Rule {
+        id: MEDIA_SEQUENCE_AFTER_SEGMENT,
+        severity: Severity::Error,
+        description: "EXT-X-MEDIA-SEQUENCE MUST appear before the first media segment (4.3.3.2)",
+    },
+    Rule {
+        id: DISCONTINUITY_SEQUENCE_MISPLACED,
+        severity: Severity::Error,
+        description: "EXT-X-DISCONTINUITY-SEQUENCE MUST appear before the first media segment and any EXT-X-DISCONTINUITY (4.3.3.3)",
+    },
+    Rule {
+        id: SEGMENT_AFTER_ENDLIST,
+        severity: Severity::Error,
+        description: "No media segments may follow EXT-X-ENDLIST (4.3.3.4)",
+    },
+    Rule {
+        id: EXTINF_WITHOUT_URI,
+        severity: Severity::Error,
+        description: "Every EXTINF MUST be followed by a media segment URI (4.3.2.1)",
+    },
+    Rule {
+        id: URI_WITHOUT_EXTINF,
+        severity: Severity::Error,
+        description: "Every media segment URI MUST be preceded by an EXTINF (4.3.2.1)",
+    },
+    Rule {
+        id: INVALID_ATTRIBUTE_VALUE,
+        severity: Severity::Error,
+        description: "Tag values MUST be well formed numbers (4.2)",
+    },
+    Rule {
+        id: VERSION_TOO_LOW,
+        severity: Severity::Error,
+        description: "EXT-X-VERSION MUST cover every feature used by the playlist (7)",
+    },
+    Rule {
+        id: MISSING_VERSION,
+        severity: Severity::Warning,
+        description: "EXT-X-VERSION SHOULD be present (4.3.1.2)",
+    },
+    Rule {
+        id: EMPTY_DISCONTINUITY,
+        severity: Severity::Warning,
+        description: "EXT-X-DISCONTINUITY SHOULD be followed by a media segment (4.3.2.3)",
+    },
+    Rule {
+        id: DISCONTINUITY_SEQUENCE_INCONSISTENT,
+        severity: Severity::Error,
+        description: "EXT-X-DISCONTINUITY-SEQUENCE MUST number the first segment, and each EXT-X-DISCONTINUITY adds one (4.3.3.3)",
+    },
+];
+
+/// Looks up a rule of the catalogue by its identifier.
+pub fn rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == id)
+}
+
+/// Runs every rule of the catalogue over `source`. Shorthand for `Linter::new().lint(source)`.
+pub fn lint(source: &str) -> Vec<Diagnostic> {
+    Linter::new().lint(source)
+}
+
+// Tags which describe the whole playlist and so may only appear once.
+const SINGLE_TAGS: &[&str] = &[
+    "#EXT-X-VERSION",
+    "#EXT-X-TARGETDURATION",
+    "#EXT-X-MEDIA-SEQUENCE",
+    "#EXT-X-DISCONTINUITY-SEQUENCE",
+    "#EXT-X-ENDLIST",
+    "#EXT-X-PLAYLIST-TYPE",
+    "#EXT-X-I-FRAMES-ONLY",
+    "#EXT-X-INDEPENDENT-SEGMENTS",
+    "#EXT-X-START",
+];
+
+/// Runs the rule catalogue over playlists, with rules individually enabled or disabled.
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    disabled: HashSet<&'static str>,
+}
+
+impl Linter {
+    /// Creates a linter with every rule enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns a rule off. Unknown identifiers are ignored.
+    pub fn disable(&mut self, id: &str) -> &mut Self {
+        if let Some(rule) = rule(id) {
+            self.disabled.insert(rule.id);
+        }
+        self
+    }
+
+    /// Turns a previously disabled rule back on.
+    pub fn enable(&mut self, id: &str) -> &mut Self {
+        self.disabled.remove(id);
+        self
+    }
+
+    pub fn is_enabled(&self, id: &str) -> bool {
+        !self.disabled.contains(id)
+    }
+
+    // The diagnostic of a rule violation, `None` when the rule is disabled.
+    fn diagnostic(
+        &self,
+        id: &'static str,
+        line: Option<usize>,
+        media_sequence: Option<u64>,
+        message: String,
+    ) -> Option<Diagnostic> {
+        let severity = rule(id).map(|rule| rule.severity).unwrap_or(Severity::Error);
+        self.is_enabled(id).then_some(Diagnostic { rule_id: id, severity, line, media_sequence, message })
+    }
+
+    /// Checks a parsed playlist against every enabled rule the model can tell. Diagnostics
+    /// about a segment give its media sequence number and none has a line.
+    pub fn lint_playlist(&self, playlist: &MediaPlaylist) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |id: &'static str, media_sequence: Option<u64>, message: String| {
+            diagnostics.extend(self.diagnostic(id, None, media_sequence, message));
+        };
+
+        let target = playlist.target_duration.as_secs();
+        if target == 0 && !playlist.segments.is_empty() {
+            report(MISSING_TARGET_DURATION, None, "missing #EXT-X-TARGETDURATION".to_string());
+        }
+        for (segment, media_sequence) in playlist.segments.iter().zip(playlist.media_sequence..) {
+            let seconds = segment.duration.as_secs_f64();
+            if target > 0 && seconds.round() as u64 > target {
+                report(
+                    SEGMENT_EXCEEDS_TARGET_DURATION,
+                    Some(media_sequence),
+                    format!("segment duration {} exceeds target duration {}", seconds, target),
+                );
+            }
+            let features = [
+                (segment.duration.subsec_nanos() != 0, 3, "floating point EXTINF durations"),
+                (segment.byte_range.is_some(), 4, "EXT-X-BYTERANGE"),
+                (segment.map.is_some(), 5, "EXT-X-MAP"),
+            ];
+            for (_, required, feature) in features.into_iter().filter(|(used, _, _)| *used) {
+                if playlist.version < required {
+                    report(
+                        VERSION_TOO_LOW,
+                        Some(media_sequence),
+                        format!("{} requires EXT-X-VERSION {} but playlist declares {}", feature, required, playlist.version),
+                    );
+                }
+            }
+        }
+        for (media_sequence, message) in discontinuity_sequence_errors(playlist) {
+            report(DISCONTINUITY_SEQUENCE_INCONSISTENT, Some(media_sequence), message);
+        }
+        diagnostics
+    }
+
+    /// Checks `source` against every enabled rule. Diagnostics are sorted by line.
+    pub fn lint(&self, source: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |id: &'static str, line: usize, message: String| {
+            diagnostics.extend(self.diagnostic(id, Some(line), None, message));
+        };
+
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim_end()))
+            .peekable();
+
+        // Only skip the first line if it really is the header, otherwise it still gets checked
+        if lines.next_if(|(_, line)| *line == "#EXTM3U").is_none() {
+            report(MISSING_HEADER, 1, "playlist does not start with #EXTM3U".to_string());
+        }
+
+        // Line of the first occurrence of each single tag
+        let mut seen: Vec<(&str, usize)> = Vec::new();
+        let mut version = None;
+        let mut target_duration = None;
+        // (line, duration) of every EXTINF, checked against the target duration at the end
+        let mut durations: Vec<(usize, f64)> = Vec::new();
+        let mut pending_extinf = None;
+        let mut pending_discontinuity = None;
+        // line of the URI of every media segment
+        let mut segment_lines = Vec::new();
+        let mut discontinuity_seen = false;
+        let mut endlist_line = None;
+        // (line, minimum version, feature) of every versioned feature used
+        let mut features: Vec<(usize, u64, &str)> = Vec::new();
+
+        for (number, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+
+            if !line.starts_with('#') {
+                // A media segment URI
+                if pending_extinf.take().is_none() {
+                    report(URI_WITHOUT_EXTINF, number, format!("URI '{}' has no preceding #EXTINF", line));
+                }
+                if let Some(endlist) = endlist_line {
+                    report(
+                        SEGMENT_AFTER_ENDLIST,
+                        number,
+                        format!("media segment after #EXT-X-ENDLIST on line {}", endlist),
+                    );
+                }
+                pending_discontinuity = None;
+                segment_lines.push(number);
+                continue;
+            }
+
+            if !line.starts_with("#EXT") {
+                continue; // comment
+            }
+
+            let (name, value) = line.split_once(':').unwrap_or((line, ""));
+
+            if SINGLE_TAGS.contains(&name) {
+                match seen.iter().find(|(tag, _)| *tag == name) {
+                    Some((_, first)) => report(
+                        DUPLICATE_TAG,
+                        number,
+                        format!("{} already appeared on line {}", name, first),
+                    ),
+                    None => seen.push((name, number)),
+                }
+            }
+
+            match name {
+                "#EXT-X-VERSION" => match value.parse::<u64>() {
+                    Ok(value) => version = Some(value),
+                    Err(_) => report(
+                        INVALID_ATTRIBUTE_VALUE,
+                        number,
+                        format!("EXT-X-VERSION '{}' is not a decimal integer", value),
+                    ),
+                },
+                "#EXT-X-TARGETDURATION" => match value.parse::<u64>() {
+                    Ok(value) => target_duration = Some((number, value)),
+                    Err(_) => report(
+                        INVALID_ATTRIBUTE_VALUE,
+                        number,
+                        format!("EXT-X-TARGETDURATION '{}' is not a decimal integer", value),
+                    ),
+                },
+                "#EXT-X-MEDIA-SEQUENCE" => {
+                    if !segment_lines.is_empty() {
+                        report(
+                            MEDIA_SEQUENCE_AFTER_SEGMENT,
+                            number,
+                            "EXT-X-MEDIA-SEQUENCE appears after the first media segment".to_string(),
+                        );
+                    }
+                    if value.parse::<u64>().is_err() {
+                        report(
+                            INVALID_ATTRIBUTE_VALUE,
+                            number,
+                            format!("EXT-X-MEDIA-SEQUENCE '{}' is not a decimal integer", value),
+                        );
+                    }
+                }
+                "#EXT-X-DISCONTINUITY-SEQUENCE" => {
+                    if !segment_lines.is_empty() || discontinuity_seen {
+                        report(
+                            DISCONTINUITY_SEQUENCE_MISPLACED,
+                            number,
+                            "EXT-X-DISCONTINUITY-SEQUENCE appears after a media segment or EXT-X-DISCONTINUITY"
+                                .to_string(),
+                        );
+                    }
+                    if value.parse::<u64>().is_err() {
+                        report(
+                            INVALID_ATTRIBUTE_VALUE,
+                            number,
+                            format!("EXT-X-DISCONTINUITY-SEQUENCE '{}' is not a decimal integer", value),
+                        );
+                    }
+                }
+                "#EXT-X-DISCONTINUITY" => {
+                    discontinuity_seen = true;
+                    if let Some(previous) = pending_discontinuity.replace(number) {
+                        report(
+                            EMPTY_DISCONTINUITY,
+                            previous,
+                            "EXT-X-DISCONTINUITY is not followed by a media segment".to_string(),
+                        );
+                    }
+                }
+                "#EXTINF" => {
+                    if let Some(previous) = pending_extinf.replace(number) {
+                        report(EXTINF_WITHOUT_URI, previous, "#EXTINF is not followed by a URI".to_string());
+                    }
+                    let duration = value.split(',').next().unwrap_or_default();
+                    match duration.parse::<f64>() {
+                        Ok(seconds) if seconds >= 0.0 => {
+                            durations.push((number, seconds));
+                            if duration.contains('.') {
+                                features.push((number, 3, "floating point EXTINF durations"));
+                            }
+                        }
+                        _ => report(
+                            INVALID_ATTRIBUTE_VALUE,
+                            number,
+                            format!("EXTINF duration '{}' is not a decimal number", duration),
+                        ),
+                    }
+                }
+                "#EXT-X-BYTERANGE" => features.push((number, 4, "EXT-X-BYTERANGE")),
+                "#EXT-X-I-FRAMES-ONLY" => features.push((number, 4, "EXT-X-I-FRAMES-ONLY")),
+                "#EXT-X-MAP" => features.push((number, 5, "EXT-X-MAP")),
+                "#EXT-X-ENDLIST" => {
+                    endlist_line.get_or_insert(number);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(line) = pending_extinf {
+            report(EXTINF_WITHOUT_URI, line, "#EXTINF is not followed by a URI".to_string());
+        }
+        if let Some(line) = pending_discontinuity {
+            report(
+                EMPTY_DISCONTINUITY,
+                line,
+                "EXT-X-DISCONTINUITY is not followed by a media segment".to_string(),
+            );
+        }
+
+        match target_duration {
+            Some((_, target)) => {
+                for (line, seconds) in durations {
+                    if seconds.round() as u64 > target {
+                        report(
+                            SEGMENT_EXCEEDS_TARGET_DURATION,
+                            line,
+                            format!("segment duration {} exceeds target duration {}", seconds, target),
+                        );
+                    }
+                }
+            }
+            None if !seen.iter().any(|(tag, _)| *tag == "#EXT-X-TARGETDURATION") => {
+                report(MISSING_TARGET_DURATION, 1, "missing #EXT-X-TARGETDURATION".to_string());
+            }
+            // Present but malformed, already reported
+            None => {}
+        }
+
+        match version {
+            Some(version) => {
+                for (line, required, feature) in features {
+                    if version < required {
+                        report(
+                            VERSION_TOO_LOW,
+                            line,
+                            format!("{} requires EXT-X-VERSION {} but playlist declares {}", feature, required, version),
+                        );
+                    }
+                }
+            }
+            None if !seen.iter().any(|(tag, _)| *tag == "#EXT-X-VERSION") => {
+                report(MISSING_VERSION, 1, "missing #EXT-X-VERSION".to_string());
+            }
+            None => {}
+        }
+
+        // the numbering the groups end up with, pointed at the URI of their first segment
+        if let Ok(playlist) = MediaPlaylist::parse_ext_m3u(source) {
+            for (media_sequence, message) in discontinuity_sequence_errors(&playlist) {
+                let index = (media_sequence - playlist.media_sequence) as usize;
+                let line = segment_lines.get(index).copied().unwrap_or(1);
+                report(DISCONTINUITY_SEQUENCE_INCONSISTENT, line, message);
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        diagnostics
+    }
+}
+
+// The discontinuity groups whose sequence number doesn't follow EXT-X-DISCONTINUITY-SEQUENCE
+// and the groups before them, with the media sequence number of their first segment.
+fn discontinuity_sequence_errors(playlist: &MediaPlaylist) -> Vec<(u64, String)> {
+    let mut errors = Vec::new();
+    let mut expected = playlist.discontinuity_sequence;
+    for (index, group) in playlist.discontinuity.iter().enumerate() {
+        // a playlist may start with an EXT-X-DISCONTINUITY, which already counts
+        let leading = index == 0 && group.discontinuity_sequence == expected + 1;
+        if group.discontinuity_sequence != expected && !leading {
+            errors.push((
+                playlist.media_sequence + group.segments.start as u64,
+                format!(
+                    "discontinuity group has sequence number {}, expecting {}",
+                    group.discontinuity_sequence, expected
+                ),
+            ));
+        }
+        expected = group.discontinuity_sequence + 1;
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_ids(diagnostics: &[Diagnostic]) -> Vec<&'static str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.rule_id).collect()
+    }
+
+    #[test]
+    fn clean_playlist_has_no_diagnostics() {
+        let playlist = indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:4
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:1
+            #EXTINF:9.5,
+            #EXT-X-BYTERANGE:100@0
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:10.4,
+            b.ts
+            #EXT-X-ENDLIST
+        "};
+        assert_eq!(lint(playlist), Vec::<Diagnostic>::new());
+    }
+
+    #[test]
+    fn reports_rule_violations_with_lines() {
+        let playlist = indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXTINF:10.6,
+            #EXT-X-BYTERANGE:100@0
+            a.ts
+            #EXT-X-MEDIA-SEQUENCE:1
+            #EXT-X-VERSION:3
+            #EXT-X-ENDLIST
+            #EXTINF:4,
+            b.ts
+        "};
+        let diagnostics = lint(playlist);
+        assert_eq!(
+            rule_ids(&diagnostics),
+            vec![
+                SEGMENT_EXCEEDS_TARGET_DURATION,
+                VERSION_TOO_LOW,
+                MEDIA_SEQUENCE_AFTER_SEGMENT,
+                DUPLICATE_TAG,
+                SEGMENT_AFTER_ENDLIST,
+            ]
+        );
+        let lines: Vec<_> = diagnostics.iter().filter_map(|diagnostic| diagnostic.line).collect();
+        assert_eq!(lines, vec![4, 5, 7, 8, 11]);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn reports_structural_problems() {
+        let playlist = indoc::indoc! {"
+            #EXTINF:4,
+            #EXT-X-DISCONTINUITY
+            #EXT-X-DISCONTINUITY
+            #EXTINF:4,
+            a.ts
+            b.ts
+            #EXT-X-DISCONTINUITY-SEQUENCE:2
+        "};
+        assert_eq!(
+            rule_ids(&lint(playlist)),
+            vec![
+                MISSING_HEADER,
+                EXTINF_WITHOUT_URI,
+                MISSING_TARGET_DURATION,
+                MISSING_VERSION,
+                EMPTY_DISCONTINUITY,
+                URI_WITHOUT_EXTINF,
+                DISCONTINUITY_SEQUENCE_MISPLACED,
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_rules_are_not_reported() {
+        let playlist = "#EXTM3U\n#EXTINF:4,\na.ts\n";
+        let mut linter = Linter::new();
+        linter.disable(MISSING_VERSION).disable(MISSING_TARGET_DURATION);
+        assert!(!linter.is_enabled(MISSING_VERSION));
+        assert_eq!(linter.lint(playlist), Vec::<Diagnostic>::new());
+
+        linter.enable(MISSING_VERSION);
+        assert_eq!(rule_ids(&linter.lint(playlist)), vec![MISSING_VERSION]);
+    }
+
+    #[test]
+    fn lints_parsed_playlists() {
+        let mut playlist = MediaPlaylist::parse_ext_m3u(indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:4
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:7
+            #EXT-X-DISCONTINUITY-SEQUENCE:3
+            #EXTINF:9.5,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXT-X-MAP:URI="init.mp4"
+            #EXTINF:10.6,
+            b.mp4
+        "#})
+        .unwrap();
+        assert_eq!(rule_ids(&Linter::new().lint_playlist(&playlist)), [SEGMENT_EXCEEDS_TARGET_DURATION, VERSION_TOO_LOW]);
+
+        // e.g. built by hand with a gap in the numbering
+        playlist.discontinuity[1].discontinuity_sequence = 6;
+        let mut linter = Linter::new();
+        linter.disable(SEGMENT_EXCEEDS_TARGET_DURATION).disable(VERSION_TOO_LOW);
+        let diagnostics = linter.lint_playlist(&playlist);
+        assert_eq!(rule_ids(&diagnostics), [DISCONTINUITY_SEQUENCE_INCONSISTENT]);
+        assert_eq!((diagnostics[0].line, diagnostics[0].media_sequence), (None, Some(8)));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "segment 8:error [HLS014] discontinuity group has sequence number 6, expecting 4"
+        );
+    }
+
+    #[test]
+    fn checks_the_numbering_of_the_text() {
+        let playlist = indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXTINF:9,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXT-X-DISCONTINUITY-SEQUENCE:5
+            #EXTINF:9,
+            b.ts
+        "};
+        let diagnostics = lint(playlist);
+        let summary: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.line, diagnostic.rule_id)).collect();
+        assert_eq!(
+            summary,
+            [
+                (Some(5), DISCONTINUITY_SEQUENCE_INCONSISTENT),
+                (Some(7), DISCONTINUITY_SEQUENCE_MISPLACED),
+                (Some(9), DISCONTINUITY_SEQUENCE_INCONSISTENT),
+            ]
+        );
+    }
+}
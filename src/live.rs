@@ -0,0 +1,299 @@
+//! Tracking of live media playlists across reloads.
+//!
+//! A live playlist only shows a sliding window of the presentation. The
+//! [`LivePlaylistTracker`] merges the successive snapshots by media sequence number so the
+//! segments and discontinuity groups keep their identity from one reload to the next. See
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-6.3.4>.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::media_playlist::{DiscontinuitySegment, MediaPlaylist, MediaSegment, OwnedSegments};
+use crate::timeline::index_groups;
+
+/// A segment of a live playlist together with the sequence numbers that identify it across
+/// reloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedSegment {
+    pub media_sequence: u64,
+    pub discontinuity_sequence: u64,
+    pub segment: MediaSegment,
+}
+
+/// How a reloaded playlist relates to the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadStatus {
+    /// The first snapshot given to the tracker.
+    Initial,
+    /// The playlist changed and was merged.
+    Updated,
+    /// The playlist is identical to the previous one.
+    Unchanged,
+    /// The media or discontinuity sequence went backwards, which a server must never do
+    /// (<https://datatracker.ietf.org/doc/html/rfc8216#section-6.2.2>). The snapshot is ignored.
+    Regressed,
+}
+
+/// What changed when a snapshot was given to [`LivePlaylistTracker::update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reload {
+    pub status: ReloadStatus,
+    /// Media sequence numbers of the segments seen for the first time.
+    pub added: Vec<u64>,
+    /// Media sequence numbers of the segments that fell out of the window.
+    pub expired: Vec<u64>,
+    /// Discontinuity sequence numbers of the groups seen for the first time.
+    pub new_discontinuities: Vec<u64>,
+    /// How long to wait before the next reload: the target duration after a change, half of
+    /// it otherwise.
+    pub reload_after: Duration,
+}
+
+/// Number of segments which left the window a [`LivePlaylistTracker`] keeps by default.
+pub const DEFAULT_HISTORY: usize = 1024;
+
+/// Merges successive snapshots of a live [`MediaPlaylist`].
+///
+/// Segments which left the window are kept up to a history, see
+/// [`with_history`][Self::with_history], so a tracker following a stream for days doesn't grow
+/// without limit. A delta update only shows the segments after the ones #EXT-X-SKIP left out,
+/// which are numbered accordingly, so the skipped segments count as having left the window.
+#[derive(Debug, Clone)]
+pub struct LivePlaylistTracker {
+    // the segments seen so far, keyed by media sequence number, the oldest evicted past the
+    // history
+    segments: BTreeMap<u64, TrackedSegment>,
+    // how many segments before the window are kept
+    history: usize,
+    // media sequence numbers of the latest snapshot
+    window: Range<u64>,
+    last: Option<MediaPlaylist>,
+    unchanged_reloads: usize,
+}
+
+impl Default for LivePlaylistTracker {
+    fn default() -> Self {
+        LivePlaylistTracker {
+            segments: BTreeMap::new(),
+            history: DEFAULT_HISTORY,
+            window: 0..0,
+            last: None,
+            unchanged_reloads: 0,
+        }
+    }
+}
+
+impl LivePlaylistTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps at most `history` segments which left the window, the oldest ones being dropped.
+    /// Defaults to [`DEFAULT_HISTORY`].
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Merges a freshly loaded snapshot and reports what changed.
+    pub fn update(&mut self, playlist: MediaPlaylist) -> Reload {
+        let target_duration = playlist.target_duration;
+        let mut reload = Reload {
+            status: ReloadStatus::Updated,
+            added: Vec::new(),
+            expired: Vec::new(),
+            new_discontinuities: Vec::new(),
+            reload_after: target_duration,
+        };
+
+        if let Some(last) = &self.last {
+            let last_end = last.media_sequence + last.segments.len() as u64;
+            let end = playlist.media_sequence + playlist.segments.len() as u64;
+            if playlist.media_sequence < last.media_sequence
+                || playlist.discontinuity_sequence < last.discontinuity_sequence
+                || end < last_end
+            {
+                reload.status = ReloadStatus::Regressed;
+                reload.reload_after = target_duration / 2;
+                return reload;
+            }
+            if *last == playlist {
+                self.unchanged_reloads += 1;
+                reload.status = ReloadStatus::Unchanged;
+                reload.reload_after = target_duration / 2;
+                return reload;
+            }
+        } else {
+            reload.status = ReloadStatus::Initial;
+        }
+        self.unchanged_reloads = 0;
+
+        let window = playlist.media_sequence..playlist.media_sequence + playlist.segments.len() as u64;
+        reload.expired = (self.window.start..self.window.end.min(window.start)).collect();
+
+        // groups only ever appear at the end, so anything past the last known one is new
+        let last_group = self.segments.values().next_back().map(|tracked| tracked.discontinuity_sequence);
+        for tracked in tracked_segments(&playlist) {
+            let is_new_group = last_group.is_none_or(|last| tracked.discontinuity_sequence > last);
+            if is_new_group && !reload.new_discontinuities.contains(&tracked.discontinuity_sequence) {
+                reload.new_discontinuities.push(tracked.discontinuity_sequence);
+            }
+            if !self.segments.contains_key(&tracked.media_sequence) {
+                reload.added.push(tracked.media_sequence);
+            }
+            self.segments.insert(tracked.media_sequence, tracked);
+        }
+
+        let oldest_kept = window.start.saturating_sub(self.history as u64);
+        self.segments = self.segments.split_off(&oldest_kept);
+        self.window = window;
+        self.last = Some(playlist);
+        reload
+    }
+
+    /// The most recent snapshot, if any.
+    pub fn playlist(&self) -> Option<&MediaPlaylist> {
+        self.last.as_ref()
+    }
+
+    /// Media sequence numbers of the segments in the most recent snapshot.
+    pub fn window(&self) -> Range<u64> {
+        self.window.clone()
+    }
+
+    /// Whether the most recent snapshot contained #EXT-X-ENDLIST.
+    pub fn ended(&self) -> bool {
+        self.last.as_ref().is_some_and(|playlist| playlist.ended)
+    }
+
+    /// Number of reloads in a row which returned an identical playlist.
+    pub fn unchanged_reloads(&self) -> usize {
+        self.unchanged_reloads
+    }
+
+    /// Every segment seen so far, ordered by media sequence number, including the ones which
+    /// already left the window within the history.
+    pub fn segments(&self) -> impl Iterator<Item = &TrackedSegment> {
+        self.segments.values()
+    }
+
+    pub fn segment(&self, media_sequence: u64) -> Option<&TrackedSegment> {
+        self.segments.get(&media_sequence)
+    }
+
+    /// Every segment seen so far grouped by discontinuity sequence number, the segment ranges
+    /// indexing [`segments`][Self::segments]. A group keeps the segments which already left the
+    /// window within the history, so it stays the same across reloads, and its start time is
+    /// measured from the oldest segment kept.
+    pub fn discontinuity(&self) -> Vec<DiscontinuitySegment> {
+        let mut groups = OwnedSegments::default();
+        for tracked in self.segments.values() {
+            groups.push_sequenced(tracked.segment.clone(), tracked.discontinuity_sequence);
+        }
+        // times are measured from the oldest segment the tracker has seen
+        let first = self.segments.keys().next().copied().unwrap_or_default();
+        index_groups(&mut groups.discontinuity, &groups.segments, first);
+        groups.discontinuity
+    }
+}
+
+// Pairs every segment of the playlist with its media and discontinuity sequence numbers.
+pub(crate) fn tracked_segments(playlist: &MediaPlaylist) -> impl Iterator<Item = TrackedSegment> + '_ {
+    playlist
+        .discontinuity_groups()
+        .flat_map(|group| {
+            group
+                .discontinuity_segments()
+                .iter()
+                .map(move |segment| (group.discontinuity_sequence, segment))
+        })
+        .zip(playlist.media_sequence..)
+        .map(|((discontinuity_sequence, segment), media_sequence)| TrackedSegment {
+            media_sequence,
+            discontinuity_sequence,
+            segment: segment.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(media_sequence: u64, discontinuity_sequence: u64, body: &str) -> MediaPlaylist {
+        let playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXT-X-DISCONTINUITY-SEQUENCE:{}\n{}",
+            media_sequence, discontinuity_sequence, body
+        );
+        MediaPlaylist::parse_ext_m3u(&playlist).expect("snapshot should parse")
+    }
+
+    #[test]
+    fn merges_sliding_window() {
+        let mut tracker = LivePlaylistTracker::new();
+
+        let first = tracker.update(snapshot(10, 0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:4,\nc.ts\n"));
+        assert_eq!(first.status, ReloadStatus::Initial);
+        assert_eq!(first.added, vec![10, 11, 12]);
+        assert_eq!(first.new_discontinuities, vec![0, 1]);
+
+        // a.ts and b.ts slide out, which takes the first discontinuity with them
+        let second = tracker.update(snapshot(12, 1, "#EXTINF:4,\nc.ts\n#EXTINF:4,\nd.ts\n"));
+        assert_eq!(second.status, ReloadStatus::Updated);
+        assert_eq!(second.added, vec![13]);
+        assert_eq!(second.expired, vec![10, 11]);
+        assert_eq!(second.new_discontinuities, Vec::<u64>::new());
+        assert_eq!(second.reload_after, Duration::from_secs(4));
+        assert_eq!(tracker.window(), 12..14);
+
+        let groups = tracker.discontinuity();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].discontinuity_sequence, 1);
+        assert_eq!(groups[1].segment_range().len(), 2);
+        assert_eq!(groups[1].discontinuity_duration, Duration::from_secs(8));
+        assert_eq!(groups[1].start_time, Duration::from_secs(8));
+        assert_eq!(groups[1].first_media_sequence(), 12);
+        assert_eq!(tracker.segment(13).unwrap().segment.url, "d.ts");
+    }
+
+    #[test]
+    fn detects_unchanged_and_regressed_playlists() {
+        let mut tracker = LivePlaylistTracker::new();
+        tracker.update(snapshot(5, 0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n"));
+
+        let unchanged = tracker.update(snapshot(5, 0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n"));
+        assert_eq!(unchanged.status, ReloadStatus::Unchanged);
+        assert_eq!(unchanged.reload_after, Duration::from_secs(2));
+        assert_eq!(tracker.unchanged_reloads(), 1);
+
+        let regressed = tracker.update(snapshot(4, 0, "#EXTINF:4,\nz.ts\n"));
+        assert_eq!(regressed.status, ReloadStatus::Regressed);
+        assert_eq!(tracker.window(), 5..7);
+        assert!(tracker.segment(4).is_none());
+    }
+
+    #[test]
+    fn numbers_delta_updates() {
+        let mut tracker = LivePlaylistTracker::new();
+        tracker.update(snapshot(10, 0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n#EXTINF:4,\nc.ts\n"));
+        let reload = tracker.update(snapshot(10, 0, "#EXT-X-SKIP:SKIPPED-SEGMENTS=2\n#EXTINF:4,\nc.ts\n#EXTINF:4,\nd.ts\n"));
+        assert_eq!(reload.added, [13]);
+        assert_eq!(tracker.segment(12).unwrap().segment.url, "c.ts");
+        assert_eq!(tracker.segment(13).unwrap().segment.url, "d.ts");
+        assert_eq!(tracker.segment(10).unwrap().segment.url, "a.ts");
+    }
+
+    #[test]
+    fn drops_segments_past_the_history() {
+        let mut tracker = LivePlaylistTracker::new().with_history(1);
+        tracker.update(snapshot(0, 0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n"));
+        tracker.update(snapshot(2, 0, "#EXTINF:4,\nc.ts\n#EXTINF:4,\nd.ts\n"));
+        let kept: Vec<_> = tracker.segments().map(|tracked| tracked.media_sequence).collect();
+        assert_eq!(kept, [1, 2, 3]);
+
+        // the sequence jumping far ahead after an encoder restart leaves only the window
+        let reload = tracker.update(snapshot(1_000_000_000_000, 1, "#EXTINF:4,\nz.ts\n"));
+        assert_eq!(reload.added, [1_000_000_000_000]);
+        assert_eq!(tracker.segments().count(), 1);
+    }
+}
@@ -0,0 +1,136 @@
+//! Seek lookups: which segment plays at a given presentation time.
+
+use core::time::Duration;
+
+use crate::media_playlist::{DiscontinuitySegment, MediaPlaylist, MediaSegment};
+
+/// Cumulative segment times, built once when the playlist is created so lookups are a binary
+/// search instead of a walk over every segment.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct TimeIndex {
+    // end time of every segment, measured from the start of the playlist
+    ends: Vec<Duration>,
+    // index of the first segment of every discontinuity group
+    group_starts: Vec<usize>,
+}
+
+impl TimeIndex {
+    pub(crate) fn new(segments: &[MediaSegment], discontinuity: &[DiscontinuitySegment]) -> Self {
+        let ends = segments
+            .iter()
+            .scan(Duration::ZERO, |time, segment| {
+                *time += segment.duration;
+                Some(*time)
+            })
+            .collect();
+        let group_starts = discontinuity.iter().map(|group| group.segments.start).collect();
+        TimeIndex { ends, group_starts }
+    }
+
+    /// Total duration of the playlist.
+    pub(crate) fn duration(&self) -> Duration {
+        self.ends.last().copied().unwrap_or_default()
+    }
+
+    /// Start time of the segment at `index`.
+    pub(crate) fn start_of(&self, index: usize) -> Duration {
+        index.checked_sub(1).map_or(Duration::ZERO, |previous| self.ends[previous])
+    }
+
+    // Index of the segment playing at `time`.
+    fn segment_at(&self, time: Duration) -> Option<usize> {
+        let index = self.ends.partition_point(|end| *end <= time);
+        (index < self.ends.len()).then_some(index)
+    }
+
+    // Index of the discontinuity group containing the segment at `index`.
+    fn group_of(&self, index: usize) -> usize {
+        self.group_starts.partition_point(|start| *start <= index) - 1
+    }
+}
+
+/// Where a presentation time falls in a playlist, see [`MediaPlaylist::locate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPosition {
+    /// Index of the segment in [`MediaPlaylist::segments`].
+    pub index: usize,
+    pub media_sequence: u64,
+    /// Index of the group in [`MediaPlaylist::discontinuity`] containing the segment.
+    pub discontinuity_index: usize,
+    /// Time from the start of the segment.
+    pub offset: Duration,
+}
+
+impl MediaPlaylist {
+    /// Finds the segment playing at `time`, measured from the start of the playlist. Returns
+    /// `None` past the end of the last segment. Runs in O(log n).
+    pub fn locate(&self, time: Duration) -> Option<SegmentPosition> {
+        let index = self.time_index.segment_at(time)?;
+        Some(SegmentPosition {
+            index,
+            media_sequence: self.media_sequence + index as u64,
+            discontinuity_index: self.time_index.group_of(index),
+            offset: time - self.time_index.start_of(index),
+        })
+    }
+
+    /// Sum of the durations of every segment.
+    pub fn duration(&self) -> Duration {
+        self.time_index.duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_segments() {
+        let playlist = MediaPlaylist::parse_ext_m3u(indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:5
+            #EXTINF:10,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:4,
+            b.ts
+            #EXTINF:6,
+            c.ts
+        "})
+        .unwrap();
+
+        assert_eq!(playlist.duration(), Duration::from_secs(20));
+        assert_eq!(
+            playlist.locate(Duration::from_secs_f32(12.5)),
+            Some(SegmentPosition {
+                index: 1,
+                media_sequence: 6,
+                discontinuity_index: 1,
+                offset: Duration::from_secs_f32(12.5) - Duration::from_secs(10),
+            })
+        );
+        // segments own their start time, not their end time
+        assert_eq!(playlist.locate(Duration::from_secs(14)).unwrap().index, 2);
+        assert_eq!(playlist.locate(Duration::ZERO).unwrap().discontinuity_index, 0);
+        assert_eq!(playlist.locate(Duration::from_secs(20)), None);
+    }
+
+    #[test]
+    fn locates_in_large_playlists() {
+        let mut source = String::from("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:2\n");
+        for index in 0..100_000 {
+            if index % 1000 == 0 && index > 0 {
+                source.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            source.push_str(&format!("#EXTINF:2,\nsegment_{}.ts\n", index));
+        }
+        let playlist = MediaPlaylist::parse_ext_m3u(&source).unwrap();
+
+        let position = playlist.locate(Duration::from_secs(123_457)).unwrap();
+        assert_eq!(position.index, 61_728);
+        assert_eq!(position.discontinuity_index, 61);
+        assert_eq!(position.offset, Duration::from_secs(1));
+    }
+}
@@ -1,5 +1,829 @@
+//! `hls`: command-line front end of the playlist parser.
+//!
+//! ```text
+//! hls <command> [options] [FILE]
+//! ```
+//!
+//! Reads the playlist from FILE, or from stdin when FILE is missing or `-`. Every command
+//! accepts `--format json` for machine-readable output. Exit codes are listed in [`exit`].
+//!
+//! `hls serve [--port N] [--config FILE] [DIR]` serves DIR (default: the current directory) over
+//! HTTP on localhost until interrupted, see `disney_hls_parser::serve` for the config file.
 
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
 
-fn main() {
-    println!("Hello, world!");
+use anyhow::{anyhow, Context, Result};
+use disney_hls_parser::lint::{Linter, Severity};
+use disney_hls_parser::serve::{ServeConfig, Server};
+use disney_hls_parser::{ByteRange, DateRange, MasterPlaylist, MediaPlaylist, MediaSegment, PlaylistType};
+
+const USAGE: &str = "\
+Usage: hls <command> [options] [FILE]
+
+Commands:
+  inspect          summary of a media or master playlist
+  segments         list of the media segments
+  discontinuities  segments grouped by EXT-X-DISCONTINUITY
+  lint             check the playlist against RFC 8216
+  convert          write the playlist back as normalized ext-m3u (or JSON)
+  serve            serve the directory FILE (default: .) over HTTP on localhost
+
+Options:
+  --format <text|json>  output format (default: text)
+  --base <URL>          location of the playlist, to resolve relative URIs
+  --disable <RULES>     comma separated lint rules to skip, e.g. HLS003,HLS012
+  -o, --output <FILE>   write to FILE instead of stdout
+  --port <PORT>         port for serve (default: 8000)
+  --config <FILE>       live streams and faults for serve
+  -h, --help            print this help
+
+Reads FILE, or stdin when FILE is missing or '-'.";
+
+/// Exit codes of the tool.
+mod exit {
+    /// Success, and for `lint` no error-level diagnostic.
+    pub const OK: u8 = 0;
+    /// `lint` found at least one error-level diagnostic.
+    pub const LINT_ERRORS: u8 = 1;
+    /// Invalid command line.
+    pub const USAGE: u8 = 2;
+    /// The input couldn't be read or isn't a valid playlist.
+    pub const INVALID_INPUT: u8 = 3;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Inspect,
+    Segments,
+    Discontinuities,
+    Lint,
+    Convert,
+    Serve,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Options {
+    command: Command,
+    format: Format,
+    base: Option<String>,
+    disabled_rules: Vec<String>,
+    output: Option<String>,
+    port: u16,
+    config: Option<String>,
+    input: Option<String>,
+}
+
+// Command line errors, reported with the usage text and `exit::USAGE`.
+#[derive(Debug)]
+struct UsageError(String);
+
+fn parse_args(args: &[String]) -> Result<Options, UsageError> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("inspect") => Command::Inspect,
+        Some("segments") => Command::Segments,
+        Some("discontinuities") => Command::Discontinuities,
+        Some("lint") => Command::Lint,
+        Some("convert") => Command::Convert,
+        Some("serve") => Command::Serve,
+        Some(other) => return Err(UsageError(format!("unknown command '{}'", other))),
+        None => return Err(UsageError("missing command".to_string())),
+    };
+
+    let mut options = Options {
+        command,
+        format: Format::Text,
+        base: None,
+        disabled_rules: Vec::new(),
+        output: None,
+        port: 8000,
+        config: None,
+        input: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| UsageError(format!("{} expects a value", name)))
+        };
+        match arg.as_str() {
+            "--format" => {
+                options.format = match value("--format")?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => return Err(UsageError(format!("unknown format '{}'", other))),
+                }
+            }
+            "--base" => options.base = Some(value("--base")?),
+            "--disable" => options
+                .disabled_rules
+                .extend(value("--disable")?.split(',').map(|rule| rule.trim().to_string())),
+            "-o" | "--output" => options.output = Some(value("--output")?),
+            "--port" => {
+                let port = value("--port")?;
+                options.port = port.parse().map_err(|_| UsageError(format!("invalid port '{}'", port)))?;
+            }
+            "--config" => options.config = Some(value("--config")?),
+            "-" => options.input = None,
+            flag if flag.starts_with('-') => return Err(UsageError(format!("unknown option '{}'", flag))),
+            path if options.input.is_none() => options.input = Some(path.to_string()),
+            extra => return Err(UsageError(format!("unexpected argument '{}'", extra))),
+        }
+    }
+    Ok(options)
+}
+
+// Reads the raw bytes, decoding is left to the parsers.
+fn read_input(input: Option<&str>) -> Result<Vec<u8>> {
+    match input {
+        Some(path) => std::fs::read(path).with_context(|| format!("reading {}", path)),
+        None => {
+            let mut source = Vec::new();
+            io::stdin().read_to_end(&mut source).context("reading stdin")?;
+            Ok(source)
+        }
+    }
+}
+
+// Decompresses gzip input (e.g. from a disk cache) once, so the linter and the master playlist
+// parser see the same text as the media playlist reader.
+fn decompress(source: Vec<u8>) -> Result<Vec<u8>> {
+    if !source.starts_with(&[0x1f, 0x8b]) {
+        return Ok(source);
+    }
+    #[cfg(feature = "gzip")]
+    {
+        let mut text = Vec::new();
+        flate2::read::MultiGzDecoder::new(source.as_slice())
+            .read_to_end(&mut text)
+            .context("decompressing the playlist")?;
+        Ok(text)
+    }
+    #[cfg(not(feature = "gzip"))]
+    Err(anyhow!("The playlist is gzip-compressed, which needs the `gzip` feature"))
+}
+
+// Goes through the reader, which handles a byte order mark and CRLF.
+fn parse_media(source: &[u8], options: &Options) -> Result<MediaPlaylist> {
+    let mut playlist = MediaPlaylist::from_reader(source)?;
+    if let Some(base) = &options.base {
+        playlist.resolve_uris(base);
+    }
+    Ok(playlist)
+}
+
+// The input as text for the master playlist parser and the linter, without a byte order mark.
+fn text(source: &[u8]) -> Result<&str> {
+    let source = source.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(source);
+    std::str::from_utf8(source).context("the playlist is not UTF-8")
+}
+
+fn is_master(source: &[u8]) -> bool {
+    text(source).is_ok_and(|text| text.lines().any(|line| line.starts_with("#EXT-X-STREAM-INF:")))
+}
+
+fn seconds(duration: std::time::Duration) -> f64 {
+    // millisecond precision, like the EXTINF durations in the playlist
+    (duration.as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+// Serves the input directory until the process is stopped.
+fn serve(options: &Options) -> Result<()> {
+    let root = options.input.as_deref().unwrap_or(".");
+    let config = match &options.config {
+        Some(path) => ServeConfig::from_path(path)?,
+        None => ServeConfig::default(),
+    };
+    let server = Server::with_config(root, config)?.bind(("127.0.0.1", options.port))?;
+    eprintln!("hls: serving {} on {}", root, server.url());
+    server.wait();
+    Ok(())
+}
+
+// Runs the command, returning what to print and the exit code.
+fn run(options: &Options, source: &[u8]) -> Result<(String, u8)> {
+    let json = options.format == Format::Json;
+    let mut out = String::new();
+
+    match options.command {
+        Command::Inspect if is_master(source) => {
+            let master = match &options.base {
+                Some(base) => MasterPlaylist::parse_ext_m3u_with_base(text(source)?, base)?,
+                None => MasterPlaylist::parse_ext_m3u(text(source)?)?,
+            };
+            if json {
+                out = Json::object([
+                    ("type", Json::from("master")),
+                    ("version", Json::from(master.version())),
+                    ("variants", Json::from(master.variants().len())),
+                    ("renditions", Json::from(master.renditions().len())),
+                ])
+                .to_string();
+            } else {
+                out.push_str(&format!("type:        master\nversion:     {}\n", master.version()));
+                out.push_str(&format!("variants:    {}\n", master.variants().len()));
+                for variant in master.variants() {
+                    out.push_str(&format!("  {:>10} bps  {}\n", variant.bandwidth, variant.uri));
+                }
+                out.push_str(&format!("renditions:  {}\n", master.renditions().len()));
+            }
+        }
+        Command::Inspect => {
+            let playlist = parse_media(source, options)?;
+            if json {
+                out = Json::object([
+                    ("type", Json::from("media")),
+                    ("version", Json::from(playlist.version())),
+                    ("target_duration", Json::from(playlist.target_duration().as_secs())),
+                    ("media_sequence", Json::from(playlist.media_sequence())),
+                    ("discontinuity_sequence", Json::from(playlist.discontinuity_sequence())),
+                    ("ended", Json::from(playlist.ended())),
+                    ("segments", Json::from(playlist.segments().len())),
+                    ("discontinuities", Json::from(playlist.discontinuity().len())),
+                    ("duration", Json::from(seconds(playlist.duration()))),
+                ])
+                .to_string();
+            } else {
+                out.push_str("type:                    media\n");
+                out.push_str(&format!("version:                 {}\n", playlist.version()));
+                out.push_str(&format!("target duration:         {}s\n", playlist.target_duration().as_secs()));
+                out.push_str(&format!("media sequence:          {}\n", playlist.media_sequence()));
+                out.push_str(&format!("discontinuity sequence:  {}\n", playlist.discontinuity_sequence()));
+                out.push_str(&format!("ended:                   {}\n", playlist.ended()));
+                out.push_str(&format!("segments:                {}\n", playlist.segments().len()));
+                out.push_str(&format!("discontinuity groups:    {}\n", playlist.discontinuity().len()));
+                out.push_str(&format!("duration:                {:.3}s\n", seconds(playlist.duration())));
+            }
+        }
+        Command::Segments => {
+            let playlist = parse_media(source, options)?;
+            let mut rows = Vec::new();
+            for (group_index, group) in playlist.discontinuity_groups().enumerate() {
+                let mut start = group.start_time();
+                for (offset, segment) in group.discontinuity_segments().iter().enumerate() {
+                    let uri = segment.resolved_uri().unwrap_or(segment.url());
+                    rows.push((
+                        group.first_media_sequence() + offset as u64,
+                        group_index,
+                        start,
+                        segment.duration(),
+                        uri.to_string(),
+                    ));
+                    start += segment.duration();
+                }
+            }
+            if json {
+                out = Json::Array(
+                    rows.into_iter()
+                        .map(|(media_sequence, group, start, duration, uri)| {
+                            Json::object([
+                                ("media_sequence", Json::from(media_sequence)),
+                                ("discontinuity", Json::from(group)),
+                                ("start", Json::from(seconds(start))),
+                                ("duration", Json::from(seconds(duration))),
+                                ("uri", Json::from(uri)),
+                            ])
+                        })
+                        .collect(),
+                )
+                .to_string();
+            } else {
+                out.push_str("     MSN  DISC      START   DURATION  URI\n");
+                for (media_sequence, group, start, duration, uri) in rows {
+                    out.push_str(&format!(
+                        "{:>8}  {:>4}  {:>9.3}  {:>9.3}  {}\n",
+                        media_sequence,
+                        group,
+                        seconds(start),
+                        seconds(duration),
+                        uri
+                    ));
+                }
+            }
+        }
+        Command::Discontinuities => {
+            let playlist = parse_media(source, options)?;
+            if json {
+                out = Json::Array(
+                    playlist
+                        .discontinuity()
+                        .iter()
+                        .map(|group| {
+                            Json::object([
+                                ("discontinuity_sequence", Json::from(group.discontinuity_sequence())),
+                                ("first_media_sequence", Json::from(group.first_media_sequence())),
+                                ("last_media_sequence", Json::from(group.last_media_sequence())),
+                                ("start", Json::from(seconds(group.start_time()))),
+                                ("end", Json::from(seconds(group.end_time()))),
+                                ("duration", Json::from(seconds(group.discontinuity_duration()))),
+                                ("segments", Json::from(group.segment_range().len())),
+                                (
+                                    "program_date_time",
+                                    group
+                                        .program_date_time()
+                                        .map_or(Json::Null, |date| Json::from(date.to_rfc3339())),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                )
+                .to_string();
+            } else {
+                out.push_str("DISC SEQ      MSN RANGE      START        END   DURATION  SEGMENTS\n");
+                for group in playlist.discontinuity() {
+                    out.push_str(&format!(
+                        "{:>8}  {:>15}  {:>9.3}  {:>9.3}  {:>9.3}  {:>8}\n",
+                        group.discontinuity_sequence(),
+                        format!("{}-{}", group.first_media_sequence(), group.last_media_sequence()),
+                        seconds(group.start_time()),
+                        seconds(group.end_time()),
+                        seconds(group.discontinuity_duration()),
+                        group.segment_range().len()
+                    ));
+                }
+            }
+        }
+        Command::Lint => {
+            let mut linter = Linter::new();
+            for rule in &options.disabled_rules {
+                linter.disable(rule);
+            }
+            let diagnostics = linter.lint(text(source)?);
+            let has_errors = diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error);
+            if json {
+                out = Json::Array(
+                    diagnostics
+                        .iter()
+                        .map(|diagnostic| {
+                            Json::object([
+                                ("rule_id", Json::from(diagnostic.rule_id)),
+                                ("severity", Json::from(diagnostic.severity.to_string())),
+                                ("line", diagnostic.line.map_or(Json::Null, Json::from)),
+                                ("message", Json::from(diagnostic.message.as_str())),
+                            ])
+                        })
+                        .collect(),
+                )
+                .to_string();
+            } else {
+                for diagnostic in &diagnostics {
+                    out.push_str(&format!("{}\n", diagnostic));
+                }
+            }
+            return Ok((out, if has_errors { exit::LINT_ERRORS } else { exit::OK }));
+        }
+        // handled by `serve`, there is no playlist to read
+        Command::Serve => return Err(anyhow!("serve does not take a playlist")),
+        Command::Convert => {
+            let playlist = parse_media(source, options)?;
+            if json {
+                out = playlist_json(&playlist).to_string();
+            } else {
+                out = playlist.to_string();
+            }
+        }
+    }
+
+    if json {
+        out.push('\n');
+    }
+    Ok((out, exit::OK))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::from(exit::OK);
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(UsageError(message)) => {
+            eprintln!("hls: {}\n\n{}", message, USAGE);
+            return ExitCode::from(exit::USAGE);
+        }
+    };
+
+    if options.command == Command::Serve {
+        return match serve(&options) {
+            Ok(()) => ExitCode::from(exit::OK),
+            Err(err) => {
+                eprintln!("hls: {:#}", err);
+                ExitCode::from(exit::INVALID_INPUT)
+            }
+        };
+    }
+
+    let result = read_input(options.input.as_deref())
+        .and_then(decompress)
+        .and_then(|source| run(&options, &source));
+    let (out, code) = match result {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("hls: {:#}", err);
+            return ExitCode::from(exit::INVALID_INPUT);
+        }
+    };
+
+    let written = match &options.output {
+        Some(path) => std::fs::write(path, &out).map_err(|err| anyhow!("writing {}: {}", path, err)),
+        None => io::stdout().write_all(out.as_bytes()).map_err(|err| anyhow!("writing stdout: {}", err)),
+    };
+    if let Err(err) = written {
+        eprintln!("hls: {}", err);
+        return ExitCode::from(exit::INVALID_INPUT);
+    }
+    ExitCode::from(code)
+}
+
+// Same shape as the serde serialization of the library, see `disney_hls_parser::serialization`.
+fn playlist_json(playlist: &MediaPlaylist) -> Json {
+    let mut fields = vec![
+        ("version", Json::from(playlist.version())),
+        ("target_duration", duration_json(playlist.target_duration())),
+        ("media_sequence", Json::from(playlist.media_sequence())),
+        ("discontinuity_sequence", Json::from(playlist.discontinuity_sequence())),
+        ("ended", Json::from(playlist.ended())),
+    ];
+    if let Some(start) = playlist.start() {
+        fields.push((
+            "start",
+            Json::object([("time_offset", Json::from(start.time_offset)), ("precise", Json::from(start.precise))]),
+        ));
+    }
+    if let Some(control) = playlist.server_control() {
+        let mut control_fields = Vec::new();
+        if let Some(can_skip_until) = control.can_skip_until {
+            control_fields.push(("can_skip_until", duration_json(can_skip_until)));
+        }
+        control_fields.push(("can_skip_dateranges", Json::from(control.can_skip_dateranges)));
+        if let Some(hold_back) = control.hold_back {
+            control_fields.push(("hold_back", duration_json(hold_back)));
+        }
+        if let Some(part_hold_back) = control.part_hold_back {
+            control_fields.push(("part_hold_back", duration_json(part_hold_back)));
+        }
+        control_fields.push(("can_block_reload", Json::from(control.can_block_reload)));
+        fields.push(("server_control", Json::Object(control_fields)));
+    }
+    if let Some(part_target) = playlist.part_target() {
+        fields.push(("part_target", duration_json(part_target)));
+    }
+    if playlist.pending_parts() > 0 {
+        fields.push(("pending_parts", Json::from(playlist.pending_parts())));
+    }
+    if let Some(playlist_type) = playlist.playlist_type() {
+        let name = match playlist_type {
+            PlaylistType::Event => "EVENT",
+            PlaylistType::Vod => "VOD",
+        };
+        fields.push(("playlist_type", Json::from(name)));
+    }
+    if !playlist.date_ranges().is_empty() {
+        fields.push(("date_ranges", Json::Array(playlist.date_ranges().iter().map(date_range_json).collect())));
+    }
+    let groups = playlist.discontinuity_groups().map(|group| {
+        let timeline = group.group();
+        let mut group_fields = vec![
+            ("discontinuity_sequence", Json::from(timeline.discontinuity_sequence())),
+            ("media_sequence", Json::from(timeline.first_media_sequence())),
+            ("start_time", duration_json(timeline.start_time())),
+            ("end_time", duration_json(timeline.end_time())),
+            ("duration", duration_json(timeline.discontinuity_duration())),
+        ];
+        if let Some(program_date_time) = timeline.program_date_time() {
+            group_fields.push(("program_date_time", date_json(program_date_time)));
+        }
+        group_fields.push(("segments", Json::Array(group.discontinuity_segments().iter().map(segment_json).collect())));
+        Json::Object(group_fields)
+    });
+    fields.push(("discontinuities", Json::Array(groups.collect())));
+    Json::Object(fields)
+}
+
+fn segment_json(segment: &MediaSegment) -> Json {
+    let mut fields = vec![("duration", duration_json(segment.duration())), ("url", Json::from(segment.url()))];
+    if let Some(program_date_time) = segment.program_date_time() {
+        fields.push(("program_date_time", date_json(program_date_time)));
+    }
+    if let Some(key) = segment.key() {
+        let mut key_fields = vec![("method", Json::from(key.method.as_str()))];
+        let optional = [
+            ("uri", &key.uri),
+            ("iv", &key.iv),
+            ("key_format", &key.key_format),
+            ("key_format_versions", &key.key_format_versions),
+            ("resolved_uri", &key.resolved_uri),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                key_fields.push((name, Json::from(value.as_str())));
+            }
+        }
+        fields.push(("key", Json::Object(key_fields)));
+    }
+    if let Some(map) = segment.map() {
+        let mut map_fields = vec![("uri", Json::from(map.uri.as_str()))];
+        if let Some(byte_range) = map.byte_range {
+            map_fields.push(("byte_range", byte_range_json(byte_range)));
+        }
+        if let Some(resolved_uri) = &map.resolved_uri {
+            map_fields.push(("resolved_uri", Json::from(resolved_uri.as_str())));
+        }
+        fields.push(("map", Json::Object(map_fields)));
+    }
+    if let Some(byte_range) = segment.byte_range() {
+        fields.push(("byte_range", byte_range_json(byte_range)));
+    }
+    if segment.gap() {
+        fields.push(("gap", Json::from(true)));
+    }
+    if let Some(resolved_url) = segment.resolved_uri() {
+        fields.push(("resolved_url", Json::from(resolved_url)));
+    }
+    Json::Object(fields)
+}
+
+fn byte_range_json(byte_range: ByteRange) -> Json {
+    let mut fields = vec![("length", Json::from(byte_range.length))];
+    if let Some(offset) = byte_range.offset {
+        fields.push(("offset", Json::from(offset)));
+    }
+    Json::Object(fields)
+}
+
+fn date_range_json(date_range: &DateRange) -> Json {
+    let mut fields = vec![("id", Json::from(date_range.id.as_str()))];
+    if let Some(class) = &date_range.class {
+        fields.push(("class", Json::from(class.as_str())));
+    }
+    fields.push(("start_date", date_json(date_range.start_date)));
+    if let Some(end_date) = date_range.end_date {
+        fields.push(("end_date", date_json(end_date)));
+    }
+    if let Some(duration) = date_range.duration {
+        fields.push(("duration", duration_json(duration)));
+    }
+    if let Some(planned_duration) = date_range.planned_duration {
+        fields.push(("planned_duration", duration_json(planned_duration)));
+    }
+    fields.push(("end_on_next", Json::from(date_range.end_on_next)));
+    if !date_range.attributes.is_empty() {
+        let attributes = date_range.attributes.iter().map(|(name, value)| {
+            Json::Array(vec![Json::from(name.as_str()), Json::from(value.as_str())])
+        });
+        fields.push(("attributes", Json::Array(attributes.collect())));
+    }
+    Json::Object(fields)
+}
+
+// RFC 3339 with `Z` for UTC, like chrono's serialization.
+fn date_json(date: chrono::DateTime<chrono::FixedOffset>) -> Json {
+    Json::from(date.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+}
+
+// Whole seconds as an integer, like the serde serialization.
+fn duration_json(duration: std::time::Duration) -> Json {
+    if duration.subsec_nanos() == 0 {
+        Json::from(duration.as_secs())
+    } else {
+        Json::from(duration.as_secs_f64())
+    }
+}
+
+// Just enough JSON to print the command results without pulling in a serializer.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    // counts and sequence numbers, printed exactly rather than through f64
+    Integer(u64),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    fn object<const N: usize>(fields: [(&'static str, Json); N]) -> Json {
+        Json::Object(fields.into())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Integer(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Integer(value as u64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Integer(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => {
+                f.write_str("\"")?;
+                for c in value.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if u32::from(c) < 0x20 => write!(f, "\\u{:04x}", u32::from(c))?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}:{}", Json::from(*name), value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    const PLAYLIST: &str = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:10\n#EXTINF:9.5,\na.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:4,\nb.ts\n#EXT-X-ENDLIST\n";
+
+    #[test]
+    fn parses_command_lines() {
+        let options = parse_args(&args("lint --format json --disable HLS003,HLS012 play.m3u8")).unwrap();
+        assert_eq!(options.command, Command::Lint);
+        assert_eq!(options.format, Format::Json);
+        assert_eq!(options.disabled_rules, vec!["HLS003", "HLS012"]);
+        assert_eq!(options.input.as_deref(), Some("play.m3u8"));
+
+        let options = parse_args(&args("serve --port 9000 --config faults.conf media")).unwrap();
+        assert_eq!((options.command, options.port), (Command::Serve, 9000));
+        assert_eq!((options.config.as_deref(), options.input.as_deref()), (Some("faults.conf"), Some("media")));
+
+        assert!(parse_args(&args("explode")).is_err());
+        assert!(parse_args(&args("serve --port http")).is_err());
+        assert!(parse_args(&args("inspect --format xml")).is_err());
+        assert!(parse_args(&args("inspect a b")).is_err());
+    }
+
+    #[test]
+    fn prints_discontinuities_as_json() {
+        let options = parse_args(&args("discontinuities --format json")).unwrap();
+        let (out, code) = run(&options, PLAYLIST.as_bytes()).unwrap();
+        assert_eq!(code, exit::OK);
+        assert_eq!(
+            out,
+            concat!(
+                r#"[{"discontinuity_sequence":0,"first_media_sequence":0,"last_media_sequence":0,"start":0,"end":9.5,"duration":9.5,"segments":1,"program_date_time":null},"#,
+                r#"{"discontinuity_sequence":1,"first_media_sequence":1,"last_media_sequence":1,"start":9.5,"end":13.5,"duration":4,"segments":1,"program_date_time":null}]"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn lint_exit_code_reflects_errors() {
+        let options = parse_args(&args("lint")).unwrap();
+        assert_eq!(run(&options, PLAYLIST.as_bytes()).unwrap().1, exit::OK);
+
+        let (out, code) = run(&options, b"#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\na.ts\n").unwrap();
+        assert_eq!(code, exit::LINT_ERRORS);
+        assert!(out.contains("[HLS002]"));
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(Json::from("a\"b\\c\n\u{1}").to_string(), r#""a\"b\\c\n\u0001""#);
+    }
+
+    #[test]
+    fn prints_integers_exactly() {
+        assert_eq!(Json::from(u64::MAX).to_string(), "18446744073709551615");
+        assert_eq!(Json::from(9.5).to_string(), "9.5");
+    }
+
+    const DETAILED: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:7
+        #EXT-X-TARGETDURATION:10
+        #EXT-X-MEDIA-SEQUENCE:8
+        #EXT-X-PLAYLIST-TYPE:EVENT
+        #EXT-X-DATERANGE:ID="ad1",START-DATE="2020-01-01T12:00:00Z",DURATION=30,X-AD-ID="42"
+        #EXT-X-KEY:METHOD=AES-128,URI="key.bin",IV=0x1F
+        #EXT-X-MAP:URI="init.mp4",BYTERANGE="720@0"
+        #EXT-X-PROGRAM-DATE-TIME:2020-01-01T12:00:00.000+00:00
+        #EXTINF:9.5,
+        #EXT-X-BYTERANGE:1024@4096
+        a.mp4
+        #EXT-X-DISCONTINUITY
+        #EXT-X-GAP
+        #EXTINF:4,
+        b.mp4
+    "#};
+
+    #[test]
+    fn converts_to_the_serialized_shape() {
+        let options = parse_args(&args("convert --format json --base https://cdn.example/live/")).unwrap();
+        let (out, _) = run(&options, DETAILED.as_bytes()).unwrap();
+        assert!(out.contains(r#""discontinuities":[{"discontinuity_sequence":0,"media_sequence":8,"start_time":0,"#));
+        assert!(out.contains(r#""key":{"method":"AES-128","uri":"key.bin","iv":"0x1F","#));
+        assert!(out.contains(r#""map":{"uri":"init.mp4","byte_range":{"length":720,"offset":0},"#));
+        assert!(out.contains(r#""byte_range":{"length":1024,"offset":4096}"#));
+        assert!(out.contains(r#""gap":true,"resolved_url":"https://cdn.example/live/b.mp4"}"#));
+        assert!(out.contains(r#""attributes":[["X-AD-ID","\"42\""]]"#));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn converts_like_serde() {
+        let options = parse_args(&args("convert --format json --base https://cdn.example/live/")).unwrap();
+        let (out, _) = run(&options, DETAILED.as_bytes()).unwrap();
+        let mut playlist = MediaPlaylist::parse_ext_m3u(DETAILED).unwrap();
+        playlist.resolve_uris("https://cdn.example/live/");
+        let converted: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(converted, serde_json::to_value(&playlist).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decompresses_once() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(PLAYLIST.as_bytes()).unwrap();
+        let source = decompress(encoder.finish().unwrap()).unwrap();
+        assert_eq!(source, PLAYLIST.as_bytes());
+        assert_eq!(run(&parse_args(&args("lint")).unwrap(), &source).unwrap().1, exit::OK);
+    }
+
+    #[test]
+    fn reads_bom_and_crlf() {
+        let options = parse_args(&args("convert")).unwrap();
+        let source = format!("\u{FEFF}{}", PLAYLIST.replace('\n', "\r\n"));
+        assert_eq!(run(&options, source.as_bytes()).unwrap(), run(&options, PLAYLIST.as_bytes()).unwrap());
+
+        let options = parse_args(&args("lint")).unwrap();
+        assert_eq!(run(&options, source.as_bytes()).unwrap().1, exit::OK);
+    }
 }
@@ -0,0 +1,266 @@
+//! Utilites for parsing master playlists, which list the variant streams and renditions of a
+//! presentation. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.4>.
+
+use anyhow::{anyhow, Result};
+
+use crate::attributes::AttributeList;
+use crate::uri;
+
+/// Storage for HLS Master Playlist data. Can be constructed from `ext-m3u` data using
+/// [`parse_ext_m3u`][MasterPlaylist::parse_ext_m3u].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MasterPlaylist {
+    /// Version of playlist for compatibility, 1 when the #EXT-X-VERSION tag is missing. See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.1.2>.
+    pub(crate) version: u64,
+
+    /// From the #EXT-X-STREAM-INF tags, in playlist order.
+    pub(crate) variants: Vec<VariantStream>,
+
+    /// From the #EXT-X-MEDIA tags, in playlist order.
+    pub(crate) renditions: Vec<Rendition>,
+}
+
+/// A variant of the presentation, from the #EXT-X-STREAM-INF tag and the URI line after it.
+/// See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.4.2>.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VariantStream {
+    /// Peak bits per second.
+    pub bandwidth: u64,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub average_bandwidth: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub codecs: Option<String>,
+    /// Width and height in pixels.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub resolution: Option<(u64, u64)>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub frame_rate: Option<f64>,
+    /// GROUP-ID of the audio renditions to use with this variant.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub audio: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub video: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub subtitles: Option<String>,
+    /// URI of the media playlist of the variant.
+    pub uri: String,
+    /// `uri` resolved against the playlist location, when it is known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub resolved_uri: Option<String>,
+}
+
+/// An alternative rendition, from the #EXT-X-MEDIA tag. See
+/// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.4.1>.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rendition {
+    /// `AUDIO`, `VIDEO`, `SUBTITLES` or `CLOSED-CAPTIONS`.
+    pub media_type: String,
+    pub group_id: String,
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub language: Option<String>,
+    pub default: bool,
+    pub autoselect: bool,
+    /// URI of the media playlist of the rendition. `None` when the rendition is muxed into the
+    /// variant streams.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub uri: Option<String>,
+    /// `uri` resolved against the playlist location, when it is known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub resolved_uri: Option<String>,
+}
+
+impl MasterPlaylist {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn variants(&self) -> &[VariantStream] {
+        &self.variants
+    }
+
+    pub fn renditions(&self) -> &[Rendition] {
+        &self.renditions
+    }
+
+    /// Same as [`parse_ext_m3u`][MasterPlaylist::parse_ext_m3u] for a playlist loaded from
+    /// `base`: every variant and rendition URI is also resolved against it.
+    pub fn parse_ext_m3u_with_base(file: &str, base: &str) -> Result<Self> {
+        let mut playlist = Self::parse_ext_m3u(file)?;
+        playlist.resolve_uris(base);
+        Ok(playlist)
+    }
+
+    /// Resolves every variant and rendition URI against `base`, the location of the playlist,
+    /// following RFC 3986.
+    pub fn resolve_uris(&mut self, base: &str) {
+        for variant in &mut self.variants {
+            variant.resolved_uri = Some(uri::resolve(base, &variant.uri));
+        }
+        for rendition in &mut self.renditions {
+            rendition.resolved_uri = rendition.uri.as_ref().map(|reference| uri::resolve(base, reference));
+        }
+    }
+
+    /// Parses the given file into a [`MasterPlaylist`], returning an error if the file does not
+    /// adhere to the specification.
+    pub fn parse_ext_m3u(file: &str) -> Result<Self> {
+        let mut lines = file.lines().map(str::trim_end);
+        if lines.next().unwrap_or_default() != "#EXTM3U" {
+            return Err(anyhow!("Missing #EXTM3U header"));
+        }
+
+        let mut version = 1;
+        let mut variants = Vec::new();
+        let mut renditions = Vec::new();
+        // EXT-X-STREAM-INF waiting for its URI line
+        let mut pending_variant: Option<VariantStream> = None;
+
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+            if !line.starts_with('#') {
+                let mut variant = pending_variant
+                    .take()
+                    .ok_or_else(|| anyhow!("URI '{}' without #EXT-X-STREAM-INF", line))?;
+                variant.uri = line.to_string();
+                variants.push(variant);
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix("#EXT-X-VERSION:") {
+                version = value
+                    .parse()
+                    .map_err(|_| anyhow!("EXT-X-VERSION: expecting decimal integer"))?;
+            } else if let Some(value) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+                pending_variant = Some(parse_stream_inf(value)?);
+            } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA:") {
+                renditions.push(parse_media(value)?);
+            } else if line.starts_with("#EXTINF:") {
+                return Err(anyhow!("#EXTINF found, this is a media playlist"));
+            }
+        }
+
+        if pending_variant.is_some() {
+            return Err(anyhow!("#EXT-X-STREAM-INF without URI"));
+        }
+        Ok(MasterPlaylist { version, variants, renditions })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(attributes: &AttributeList, name: &str) -> Result<Option<T>> {
+    attributes
+        .get(name)
+        .map(|value| value.parse().map_err(|_| anyhow!("{}: expecting a number, got '{}'", name, value)))
+        .transpose()
+}
+
+fn parse_stream_inf(value: &str) -> Result<VariantStream> {
+    let attributes = AttributeList::parse(value)?;
+    let resolution = match attributes.get("RESOLUTION") {
+        Some(resolution) => {
+            let (width, height) = resolution
+                .split_once('x')
+                .ok_or_else(|| anyhow!("RESOLUTION: expecting <width>x<height>"))?;
+            Some((
+                width.parse().map_err(|_| anyhow!("RESOLUTION: invalid width"))?,
+                height.parse().map_err(|_| anyhow!("RESOLUTION: invalid height"))?,
+            ))
+        }
+        None => None,
+    };
+    Ok(VariantStream {
+        bandwidth: parse_number(&attributes, "BANDWIDTH")?
+            .ok_or_else(|| anyhow!("EXT-X-STREAM-INF: missing BANDWIDTH"))?,
+        average_bandwidth: parse_number(&attributes, "AVERAGE-BANDWIDTH")?,
+        codecs: attributes.get("CODECS").map(str::to_string),
+        resolution,
+        frame_rate: parse_number(&attributes, "FRAME-RATE")?,
+        audio: attributes.get("AUDIO").map(str::to_string),
+        video: attributes.get("VIDEO").map(str::to_string),
+        subtitles: attributes.get("SUBTITLES").map(str::to_string),
+        uri: String::new(),
+        resolved_uri: None,
+    })
+}
+
+fn parse_media(value: &str) -> Result<Rendition> {
+    let attributes = AttributeList::parse(value)?;
+    Ok(Rendition {
+        media_type: attributes.required("TYPE")?.to_string(),
+        group_id: attributes.required("GROUP-ID")?.to_string(),
+        name: attributes.required("NAME")?.to_string(),
+        language: attributes.get("LANGUAGE").map(str::to_string),
+        default: attributes.get("DEFAULT") == Some("YES"),
+        autoselect: attributes.get("AUTOSELECT") == Some("YES"),
+        uri: attributes.get("URI").map(str::to_string),
+        resolved_uri: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:6
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",DEFAULT=YES,AUTOSELECT=YES,URI="audio/en.m3u8"
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="Muxed"
+        #EXT-X-STREAM-INF:BANDWIDTH=1280000,AVERAGE-BANDWIDTH=1000000,CODECS="avc1.4d401f,mp4a.40.2",RESOLUTION=1280x720,FRAME-RATE=29.97,AUDIO="aac"
+        video/720p.m3u8
+        #EXT-X-STREAM-INF:BANDWIDTH=640000
+        http://other.example/480p.m3u8
+    "#};
+
+    #[test]
+    fn parses_variants_and_renditions() {
+        let playlist = MasterPlaylist::parse_ext_m3u(MASTER).unwrap();
+        assert_eq!(playlist.version, 6);
+        assert_eq!(playlist.variants.len(), 2);
+
+        let variant = &playlist.variants[0];
+        assert_eq!(variant.bandwidth, 1_280_000);
+        assert_eq!(variant.average_bandwidth, Some(1_000_000));
+        assert_eq!(variant.codecs.as_deref(), Some("avc1.4d401f,mp4a.40.2"));
+        assert_eq!(variant.resolution, Some((1280, 720)));
+        assert_eq!(variant.frame_rate, Some(29.97));
+        assert_eq!(variant.audio.as_deref(), Some("aac"));
+        assert_eq!(variant.uri, "video/720p.m3u8");
+
+        assert_eq!(
+            playlist.renditions[0],
+            Rendition {
+                media_type: "AUDIO".to_string(),
+                group_id: "aac".to_string(),
+                name: "English".to_string(),
+                language: Some("en".to_string()),
+                default: true,
+                autoselect: true,
+                uri: Some("audio/en.m3u8".to_string()),
+                resolved_uri: None,
+            }
+        );
+        assert_eq!(playlist.renditions[1].uri, None);
+    }
+
+    #[test]
+    fn resolves_uris() {
+        let playlist = MasterPlaylist::parse_ext_m3u_with_base(MASTER, "https://cdn.example/show/master.m3u8?token=1").unwrap();
+        assert_eq!(playlist.variants[0].resolved_uri.as_deref(), Some("https://cdn.example/show/video/720p.m3u8"));
+        assert_eq!(playlist.variants[1].resolved_uri.as_deref(), Some("http://other.example/480p.m3u8"));
+        assert_eq!(playlist.renditions[0].resolved_uri.as_deref(), Some("https://cdn.example/show/audio/en.m3u8"));
+        assert_eq!(playlist.renditions[1].resolved_uri, None);
+    }
+
+    #[test]
+    fn rejects_media_playlists() {
+        assert!(MasterPlaylist::parse_ext_m3u("#EXTM3U\n#EXTINF:4,\na.ts\n").is_err());
+        assert!(MasterPlaylist::parse_ext_m3u("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\n").is_err());
+    }
+}
@@ -3,7 +3,13 @@
 
 use core::time::Duration;
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset};
 use std::num::ParseIntError;
+use std::ops::{Deref, Range};
+
+use crate::attributes::AttributeList;
+use crate::locate::TimeIndex;
+use crate::uri;
 
 /// Storage for HLS Media Playlist data. Can be constructed from `ext-m3u` data using
 /// [`parse_ext_m3u`][MediaPlaylist::parse_ext_m3u].
@@ -11,11 +17,11 @@ use std::num::ParseIntError;
 pub struct MediaPlaylist {
     /// Whether or not an ENDLIST tag was found. See
     /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.3.4>.
-    ended: bool,
+    pub(crate) ended: bool,
 
     // [ MediaSegment, MediaSegment, MediaSegment, MediaSegment...]
     // [ [Duration, string], [Duration, string], [Duration, string],...]
-    segments: Vec<MediaSegment>,
+    pub(crate) segments: Vec<MediaSegment>,
 
     /// Duration that no media segment can exceed. See
     /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.3.1>.
@@ -23,74 +29,528 @@ pub struct MediaPlaylist {
     ///  secs: u64,
     /// nanos: Nanoseconds
     /// Duration:  [secs, nanos]
-    target_duration: Duration,
+    pub(crate) target_duration: Duration,
 
     /// Version of playlist for compatibility. See
     /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.1.2>.
-    version: u64,
+    pub(crate) version: u64,
+
+    /// Media sequence number of the first segment, from the #EXT-X-MEDIA-SEQUENCE tag (0 when
+    /// absent). See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.3.2>. In a delta
+    /// update the segments left out by #EXT-X-SKIP are counted, so it is the number of the first
+    /// segment kept.
+    pub(crate) media_sequence: u64,
+
+    /// Discontinuity sequence number of the first segment, from the
+    /// #EXT-X-DISCONTINUITY-SEQUENCE tag (0 when absent). See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.3.3>.
+    pub(crate) discontinuity_sequence: u64,
+
+    /// Preferred point to start playing from, from the #EXT-X-START tag. See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.5.2>.
+    pub(crate) start: Option<StartPoint>,
+
+    /// Low-latency delivery capabilities of the server, from the #EXT-X-SERVER-CONTROL tag. See
+    /// <https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.3.8>.
+    pub(crate) server_control: Option<ServerControl>,
+
+    /// Maximum duration of a partial segment, from the #EXT-X-PART-INF tag.
+    pub(crate) part_target: Option<Duration>,
+
+    /// Number of #EXT-X-PART tags after the last segment, i.e. the partial segments published so
+    /// far of the segment being produced.
+    pub(crate) pending_parts: usize,
+
+    /// From the #EXT-X-PLAYLIST-TYPE tag. See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.3.5>.
+    pub(crate) playlist_type: Option<PlaylistType>,
+
+    /// Every #EXT-X-DATERANGE tag, in playlist order. See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.7>.
+    pub(crate) date_ranges: Vec<DateRange>,
 
     // The video segment between the discontinuity tag 
     // [ [[Duration, string], [Duration, string], [Duration, string]...],  
     //   [[Duration, string], [Duration, string], [Duration, string],...], 
     //   [[Duration, string], [Duration, string], [Duration, string],...]
     //  ]
-    discontinuity: Vec<DiscontinuitySegment>,
+    pub(crate) discontinuity: Vec<DiscontinuitySegment>,
+
+    // cumulative segment times for seek lookups, see locate.rs
+    pub(crate) time_index: TimeIndex,
 }
 
 /// A media segment contains information to actually load the presentation. See [the
 /// specification][spec] for more details.
 ///
 /// [spec]: https://datatracker.ietf.org/doc/html/rfc8216#section-3
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct MediaSegment {
     /// From the #EXTINF tag. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.1>.
     ///  secs: u64,
     /// nanos: Nanoseconds
     /// Duration:  [secs, nanos]
-    duration: Duration,
+    pub(crate) duration: Duration,
 
     /// Relative URL of media segment. See
     /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2> and
     /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.1>.
-    url: String,
+    pub(crate) url: String,
+
+    /// Wall clock time of the first sample of the segment, from the #EXT-X-PROGRAM-DATE-TIME
+    /// tag right before it. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.6>.
+    pub(crate) program_date_time: Option<DateTime<FixedOffset>>,
+
+    /// Decryption key of the segment, from the last #EXT-X-KEY tag before it. `None` when the
+    /// segment isn't encrypted. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.4>.
+    pub(crate) key: Option<Key>,
+
+    /// Media initialization section, from the last #EXT-X-MAP tag before the segment. See
+    /// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.5>.
+    pub(crate) map: Option<Map>,
+
+    /// The part of the resource at `url` holding the segment, from the #EXT-X-BYTERANGE tag. The
+    /// offset is always set: when the tag leaves it out, it is the end of the previous range.
+    /// See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.2>.
+    pub(crate) byte_range: Option<ByteRange>,
+
+    /// Whether the segment is missing, from the #EXT-X-GAP tag: clients must not load it. See
+    /// <https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.4.7>.
+    pub(crate) gap: bool,
+
+    // `url` resolved against the playlist location, when it is known
+    pub(crate) resolved_url: Option<String>,
+}
+
+/// From the #EXT-X-KEY tag. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.4>.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key {
+    /// Encryption method, e.g. `AES-128` or `SAMPLE-AES`. Never `NONE`, unencrypted segments
+    /// have no key at all.
+    pub method: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub uri: Option<String>,
+    /// Initialization vector as written in the playlist, e.g. `0x1F...`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub iv: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub key_format: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub key_format_versions: Option<String>,
+    /// `uri` resolved against the playlist location, when it is known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub resolved_uri: Option<String>,
+}
+
+/// From the #EXT-X-MAP tag. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.5>.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Map {
+    pub uri: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub byte_range: Option<ByteRange>,
+    /// `uri` resolved against the playlist location, when it is known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub resolved_uri: Option<String>,
+}
+
+/// A sub-range of a resource, as in #EXT-X-BYTERANGE and the BYTERANGE attribute of
+/// #EXT-X-MAP. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.2>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ByteRange {
+    pub length: u64,
+    /// Start of the range. When `None`, the range starts right after the previous one.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub offset: Option<u64>,
 }
 
+impl ByteRange {
+    // Offset right after the range, which starts at 0 without an offset. `None` when it doesn't
+    // fit in a u64.
+    pub(crate) fn end(&self) -> Option<u64> {
+        self.offset.unwrap_or(0).checked_add(self.length)
+    }
+
+    // Parses `<n>[@<o>]`.
+    pub(crate) fn parse(value: &str) -> Result<Self> {
+        let (length, offset) = match value.split_once('@') {
+            Some((length, offset)) => (length, Some(offset)),
+            None => (value, None),
+        };
+        Ok(ByteRange {
+            length: length.trim().parse().map_err(|_| anyhow!("Invalid byte range '{}'", value))?,
+            offset: offset
+                .map(|offset| offset.trim().parse())
+                .transpose()
+                .map_err(|_| anyhow!("Invalid byte range '{}'", value))?,
+        })
+    }
+}
+
+/// From the #EXT-X-PLAYLIST-TYPE tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum PlaylistType {
+    /// Segments may only be added at the end.
+    Event,
+    /// The playlist never changes.
+    Vod,
+}
+
+/// From the #EXT-X-DATERANGE tag, a range of time with attributes, e.g. an ad break. See
+/// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.7>.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DateRange {
+    pub id: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub class: Option<String>,
+    pub start_date: DateTime<FixedOffset>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub end_date: Option<DateTime<FixedOffset>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serialization::optional_seconds", skip_serializing_if = "Option::is_none")
+    )]
+    pub duration: Option<Duration>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serialization::optional_seconds", skip_serializing_if = "Option::is_none")
+    )]
+    pub planned_duration: Option<Duration>,
+    /// Whether the range ends where the next one of the same class starts (`END-ON-NEXT=YES`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub end_on_next: bool,
+    /// The other attributes (`X-...`, `SCTE35-...`) with their values as written, quotes
+    /// included.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub attributes: Vec<(String, String)>,
+}
+
+impl DateRange {
+    // Parses the attribute list of the tag.
+    pub(crate) fn parse(list: &str) -> Result<Self> {
+        const KNOWN: [&str; 7] = ["ID", "CLASS", "START-DATE", "END-DATE", "DURATION", "PLANNED-DURATION", "END-ON-NEXT"];
+        let attributes = AttributeList::parse(list)?;
+        let seconds = |name: &str| {
+            attributes
+                .get(name)
+                .map(|value| parse_seconds(value).ok_or_else(|| anyhow!("EXT-X-DATERANGE: {} expecting decimal number", name)))
+                .transpose()
+        };
+        Ok(DateRange {
+            id: attributes.required("ID")?.to_string(),
+            class: attributes.get("CLASS").map(str::to_string),
+            start_date: parse_date_time(attributes.required("START-DATE")?)?,
+            end_date: attributes.get("END-DATE").map(parse_date_time).transpose()?,
+            duration: seconds("DURATION")?,
+            planned_duration: seconds("PLANNED-DURATION")?,
+            end_on_next: attributes.get("END-ON-NEXT") == Some("YES"),
+            attributes: attributes
+                .raw()
+                .filter(|(name, _)| !KNOWN.contains(name))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+}
+
+/// From the #EXT-X-START tag. See <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.5.2>.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StartPoint {
+    /// Seconds from the beginning of the playlist, or from the end of the last segment when
+    /// negative.
+    pub time_offset: f64,
+    /// Whether playback should start exactly at `time_offset` instead of at the start of the
+    /// segment containing it.
+    pub precise: bool,
+}
+
+/// From the #EXT-X-SERVER-CONTROL tag, what a low-latency server lets clients ask for. See
+/// <https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.3.8>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerControl {
+    /// How far from the end of the playlist segments may be skipped in a delta update
+    /// (`_HLS_skip=YES`), `None` when delta updates are not supported.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serialization::optional_seconds", skip_serializing_if = "Option::is_none")
+    )]
+    pub can_skip_until: Option<Duration>,
+    /// Whether EXT-X-DATERANGE tags may be skipped too (`_HLS_skip=v2`).
+    pub can_skip_dateranges: bool,
+    /// Minimum distance from the end of the playlist to start playing at.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serialization::optional_seconds", skip_serializing_if = "Option::is_none")
+    )]
+    pub hold_back: Option<Duration>,
+    /// Like `hold_back` for low-latency playback with partial segments.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serialization::optional_seconds", skip_serializing_if = "Option::is_none")
+    )]
+    pub part_hold_back: Option<Duration>,
+    /// Whether the server holds a playlist request until the asked media sequence number (and
+    /// part) is available (`_HLS_msn` and `_HLS_part`).
+    pub can_block_reload: bool,
+}
+
+/// The media segments between two EXT-X-DISCONTINUITY tags. See
+/// <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.3>.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct DiscontinuitySegment {
     // sum of segment durations before the EXT-X-DISCONTINUITY
     //  secs: u64,
     // nanos: Nanoseconds
     // Duration:  [secs, nanos]
-    discontinuity_duration: Duration,
+    pub(crate) discontinuity_duration: Duration,
 
-    // segment before the EXT-X-DISCONTINUITY
-    discontinuity_segments: Vec<MediaSegment>,
-}
+    // indices in `MediaPlaylist::segments` of the segments before the EXT-X-DISCONTINUITY, so
+    // the segments are stored once and the groups can't drift apart from them
+    pub(crate) segments: Range<usize>,
 
+    // discontinuity sequence number shared by every segment of the group, i.e.
+    // EXT-X-DISCONTINUITY-SEQUENCE plus the number of EXT-X-DISCONTINUITY tags before the group
+    pub(crate) discontinuity_sequence: u64,
+
+    // position of the group on the presentation timeline, filled in by `index_groups` (see
+    // timeline.rs) once every group is known
+    pub(crate) start_time: Duration,
+    pub(crate) end_time: Duration,
+    // media sequence number of the first segment
+    pub(crate) media_sequence: u64,
+    // wall clock time of the start of the group, if any segment of the group has a PDT
+    pub(crate) program_date_time: Option<DateTime<FixedOffset>>,
+}
 
 impl MediaPlaylist {
-    // Parses the given file into a [`MediaPlaylist`], returning an error if the file does not
-    // adhere to the specification.
-    pub fn parse_ext_m3u(_file: &str) -> Result<Self> {
+    /// Whether the playlist ended with #EXT-X-ENDLIST, i.e. no more segments will be added.
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Every media segment of the playlist in order.
+    pub fn segments(&self) -> &[MediaSegment] {
+        &self.segments
+    }
+
+    pub fn target_duration(&self) -> Duration {
+        self.target_duration
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Media sequence number of the first segment.
+    pub fn media_sequence(&self) -> u64 {
+        self.media_sequence
+    }
+
+    /// Discontinuity sequence number of the first segment.
+    pub fn discontinuity_sequence(&self) -> u64 {
+        self.discontinuity_sequence
+    }
+
+    /// The segments grouped by EXT-X-DISCONTINUITY tags, as ranges of [`segments`][Self::segments].
+    /// See [`discontinuity_groups`][Self::discontinuity_groups] for the groups with their segments.
+    pub fn discontinuity(&self) -> &[DiscontinuitySegment] {
+        &self.discontinuity
+    }
+
+    /// The discontinuity groups together with their segments.
+    pub fn discontinuity_groups(&self) -> impl ExactSizeIterator<Item = DiscontinuityGroup<'_>> + DoubleEndedIterator {
+        self.discontinuity.iter().map(|group| DiscontinuityGroup::new(group, &self.segments))
+    }
+
+    /// The discontinuity group at `index` in [`discontinuity`][Self::discontinuity], with its
+    /// segments.
+    pub fn discontinuity_group(&self, index: usize) -> Option<DiscontinuityGroup<'_>> {
+        self.discontinuity.get(index).map(|group| DiscontinuityGroup::new(group, &self.segments))
+    }
+
+    pub fn start(&self) -> Option<StartPoint> {
+        self.start
+    }
+
+    pub fn server_control(&self) -> Option<ServerControl> {
+        self.server_control
+    }
+
+    /// From the #EXT-X-PART-INF tag, `None` if the playlist has no partial segments.
+    pub fn part_target(&self) -> Option<Duration> {
+        self.part_target
+    }
+
+    /// Number of partial segments published so far of the segment after the last one.
+    pub fn pending_parts(&self) -> usize {
+        self.pending_parts
+    }
+
+    pub fn playlist_type(&self) -> Option<PlaylistType> {
+        self.playlist_type
+    }
+
+    pub fn date_ranges(&self) -> &[DateRange] {
+        &self.date_ranges
+    }
+}
+
+impl MediaSegment {
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn program_date_time(&self) -> Option<DateTime<FixedOffset>> {
+        self.program_date_time
+    }
+
+    pub fn key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
+    pub fn map(&self) -> Option<&Map> {
+        self.map.as_ref()
+    }
+
+    pub fn byte_range(&self) -> Option<ByteRange> {
+        self.byte_range
+    }
+
+    pub fn gap(&self) -> bool {
+        self.gap
+    }
+
+    /// The segment URL resolved against the playlist location following RFC 3986. Only
+    /// available for playlists parsed with
+    /// [`parse_ext_m3u_with_base`][MediaPlaylist::parse_ext_m3u_with_base] or after
+    /// [`resolve_uris`][MediaPlaylist::resolve_uris].
+    pub fn resolved_uri(&self) -> Option<&str> {
+        self.resolved_url.as_deref()
+    }
+}
+
+// Parses a date-time as found in #EXT-X-PROGRAM-DATE-TIME, which is RFC 3339 but some packagers
+// leave out the colon in the UTC offset.
+pub(crate) fn parse_date_time(value: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .map_err(|err| anyhow!("Invalid date-time '{}': {}", value, err))
+}
+
+// Parses a decimal number of seconds, `None` unless it is a valid duration: negative, NaN and
+// infinite values are refused.
+pub(crate) fn parse_seconds(value: &str) -> Option<Duration> {
+    value.parse().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+impl DiscontinuitySegment {
+    // Starts a new group with its first segment, found at `index` in the segments of the playlist.
+    pub(crate) fn new(discontinuity_sequence: u64, index: usize, duration: Duration) -> Self {
+        DiscontinuitySegment {
+            discontinuity_duration: duration,
+            segments: index..index + 1,
+            discontinuity_sequence,
+            ..Default::default()
+        }
+    }
+
+    // Adds the next segment of the playlist to the group and pumps up the duration.
+    pub(crate) fn push(&mut self, duration: Duration) {
+        // sum the discontinuity duration in milliseconds: summing the nanoseconds of durations
+        // made with 'from_secs_f32()' may introduce extra digits, potentially causing failure in
+        // 'assert_eq!()' statements within the test suite.
+        let sum_discontinuity_duration = self.discontinuity_duration.as_millis() + duration.as_millis();
+        // Then save back in the Duration format.
+        self.discontinuity_duration = Duration::from_millis(sum_discontinuity_duration.try_into().unwrap());
+        self.segments.end += 1;
+    }
+
+    /// Sum of the durations of the segments in the group.
+    pub fn discontinuity_duration(&self) -> Duration {
+        self.discontinuity_duration
+    }
+
+    /// Indices of the segments of the group in [`MediaPlaylist::segments`].
+    pub fn segment_range(&self) -> Range<usize> {
+        self.segments.clone()
+    }
+
+    pub fn discontinuity_sequence(&self) -> u64 {
+        self.discontinuity_sequence
+    }
+}
+
+/// A [`DiscontinuitySegment`] together with its segments, borrowed from the playlist. Derefs
+/// to the group for everything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscontinuityGroup<'a> {
+    group: &'a DiscontinuitySegment,
+    segments: &'a [MediaSegment],
+}
+
+impl<'a> DiscontinuityGroup<'a> {
+    pub(crate) fn new(group: &'a DiscontinuitySegment, segments: &'a [MediaSegment]) -> Self {
+        DiscontinuityGroup { group, segments: &segments[group.segments.clone()] }
+    }
+
+    pub fn group(&self) -> &'a DiscontinuitySegment {
+        self.group
+    }
+
+    /// The segments of the group, in order.
+    pub fn discontinuity_segments(&self) -> &'a [MediaSegment] {
+        self.segments
+    }
+}
 
-        //*** Variables for MedisPlaylist Structure ***/
-        //set the ended to false
-        let mut ended = false;
-        // Create a new vector for storing the segments
-        // Such ["10.000:a_01.ts", "10.102:a_02.ts", "10:113:a_03.ts"...]
-        //
-        let mut segments = Vec::new();
-        // Create a new variable to store the target duration
-        // // set to default value 0
-        let mut target_duration = Duration::new(0, 0);
+impl Deref for DiscontinuityGroup<'_> {
+    type Target = DiscontinuitySegment;
 
-        // Create a new variable to store the version
-        let mut version = None;
+    fn deref(&self) -> &DiscontinuitySegment {
+        self.group
+    }
+}
 
-        // Create a new vector for storing the discontinuity segments
-        // Such [[[30.225,["10.000:a_01.ts", "10.102:a_02.ts", "10:113:a_03.ts"], ["10.000:a_01.ts", "10.102:a_02.ts", "10:113:a_03.ts"], ["10.000:a_01.ts", "10.102:a_02.ts", "10:113:a_03.ts"]],
-        let mut discontinuity: Vec<_> = Vec::new();
 
+impl MediaPlaylist {
+    /// Same as [`parse_ext_m3u`][MediaPlaylist::parse_ext_m3u] for a playlist loaded from `base`
+    /// (usually its URL): every segment, key and map URI is also resolved against it, see
+    /// [`MediaSegment::resolved_uri`].
+    pub fn parse_ext_m3u_with_base(file: &str, base: &str) -> Result<Self> {
+        let mut playlist = Self::parse_ext_m3u(file)?;
+        playlist.resolve_uris(base);
+        Ok(playlist)
+    }
+
+    /// Resolves every segment, key and map URI against `base`, the location of the playlist,
+    /// following RFC 3986. Replaces any previous resolution.
+    pub fn resolve_uris(&mut self, base: &str) {
+        let resolve_segment = |segment: &mut MediaSegment| {
+            segment.resolved_url = Some(uri::resolve(base, &segment.url));
+            if let Some(key) = &mut segment.key {
+                key.resolved_uri = key.uri.as_ref().map(|key_uri| uri::resolve(base, key_uri));
+            }
+            if let Some(map) = &mut segment.map {
+                map.resolved_uri = Some(uri::resolve(base, &map.uri));
+            }
+        };
+        self.segments.iter_mut().for_each(resolve_segment);
+    }
+
+    // Parses the given file into a [`MediaPlaylist`], returning an error if the file does not
+    // adhere to the specification.
+    pub fn parse_ext_m3u(_file: &str) -> Result<Self> {
         //*** Valiables for process */
         // Create a new variable to store the lines of the file
         let mut lines = _file.lines();
@@ -101,155 +561,492 @@ impl MediaPlaylist {
             return Err(anyhow!("Missing #EXTM3U header"));
         }
 
-        // variable to store the duration of the segment
-        let mut duration_seg = Duration::from_secs_f32(0.000);
+        //get into the LOOP to parse manifest content line by line, the state kept between the
+        // lines lives in `LineParser` so the streaming parser (see streaming.rs) shares it
+        let mut parser = LineParser::new(OwnedSegments::default());
+        for line in lines {
+            parser.line(line)?;
+        } //end of loop
 
-        // Set Variable to store the duration of the discontinuity segment:
-        // I need this variable to be on milliseconds because 
-        // it will be used for arithmetic operation (sums).
-        // The issue with using 'from_secs_f32()' arises when performing summation, 
-        // as it may introdure extra digit in nanosecconds
-        // potentially causing failure in 'assert_eq!()' statement within the test suite.
-        // Ref: https://doc.rust-lang.org/core/time/struct.Duration.html
-        let mut sum_discontinuity_duration = Duration::from_millis(0); // use ::from_millis()
+        parser.finish()
+    }
+}
+
+// remove alphabet before convert to u64
+fn u64_from_string (s: &str) -> Result<u64, String> {
+    let digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
+    match digits.parse::<u64>() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(String::from("Error: the string contains non-numeric characters")),
+    }
+}
 
-        let mut discontinuity_flag = true;
+// remove alphabet before convert to f32, only copying the string when there is something to remove
+fn f32_from_string (s: &str) -> Result<f32, String> {
+    let parsed = if s.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        s.parse::<f32>()
+    } else {
+        s.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect::<String>().parse::<f32>()
+    };
+    match parsed {
+        Ok(value) => Ok(value),
+        Err(_) => Err(String::from("Error: the string contains non-numeric characters")),
+    }
+}
 
-        // Create a new variable to store the flag to get the url of the segment
-        let mut get_url = false;
+/// What a line turned out to be, see [`LineParser::line`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineKind {
+    /// A tag other than #EXTINF, which only completes with the URI line of its segment.
+    Tag,
+    /// The URI line completing a segment, now the last of `LineParser::segments`.
+    Segment,
+    /// Anything else: blank lines, comments, #EXTINF.
+    Other,
+}
 
-        // start segment index to clone the segments from prious discontinuity tag
-        let mut start_discontinuity_segment = 0;
+/// A segment as found by [`LineParser`], borrowing from the line and the parser.
+pub(crate) struct SegmentLine<'l, 'k> {
+    pub(crate) duration: Duration,
+    pub(crate) url: &'l str,
+    pub(crate) program_date_time: Option<DateTime<FixedOffset>>,
+    pub(crate) key: Option<&'k Key>,
+    pub(crate) map: Option<&'k Map>,
+    pub(crate) byte_range: Option<ByteRange>,
+    pub(crate) gap: bool,
+    pub(crate) discontinuity_sequence: u64,
+    /// Whether the segment is the first of a discontinuity group.
+    pub(crate) new_group: bool,
+}
 
-        // remove alphabet before convert to u64
-        fn u64_from_string (s: &str) -> Result<u64, String> {
-            let digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
-            match digits.parse::<u64>() {
-                Ok(value) => Ok(value),
-                Err(_) => Err(String::from("Error: the string contains non-numeric characters")),
-            }
+/// Where [`LineParser`] puts the segments it finds, for lines borrowed for `'l`: the owned
+/// playlist copies them, the borrowed one (see borrowed.rs) keeps the slices.
+pub(crate) trait SegmentStore<'l> {
+    fn push(&mut self, segment: SegmentLine<'l, '_>);
+}
+
+/// Segments of a [`MediaPlaylist`], flat and grouped by discontinuity.
+#[derive(Debug, Default)]
+pub(crate) struct OwnedSegments {
+    // Such ["10.000:a_01.ts", "10.102:a_02.ts", "10:113:a_03.ts"...]
+    pub(crate) segments: Vec<MediaSegment>,
+    // Such [[30.225, 0..3], [34.374, 3..6]...]
+    pub(crate) discontinuity: Vec<DiscontinuitySegment>,
+}
+
+impl OwnedSegments {
+    // Appends `segment`, to a new discontinuity group when `new_group` (always for the first one).
+    pub(crate) fn push_segment(&mut self, segment: MediaSegment, discontinuity_sequence: u64, new_group: bool) {
+        let duration = segment.duration;
+        // *** Save the duration and url to MediaPlaylist.segments.
+        self.segments.push(segment);
+
+        // *** Save discontinuity
+        // MydiaPlaylist = [...
+        //              [ [Segment_Duration, string], [Segment_Duration, string] ], ...]
+        //                 |
+        // discontinuity = |----> [ [discontinuity_duration, range of the segments in MediaPlaylist.segments],...,]
+        match self.discontinuity.last_mut() {
+            // if the discontinuity is not empty, then extend the last element of the discontinuity
+            // to the segment, then pump up the duration
+            Some(last_discontinuity) if !new_group => last_discontinuity.push(duration),
+            // create a new discontinuity group starting at the segment
+            _ => self.discontinuity.push(DiscontinuitySegment::new(
+                discontinuity_sequence,
+                self.segments.len() - 1,
+                duration,
+            )),
+        }
+    }
+
+    // Appends `segment`, starting a new group when its discontinuity sequence number differs from
+    // the previous segment.
+    pub(crate) fn push_sequenced(&mut self, segment: MediaSegment, discontinuity_sequence: u64) {
+        let new_group = self
+            .discontinuity
+            .last()
+            .is_none_or(|last| last.discontinuity_sequence != discontinuity_sequence);
+        self.push_segment(segment, discontinuity_sequence, new_group);
+    }
+}
+
+impl<'l> SegmentStore<'l> for OwnedSegments {
+    fn push(&mut self, line: SegmentLine<'l, '_>) {
+        let segment = MediaSegment {
+            duration: line.duration,
+            url: line.url.to_string(),
+            program_date_time: line.program_date_time,
+            key: line.key.cloned(),
+            map: line.map.cloned(),
+            byte_range: line.byte_range,
+            gap: line.gap,
+            resolved_url: None,
+        };
+        self.push_segment(segment, line.discontinuity_sequence, line.new_group);
+    }
+}
+
+/// State of the media playlist parser between two lines (everything after the #EXTM3U header).
+#[derive(Debug)]
+pub(crate) struct LineParser<S> {
+    //*** Variables for MedisPlaylist Structure ***/
+    pub(crate) ended: bool,
+    // the segments found so far
+    pub(crate) store: S,
+    // number of segments found so far
+    pub(crate) segment_count: usize,
+    // set to default value 0
+    pub(crate) target_duration: Duration,
+    pub(crate) version: Option<u64>,
+
+    // Sequence numbers of the first segment, both default to 0 when the tags are missing
+    pub(crate) media_sequence: u64,
+    pub(crate) discontinuity_sequence: u64,
+
+    pub(crate) start: Option<StartPoint>,
+    pub(crate) server_control: Option<ServerControl>,
+    pub(crate) part_target: Option<Duration>,
+    // EXT-X-PART tags since the last segment
+    pub(crate) pending_parts: usize,
+    pub(crate) playlist_type: Option<PlaylistType>,
+    pub(crate) date_ranges: Vec<DateRange>,
+
+    // EXT-X-PROGRAM-DATE-TIME waiting for the segment it applies to
+    program_date_time: Option<DateTime<FixedOffset>>,
+
+    // EXT-X-KEY and EXT-X-MAP apply to every segment until the next tag of the same kind
+    key: Option<Key>,
+    map: Option<Map>,
+
+    // duration of the segment, from the last #EXTINF
+    duration_seg: Duration,
+
+    // EXT-X-BYTERANGE waiting for the segment it applies to
+    byte_range: Option<ByteRange>,
+    // resource and end of the last segment with a byte range, where a range without offset starts
+    range_end: Option<(String, u64)>,
+
+    // EXT-X-GAP waiting for the segment it applies to
+    gap: bool,
+
+    discontinuity_flag: bool,
+
+    // discontinuity sequence number of the next group, bumped by every EXT-X-DISCONTINUITY tag
+    pub(crate) discontinuity_number: u64,
+
+    // flag to get the url of the segment
+    get_url: bool,
+}
+
+impl<S> LineParser<S> {
+    pub(crate) fn new(store: S) -> Self {
+        LineParser {
+            //set the ended to false
+            ended: false,
+            store,
+            segment_count: 0,
+            target_duration: Duration::new(0, 0),
+            version: None,
+            media_sequence: 0,
+            discontinuity_sequence: 0,
+            start: None,
+            server_control: None,
+            part_target: None,
+            pending_parts: 0,
+            playlist_type: None,
+            date_ranges: Vec::new(),
+            program_date_time: None,
+            key: None,
+            map: None,
+            duration_seg: Duration::from_secs_f32(0.000),
+            byte_range: None,
+            range_end: None,
+            gap: false,
+            discontinuity_flag: true,
+            discontinuity_number: 0,
+            get_url: false,
         }
+    }
 
-        // remove alphabet before convert to f32
-        fn f32_from_string (s: &str) -> Result<f32, String> {
-            let digits: String = s.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect();
-            match digits.parse::<f32>() {
-                Ok(value) => Ok(value),
-                Err(_) => Err(String::from("Error: the string contains non-numeric characters")),
+    /// Parses one line of the playlist, without its line terminator.
+    pub(crate) fn line<'l>(&mut self, line: &'l str) -> Result<LineKind>
+    where
+        S: SegmentStore<'l>,
+    {
+        if self.get_url { //found the duration, then looking for url for the segment
+            // check if the line contains the url: any line which isn't a tag or blank, so
+            // fMP4 (EXT-X-MAP) segments such as .m4s are found as well as .ts ones
+            if !line.starts_with('#') && !line.trim().is_empty() {
+                let byte_range = self.byte_range.take().map(|range| self.locate_range(range, line)).transpose()?;
+                if byte_range.is_none() {
+                    self.range_end = None;
+                }
+                self.store.push(SegmentLine {
+                    duration: self.duration_seg,
+                    url: line,
+                    program_date_time: self.program_date_time.take(),
+                    key: self.key.as_ref(),
+                    map: self.map.as_ref(),
+                    byte_range,
+                    gap: std::mem::take(&mut self.gap),
+                    discontinuity_sequence: self.discontinuity_number,
+                    new_group: self.segment_count == 0 || self.discontinuity_flag,
+                });
+                self.segment_count += 1;
+                self.pending_parts = 0;
+                self.discontinuity_flag = false;
+                // Set get_url flag OFF
+                self.get_url = false;
+                return Ok(LineKind::Segment);
             }
-        }        
+            // if the line does not contain the url, it may be a tag between the EXTINF and the
+            // url such as EXT-X-PROGRAM-DATE-TIME, so it goes through the match below
+        }
 
-        //get into the LOOP to parse manifest content line by line
-        for line in lines {
-            if get_url { //found the duration, then looking for url for the segment
-                if line.contains(".ts") { //check if the line contains the url
-                    // *** Save the duration and url to MediaPlaylist.segments.
-                    segments.push(MediaSegment { duration: duration_seg, url: line.to_string() });
-
-                    // *** Save discontinuity
-                    // MydiaPlaylist = [...
-                    //              [ [Segment_Duration, string], [Segment_Duration, string] ], ...]
-                    //                 |
-                    // discontinuity = |----> [ [discontinuity_duration,[[Segment_Duration, string],...,[Segment_Duration, string]],...,]
-                    if discontinuity.is_empty() || discontinuity_flag { // create a new discontinuity vector and push the segment
-                        let mut discontinuity_segment = DiscontinuitySegment {
-                            discontinuity_segments: vec![MediaSegment { duration: duration_seg, url: line.to_string() }],  // creating a new vector containing a single 'MeidaSegment' struct
-                            discontinuity_duration: duration_seg,
-                        };
-                        discontinuity.push(discontinuity_segment);
-                        discontinuity_flag = false;
-                    } else { 
-                        // if the discontinuity is not empty, then get the last element of the discontinuity
-                        // and push the segment to the last element of the discontinuity, then pump up the duration
-                        let last_discontinuity = discontinuity.last_mut().unwrap();
-                        // sum the discontinuity duration in milliseconds
-                        let sum_discontinuity_duration = last_discontinuity.discontinuity_duration.as_millis() + duration_seg.as_millis() as u128;
-                        // Then save back in the Duration format.
-                        last_discontinuity.discontinuity_duration = Duration::from_millis(sum_discontinuity_duration.try_into().unwrap());
-                        // Then push the segment to the last element of the discontinuity
-                        last_discontinuity.discontinuity_segments.push(MediaSegment { duration: duration_seg, url: line.to_string() });
-                    }
-                    // Set get_url flag OFF
-                    get_url = false;
-                    continue;
-                } else { // if the line does not contain the url, then get the next line, may need to handle the error here if the HLS content is missing ".ts"
-                    continue; // skip the line below
+        match line {
+            s if s.contains("EXT-X-TARGETDURATION") => {
+                //#EXT-X-TARGETDURATION:20
+                let target_duration_str = s
+                .split(':') // [#EXT-X-TARGETDURATION, 20]
+                .next_back() // 20
+                .ok_or_else(|| anyhow!("EXT-X-TARGETDURATION: expecting digit")).unwrap();
+
+                //Save the target_duration
+                // Function u64_from_string will remove alphabet from the string
+                // before convert to u64
+                // by using library Duration and from_secs() function
+                // Note: the from_secs will set the nanos to 0.
+                // secs: u64,
+                // nanos: Nanoseconds
+                // Duration:  [secs, nanos]
+                match u64_from_string(target_duration_str) {
+                     Ok(num) => self.target_duration = Duration::from_secs(num),
+                     Err(err) => println!{"EXT-X-TARGETDURATION: expecting digit in HLS manifest after 'EXT-X-TARGETDURATION:' tag"},
+                }
+
+            },
+            s if s.contains("#EXT-X-VERSION:") => { // HLS manifest version
+                //#EXT-X-VERSION:4
+                // Try with string slice to get a string starting from the length of "EXT-X-VERSION:" until the end of the line
+                // convert the string to u64
+                // If the .parse return an error, the ok() will set the version to None
+                self.version = line["#EXT-X-VERSION:".len()..]// get the value after the "#EXT-X-VERSION:"
+                    .parse()// convert to u64
+                    .ok(); // if error, set to None
+            },
+            s if s.contains("#EXTINF:") => { // segment duration
+                // // ------parsing to get the durration by using string slice ------
+                // // #EXTINF:12.166,
+                let duration_f32 = line["#EXTINF:".len()..]// string slide to get the value after the "12.166,"
+                    .split(',')// 12.166, => ["12.166", ""]
+                    .next().unwrap();// get the first part, "12.166"
+
+                // Put the duration_f32 in the Duration struct{[secs, nanos]}
+                // by using the from_secs_f32() function because we need to preserve the nanos
+                // Use function f32_from_string to remove alphabet from the string before
+                // convert to f32.
+                // If there is error then display the error message.
+                match f32_from_string(duration_f32) {
+                        Ok(num) => self.duration_seg = Duration::from_secs_f32(num),
+                        Err(err) => println!{"EXTINF: expecting digit in HLS manifest after 'EXTINF:'"},
+                }
+
+                // need to get the url of the segment in the next two lines, so set get_url to true
+                // turn get_url flag ON
+                self.get_url = true;
+                return Ok(LineKind::Other);
+           },
+            s if s.starts_with("#EXT-X-MEDIA-SEQUENCE:") => {
+                //#EXT-X-MEDIA-SEQUENCE:1
+                self.media_sequence = line["#EXT-X-MEDIA-SEQUENCE:".len()..]
+                    .parse()
+                    .map_err(|_| anyhow!("EXT-X-MEDIA-SEQUENCE: expecting decimal integer"))?;
+            },
+            // must be checked before EXT-X-DISCONTINUITY, which is a prefix of this tag
+            s if s.starts_with("#EXT-X-DISCONTINUITY-SEQUENCE:") => {
+                //#EXT-X-DISCONTINUITY-SEQUENCE:3
+                self.discontinuity_sequence = line["#EXT-X-DISCONTINUITY-SEQUENCE:".len()..]
+                    .parse()
+                    .map_err(|_| anyhow!("EXT-X-DISCONTINUITY-SEQUENCE: expecting decimal integer"))?;
+                self.discontinuity_number = self.discontinuity_sequence;
+            },
+            s if s.starts_with("#EXT-X-PROGRAM-DATE-TIME:") => {
+                //#EXT-X-PROGRAM-DATE-TIME:2015-08-25T01:59:23.708+00:00
+                self.program_date_time = Some(parse_date_time(&line["#EXT-X-PROGRAM-DATE-TIME:".len()..])?);
+            },
+            s if s.starts_with("#EXT-X-KEY:") => {
+                //#EXT-X-KEY:METHOD=AES-128,URI="https://priv.example.com/key.php?r=52"
+                let attributes = AttributeList::parse(&line["#EXT-X-KEY:".len()..])?;
+                self.key = match attributes.required("METHOD")? {
+                    "NONE" => None,
+                    method => Some(Key {
+                        method: method.to_string(),
+                        uri: attributes.get("URI").map(str::to_string),
+                        iv: attributes.get("IV").map(str::to_string),
+                        key_format: attributes.get("KEYFORMAT").map(str::to_string),
+                        key_format_versions: attributes.get("KEYFORMATVERSIONS").map(str::to_string),
+                        resolved_uri: None,
+                    }),
+                };
+            },
+            s if s.starts_with("#EXT-X-MAP:") => {
+                //#EXT-X-MAP:URI="init.mp4",BYTERANGE="720@0"
+                let attributes = AttributeList::parse(&line["#EXT-X-MAP:".len()..])?;
+                self.map = Some(Map {
+                    uri: attributes.required("URI")?.to_string(),
+                    byte_range: attributes.get("BYTERANGE").map(ByteRange::parse).transpose()?,
+                    resolved_uri: None,
+                });
+            },
+            s if s.starts_with("#EXT-X-START:") => {
+                //#EXT-X-START:TIME-OFFSET=-12.5,PRECISE=YES
+                let attributes = AttributeList::parse(&line["#EXT-X-START:".len()..])?;
+                let time_offset = attributes
+                    .required("TIME-OFFSET")?
+                    .parse()
+                    .map_err(|_| anyhow!("EXT-X-START: TIME-OFFSET expecting decimal number"))?;
+                self.start = Some(StartPoint { time_offset, precise: attributes.get("PRECISE") == Some("YES") });
+            },
+            s if s.starts_with("#EXT-X-SERVER-CONTROL:") => {
+                //#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,CAN-SKIP-UNTIL=12.0,PART-HOLD-BACK=1.0
+                let attributes = AttributeList::parse(&line["#EXT-X-SERVER-CONTROL:".len()..])?;
+                let seconds = |name: &str| {
+                    attributes
+                        .get(name)
+                        .map(|value| {
+                            parse_seconds(value)
+                                .ok_or_else(|| anyhow!("EXT-X-SERVER-CONTROL: {} expecting decimal number", name))
+                        })
+                        .transpose()
+                };
+                self.server_control = Some(ServerControl {
+                    can_skip_until: seconds("CAN-SKIP-UNTIL")?,
+                    can_skip_dateranges: attributes.get("CAN-SKIP-DATERANGES") == Some("YES"),
+                    hold_back: seconds("HOLD-BACK")?,
+                    part_hold_back: seconds("PART-HOLD-BACK")?,
+                    can_block_reload: attributes.get("CAN-BLOCK-RELOAD") == Some("YES"),
+                });
+            },
+            s if s.starts_with("#EXT-X-PART-INF:") => {
+                //#EXT-X-PART-INF:PART-TARGET=1.004
+                let attributes = AttributeList::parse(&line["#EXT-X-PART-INF:".len()..])?;
+                let part_target = parse_seconds(attributes.required("PART-TARGET")?)
+                    .ok_or_else(|| anyhow!("EXT-X-PART-INF: PART-TARGET expecting decimal number"))?;
+                self.part_target = Some(part_target);
+            },
+            s if s.starts_with("#EXT-X-BYTERANGE:") => {
+                //#EXT-X-BYTERANGE:1430680@4048392
+                self.byte_range = Some(ByteRange::parse(&line["#EXT-X-BYTERANGE:".len()..])?);
+            },
+            s if s.starts_with("#EXT-X-PLAYLIST-TYPE:") => {
+                //#EXT-X-PLAYLIST-TYPE:VOD
+                self.playlist_type = Some(match &line["#EXT-X-PLAYLIST-TYPE:".len()..] {
+                    "EVENT" => PlaylistType::Event,
+                    "VOD" => PlaylistType::Vod,
+                    other => return Err(anyhow!("EXT-X-PLAYLIST-TYPE: unknown type '{}'", other)),
+                });
+            },
+            s if s.starts_with("#EXT-X-DATERANGE:") => {
+                //#EXT-X-DATERANGE:ID="ad1",START-DATE="2020-01-01T00:00:00Z",DURATION=30
+                self.date_ranges.push(DateRange::parse(&line["#EXT-X-DATERANGE:".len()..])?);
+            },
+            "#EXT-X-GAP" => {
+                self.gap = true;
+            },
+            s if s.starts_with("#EXT-X-SKIP:") => {
+                //#EXT-X-SKIP:SKIPPED-SEGMENTS=3
+                // a delta update leaves out the oldest segments, the first one kept is numbered as
+                // if they were there
+                let attributes = AttributeList::parse(&line["#EXT-X-SKIP:".len()..])?;
+                let skipped: u64 = attributes
+                    .required("SKIPPED-SEGMENTS")?
+                    .parse()
+                    .map_err(|_| anyhow!("EXT-X-SKIP: SKIPPED-SEGMENTS expecting decimal integer"))?;
+                if self.segment_count > 0 {
+                    return Err(anyhow!("EXT-X-SKIP: must come before the first segment"));
                 }
+                self.media_sequence = self
+                    .media_sequence
+                    .checked_add(skipped)
+                    .ok_or_else(|| anyhow!("EXT-X-SKIP: SKIPPED-SEGMENTS too large"))?;
+            },
+            s if s.starts_with("#EXT-X-PART:") => {
+                // partial segments are not kept, only how many there are of the next segment
+                self.pending_parts += 1;
+            },
+           s if s.contains("#EXT-X-DISCONTINUITY") => { // IF found the EXT-X-DISCONTINUITY tag,
+                // Set discontinuity flag to true
+                self.discontinuity_flag = true;
+                // the segments after the tag belong to the next discontinuity sequence number
+                self.discontinuity_number += 1;
+            },
+            s if s.contains("#EXT-X-ENDLIST") => { // FOUND the end of the playlist
+                // set the ended to true
+                self.ended = true;
+            },
+           _ => { // do nothing
             }
+        }
 
-            match line.to_string() {
-                s if s.contains("EXT-X-TARGETDURATION") => {
-                    //#EXT-X-TARGETDURATION:20
-                    let target_duration_str = s
-                    .split(':') // [#EXT-X-TARGETDURATION, 20]
-                    .last() // 20
-                    .ok_or_else(|| anyhow!("EXT-X-TARGETDURATION: expecting digit")).unwrap();
-
-                    //Save the target_duration
-                    // Function u64_from_string will remove alphabet from the string
-                    // before convert to u64
-                    // by using library Duration and from_secs() function
-                    // Note: the from_secs will set the nanos to 0.
-                    // secs: u64,
-                    // nanos: Nanoseconds
-                    // Duration:  [secs, nanos]
-                    match u64_from_string(target_duration_str) {
-                         Ok(num) => target_duration = Duration::from_secs(num),
-                         Err(err) => println!{"EXT-X-TARGETDURATION: expecting digit in HLS manifest after 'EXT-X-TARGETDURATION:' tag"},
-                    }
+        Ok(if line.starts_with("#EXT") { LineKind::Tag } else { LineKind::Other })
+    }
 
-                },
-                s if s.contains("#EXT-X-VERSION:") => { // HLS manifest version
-                    //#EXT-X-VERSION:4
-                    // Try with string slice to get a string starting from the length of "EXT-X-VERSION:" until the end of the line
-                    // convert the string to u64
-                    // If the .parse return an error, the ok() will set the version to None 
-                    version = line["#EXT-X-VERSION:".len()..]// get the value after the "#EXT-X-VERSION:"
-                        .parse()// convert to u64
-                        .ok(); // if error, set to None
-                },
-                s if s.contains("#EXTINF:") => { // segment duration
-                    // // ------parsing to get the durration by using string slice ------
-                    // // #EXTINF:12.166,
-                    let duration_f32 = line["#EXTINF:".len()..]// string slide to get the value after the "12.166,"
-                        .splitn(2,',')// 12.166, => ["12.166", ""]
-                        .next().unwrap();// get the first part, "12.166"
-
-                    // Put the duration_f32 in the Duration struct{[secs, nanos]}
-                    // by using the from_secs_f32() function because we need to preserve the nanos
-                    // Use function f32_from_string to remove alphabet from the string before 
-                    // convert to f32.
-                    // If there is error then display the error message.
-                    match f32_from_string(duration_f32) {
-                            Ok(num) => duration_seg = Duration::from_secs_f32(num),
-                            Err(err) => println!{"EXTINF: expecting digit in HLS manifest after 'EXTINF:'"},
-                    }
-   
-                    // need to get the url of the segment in the next two lines, so set get_url to true
-                    // turn get_url flag ON
-                    get_url = true;
-               },
-               s if s.contains("#EXT-X-DISCONTINUITY") => { // IF found the EXT-X-DISCONTINUITY tag,
-                    // Set discontinuity flag to true
-                    discontinuity_flag = true;
-                },
-                s if s.contains("#EXT-X-ENDLIST") => { // FOUND the end of the playlist
-                    // set the ended to true
-                    ended = true;
-                },
-               _ => { // do nothing
-                    continue;
+    // Fills in the offset of a segment byte range, which defaults to the end of the previous
+    // range of the same resource.
+    fn locate_range(&mut self, range: ByteRange, url: &str) -> Result<ByteRange> {
+        let offset = match (range.offset, &self.range_end) {
+            (Some(offset), _) => offset,
+            (None, Some((previous, end))) if previous == url => *end,
+            (None, _) => return Err(anyhow!("EXT-X-BYTERANGE: missing offset, the previous segment is not a range of {}", url)),
+        };
+        let located = ByteRange { length: range.length, offset: Some(offset) };
+        let end = located
+            .end()
+            .ok_or_else(|| anyhow!("EXT-X-BYTERANGE: {} ends past the largest offset", located))?;
+        // the resource is usually the same as the previous segment's, its copy is kept then
+        match &mut self.range_end {
+            Some((previous, previous_end)) => {
+                if previous != url {
+                    previous.clear();
+                    previous.push_str(url);
                 }
+                *previous_end = end;
             }
-        } //end of loop
+            None => self.range_end = Some((url.to_string(), end)),
+        }
+        Ok(located)
+    }
+
+}
 
+impl LineParser<OwnedSegments> {
+    /// Builds the playlist once every line went through [`line`][LineParser::line].
+    pub(crate) fn finish(self) -> Result<MediaPlaylist> {
         // if the version is None, return an error message
-        let version = version.ok_or_else(|| anyhow!("Missing #EXT-X-VERSION"))?;
+        let version = self.version.ok_or_else(|| anyhow!("Missing #EXT-X-VERSION"))?;
 
         // return the MediaPlaylist with the values
-        // { ended: bool, segments: Vec<MediaSegment>, target_duration: Duration, version: u64}
+        // { ended: bool, segments: Vec<MediaSegment>, target_duration: Duration, version: u64, ...}
         // put in Ok() to return the Result<Self>
-        Ok(MediaPlaylist { ended, segments, target_duration, version, discontinuity })
+        let mut playlist = MediaPlaylist {
+            ended: self.ended,
+            segments: self.store.segments,
+            target_duration: self.target_duration,
+            version,
+            media_sequence: self.media_sequence,
+            discontinuity_sequence: self.discontinuity_sequence,
+            start: self.start,
+            server_control: self.server_control,
+            part_target: self.part_target,
+            pending_parts: self.pending_parts,
+            playlist_type: self.playlist_type,
+            date_ranges: self.date_ranges,
+            discontinuity: self.store.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        // fill in the seek index and the timeline of the discontinuity groups
+        playlist.reindex();
+        Ok(playlist)
     }
 }
 
@@ -323,6 +1120,39 @@ mod tests {
             assert_ne!(playlist.target_duration, Duration::from_secs(21));
         }
 
+        #[test]
+        fn parses_media_sequence() {
+            let playlist = big_buck_bunny();
+            assert_eq!(playlist.media_sequence, 1);
+            assert_eq!(playlist.discontinuity_sequence, 0);
+        }
+
+        #[test]
+        fn parses_discontinuity_timeline() {
+            let playlist = big_buck_bunny();
+            let groups = playlist.discontinuity();
+
+            assert_eq!(groups[0].start_time(), Duration::ZERO);
+            assert_eq!(groups[1].start_time(), groups[0].end_time());
+            assert_eq!(groups[2].start_time(), groups[1].end_time());
+            assert_eq!(groups[2].end_time(), playlist.duration());
+            assert_eq!(
+                groups[1].start_time(),
+                Duration::from_secs_f32(12.166) + Duration::from_secs_f32(13.292)
+            );
+
+            assert_eq!(groups[1].first_media_sequence(), 3);
+            assert_eq!(groups[1].last_media_sequence(), 5);
+            assert_eq!(groups[1].discontinuity_sequence(), 1);
+
+            // only the first group has a PROGRAM-DATE-TIME, the others can't be derived from it
+            assert_eq!(
+                groups[0].program_date_time(),
+                Some(parse_date_time("2015-08-25T01:59:23.708+00:00").unwrap())
+            );
+            assert_eq!(groups[1].program_date_time(), None);
+        }
+
         #[test]
         fn parses_end_tag() {
             let playlist = big_buck_bunny();
@@ -336,34 +1166,58 @@ mod tests {
                 MediaSegment {
                     duration: Duration::from_secs_f32(12.166),
                     url: "segment_1440468394459_1440468394459_1.ts".to_string(),
+                    program_date_time: Some(parse_date_time("2015-08-25T01:59:23.708+00:00").unwrap()),
+                    byte_range: Some(ByteRange { length: 1430680, offset: Some(4048392) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(13.292),
                     url: "segment_1440468394459_1440468394459_2.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 840360, offset: Some(5479072) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(10.500),
                     url: "segment_1440468394459_1440468394459_3.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 1009184, offset: Some(6319432) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(11.417),
                     url: "segment_1440468394459_1440468394459_4.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 806332, offset: Some(0) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(12.459),
                     url: "segment_1440468394459_1440468394459_5.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 701616, offset: Some(806332) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(14.000),
                     url: "segment_1440468394459_1440468394459_6.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 931352, offset: Some(1507948) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(19.292),
                     url: "segment_1440468394459_1440468394459_7.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 1593676, offset: Some(2439300) }),
+                    ..Default::default()
                 },
                 MediaSegment {
                     duration: Duration::from_secs_f32(7.834),
                     url: "segment_1440468394459_1440468394459_8.ts".to_string(),
+                    program_date_time: None,
+                    byte_range: Some(ByteRange { length: 657812, offset: Some(4032976) }),
+                    ..Default::default()
                 },
             ];
 
@@ -387,67 +1241,192 @@ mod tests {
         fn parses_discontinuity() {
             let playlist = big_buck_bunny();
             let expected = vec![
-                DiscontinuitySegment {
-                    discontinuity_duration: Duration::from_millis(25457),
-                    discontinuity_segments: vec![
+                (
+                    // (discontinuity_sequence, discontinuity_duration, discontinuity_segments)
+                    0,
+                    Duration::from_millis(25457),
+                    vec![
                         MediaSegment {
                             duration: Duration::from_secs_f32(12.166),
                             url: "segment_1440468394459_1440468394459_1.ts".to_string(),
+                            program_date_time: Some(parse_date_time("2015-08-25T01:59:23.708+00:00").unwrap()),
+                            byte_range: Some(ByteRange { length: 1430680, offset: Some(4048392) }),
+                            ..Default::default()
                         },
                         MediaSegment {
                             duration: Duration::from_secs_f32(13.292),
                             url: "segment_1440468394459_1440468394459_2.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 840360, offset: Some(5479072) }),
+                            ..Default::default()
                         },
                     ],
-                },
-                DiscontinuitySegment {
-                    discontinuity_duration: Duration::from_millis(34374),
-                    discontinuity_segments: vec![
+                ),
+                (
+                    // (discontinuity_sequence, discontinuity_duration, discontinuity_segments)
+                    1,
+                    Duration::from_millis(34374),
+                    vec![
                         MediaSegment {
                             duration: Duration::from_secs_f32(10.500),
                             url: "segment_1440468394459_1440468394459_3.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 1009184, offset: Some(6319432) }),
+                            ..Default::default()
                         },
                         MediaSegment {
                             duration: Duration::from_secs_f32(11.417),
                             url: "segment_1440468394459_1440468394459_4.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 806332, offset: Some(0) }),
+                            ..Default::default()
                         },
                         MediaSegment {
                             duration: Duration::from_secs_f32(12.459),
                             url: "segment_1440468394459_1440468394459_5.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 701616, offset: Some(806332) }),
+                            ..Default::default()
                         },
                     ],
-                },
-                DiscontinuitySegment {
-                    discontinuity_duration: Duration::from_millis(41125),
-                    discontinuity_segments: vec![
+                ),
+                (
+                    // (discontinuity_sequence, discontinuity_duration, discontinuity_segments)
+                    2,
+                    Duration::from_millis(41125),
+                    vec![
                         MediaSegment {
                             duration: Duration::from_secs_f32(14.000),
                             url: "segment_1440468394459_1440468394459_6.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 931352, offset: Some(1507948) }),
+                            ..Default::default()
                         },
                         MediaSegment {
                             duration: Duration::from_secs_f32(19.292),
                             url: "segment_1440468394459_1440468394459_7.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 1593676, offset: Some(2439300) }),
+                            ..Default::default()
                         },
                         MediaSegment {
                             duration: Duration::from_secs_f32(7.834),
                             url: "segment_1440468394459_1440468394459_8.ts".to_string(),
+                            program_date_time: None,
+                            byte_range: Some(ByteRange { length: 657812, offset: Some(4032976) }),
+                            ..Default::default()
                         },
                     ],
-                },
+                ),
             ];
             // loop through the discontinuity segments and compare the discontinuity_duration and
             // get in side the discontinuity segments and compare the duration and url with above value in the expected
             // [ [discontinuity_duration=34.375, [ [Duration, string], [Duration, string], [Duration, string] ] ],
             // [ [discontinuity_duration=25.458, [ [Duration, string], [Duration, string], [Duration, string] ] ],
             // [ [discontinuity_duration=41.126, [ [Duration, string], [Duration, string], [Duration, string] ] ],
-            for (outter_actual, outter_expected) in playlist.discontinuity.into_iter().zip(expected) {
-                // compare the discontinuity_duration
-                assert_eq!(outter_actual.discontinuity_duration, outter_expected.discontinuity_duration);
+            assert_eq!(playlist.discontinuity_groups().len(), expected.len());
+            for (outter_actual, outter_expected) in playlist.discontinuity_groups().zip(expected) {
+                // compare the discontinuity_duration and sequence number
+                assert_eq!(outter_actual.discontinuity_duration(), outter_expected.1);
+                assert_eq!(outter_actual.discontinuity_sequence(), outter_expected.0);
                 // [Duration, string], [Duration, string], [Duration, string]...],
-                for (inner_actual, inner_expected) in outter_actual.discontinuity_segments.into_iter().zip(outter_expected.discontinuity_segments) {
+                assert_eq!(outter_actual.discontinuity_segments().len(), outter_expected.2.len());
+                for (inner_actual, inner_expected) in outter_actual.discontinuity_segments().iter().zip(&outter_expected.2) {
                     assert_eq!(inner_actual, inner_expected);
                 }
             }
+            // the groups are ranges of the segments, one after the other
+            let ranges: Vec<_> = playlist.discontinuity().iter().map(DiscontinuitySegment::segment_range).collect();
+            assert_eq!(ranges, vec![0..2, 2..5, 5..8]);
+        }
+    }
+
+    mod uris {
+        use super::*;
+
+        const ENCRYPTED: &str = indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:6
+            #EXT-X-TARGETDURATION:4
+            #EXT-X-MAP:URI="../init.mp4"
+            #EXT-X-KEY:METHOD=SAMPLE-AES,URI="/keys/1?id=2",KEYFORMAT="identity"
+            #EXTINF:4,
+            seg1.m4s?v=1
+            #EXT-X-KEY:METHOD=NONE
+            #EXTINF:4,
+            https://other.example/seg2.m4s
+        "#};
+
+        #[test]
+        fn parses_key_and_map() {
+            let playlist = MediaPlaylist::parse_ext_m3u(ENCRYPTED).unwrap();
+            let key = playlist.segments[0].key().unwrap();
+            assert_eq!(key.method, "SAMPLE-AES");
+            assert_eq!(key.uri.as_deref(), Some("/keys/1?id=2"));
+            assert_eq!(key.key_format.as_deref(), Some("identity"));
+            assert_eq!(playlist.segments[1].key(), None);
+            assert_eq!(playlist.segments[1].map().unwrap().uri, "../init.mp4");
+            assert_eq!(playlist.segments[0].resolved_uri(), None);
+        }
+
+        #[test]
+        fn resolves_against_base() {
+            let playlist =
+                MediaPlaylist::parse_ext_m3u_with_base(ENCRYPTED, "https://cdn.example/show/hd/index.m3u8").unwrap();
+            let first = &playlist.segments[0];
+            assert_eq!(first.resolved_uri(), Some("https://cdn.example/show/hd/seg1.m4s?v=1"));
+            assert_eq!(first.key().unwrap().resolved_uri.as_deref(), Some("https://cdn.example/keys/1?id=2"));
+            assert_eq!(first.map().unwrap().resolved_uri.as_deref(), Some("https://cdn.example/show/init.mp4"));
+            assert_eq!(playlist.segments[1].resolved_uri(), Some("https://other.example/seg2.m4s"));
+            assert_eq!(
+                playlist.discontinuity_group(0).unwrap().discontinuity_segments()[1].resolved_uri(),
+                Some("https://other.example/seg2.m4s")
+            );
+        }
+    }
+
+    #[test]
+    fn continues_byte_ranges_without_offset() {
+        let source = "#EXTM3U\n#EXT-X-VERSION:4\n#EXTINF:4,\n#EXT-X-BYTERANGE:100@50\nall.ts\n#EXTINF:4,\n#EXT-X-BYTERANGE:200\nall.ts\n";
+        let playlist = MediaPlaylist::parse_ext_m3u(source).unwrap();
+        assert_eq!(playlist.segments[1].byte_range(), Some(ByteRange { length: 200, offset: Some(150) }));
+        assert_eq!(MediaPlaylist::parse_ext_m3u(&playlist.to_string()).unwrap(), playlist);
+
+        let other_resource = source.replacen("all.ts", "other.ts", 1);
+        assert!(MediaPlaylist::parse_ext_m3u(&other_resource).is_err());
+    }
+
+    #[test]
+    fn rejects_byte_ranges_past_the_largest_offset() {
+        let source = "#EXTM3U\n#EXT-X-VERSION:4\n#EXTINF:4,\n#EXT-X-BYTERANGE:10@18446744073709551615\nall.ts\n";
+        let error = MediaPlaylist::parse_ext_m3u(source).unwrap_err();
+        assert!(format!("{:#}", error).contains("EXT-X-BYTERANGE: 10@18446744073709551615 ends past"), "{:#}", error);
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(f32_from_string("12.166"), Ok(12.166));
+        assert_eq!(f32_from_string("12.166s"), Ok(12.166));
+        assert!(f32_from_string("abc").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_seconds() {
+        for value in ["-1", "NaN", "inf", "1e400"] {
+            let server_control = format!("#EXTM3U\n#EXT-X-VERSION:9\n#EXT-X-SERVER-CONTROL:HOLD-BACK={}\n", value);
+            let error = MediaPlaylist::parse_ext_m3u(&server_control).unwrap_err();
+            assert!(format!("{:#}", error).contains("HOLD-BACK expecting decimal number"), "{:#}", error);
+
+            let date_range = format!(
+                "#EXTM3U\n#EXT-X-VERSION:9\n#EXT-X-DATERANGE:ID=\"a\",START-DATE=\"2020-01-01T00:00:00Z\",DURATION={}\n",
+                value
+            );
+            let error = MediaPlaylist::parse_ext_m3u(&date_range).unwrap_err();
+            assert!(format!("{:#}", error).contains("DURATION expecting decimal number"), "{:#}", error);
+
+            let part_inf = format!("#EXTM3U\n#EXT-X-VERSION:9\n#EXT-X-PART-INF:PART-TARGET={}\n", value);
+            let error = MediaPlaylist::parse_ext_m3u(&part_inf).unwrap_err();
+            assert!(format!("{:#}", error).contains("PART-TARGET expecting decimal number"), "{:#}", error);
         }
     }
-}
\ No newline at end of file
+}
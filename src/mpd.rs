@@ -0,0 +1,530 @@
+//! Conversion of HLS presentations to DASH.
+//!
+//! [`MpdWriter`] turns a master playlist and its media playlists into a static MPEG-DASH
+//! manifest (ISO/IEC 23009-1). Each discontinuity group becomes a Period, so the media playlists
+//! must have the same discontinuities. The variant streams make up one AdaptationSet and each
+//! rendition with a URI one more, every media playlist a Representation whose BaseURL is the
+//! directory of the playlist, so the segment URLs can be kept as written.
+//!
+//! Segments are listed either in a SegmentList, where byte ranges become `mediaRange`, or in a
+//! SegmentTemplate when their URLs only differ by a counter, see [`SegmentAddressing`]. Both
+//! carry a SegmentTimeline with the #EXTINF durations in milliseconds, and EXT-X-MAP becomes the
+//! Initialization of the Representation.
+//!
+//! Encrypted segments and EXT-X-GAP have no equivalent and are refused.
+
+use core::fmt::Write;
+use core::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::master_playlist::MasterPlaylist;
+use crate::media_playlist::{ByteRange, DiscontinuityGroup, Map, MediaPlaylist, MediaSegment};
+
+/// How an [`MpdWriter`] lists the segments of a Representation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SegmentAddressing {
+    /// A SegmentList with a SegmentURL per segment.
+    #[default]
+    List,
+    /// A SegmentTemplate with a `$Number$` media pattern. Segment URLs must only differ by a
+    /// counter increasing by one, and have no byte ranges.
+    Template,
+}
+
+/// Writes a DASH MPD for a master playlist, see the [module documentation][crate::mpd].
+#[derive(Debug, Clone)]
+pub struct MpdWriter<'a> {
+    master: &'a MasterPlaylist,
+    media: Vec<(&'a str, &'a MediaPlaylist)>,
+    addressing: SegmentAddressing,
+}
+
+// A Representation to write: its attributes and media playlist.
+struct Representation<'a> {
+    id: String,
+    bandwidth: u64,
+    codecs: Option<String>,
+    resolution: Option<(u64, u64)>,
+    frame_rate: Option<f64>,
+    uri: &'a str,
+    playlist: &'a MediaPlaylist,
+}
+
+// An AdaptationSet to write.
+struct AdaptationSet<'a> {
+    content_type: &'static str,
+    language: Option<&'a str>,
+    label: Option<&'a str>,
+    main: bool,
+    representations: Vec<Representation<'a>>,
+}
+
+impl<'a> MpdWriter<'a> {
+    pub fn new(master: &'a MasterPlaylist) -> Self {
+        MpdWriter { master, media: Vec::new(), addressing: SegmentAddressing::default() }
+    }
+
+    /// Gives the media playlist of a variant or rendition, `uri` being written as in the master
+    /// playlist.
+    pub fn with_media_playlist(mut self, uri: &'a str, playlist: &'a MediaPlaylist) -> Self {
+        self.media.push((uri, playlist));
+        self
+    }
+
+    pub fn with_addressing(mut self, addressing: SegmentAddressing) -> Self {
+        self.addressing = addressing;
+        self
+    }
+
+    /// The MPD document.
+    ///
+    /// Fails when a media playlist of the master playlist wasn't given, or the presentation
+    /// can't be expressed in DASH.
+    pub fn write(&self) -> Result<String> {
+        let adaptation_sets = self.adaptation_sets()?;
+        let playlists: Vec<&MediaPlaylist> =
+            adaptation_sets.iter().flat_map(|set| &set.representations).map(|r| r.playlist).collect();
+        let Some(first) = playlists.first() else {
+            bail!("the master playlist has no variant stream");
+        };
+        for playlist in &playlists {
+            if !playlist.ended {
+                bail!("only ended playlists make a static MPD");
+            }
+            if playlist.discontinuity.len() != first.discontinuity.len() {
+                bail!("the media playlists don't have the same discontinuities");
+            }
+        }
+        let min_buffer_time = playlists.iter().map(|playlist| playlist.target_duration).max().unwrap_or_default();
+
+        let mut mpd = String::new();
+        writeln!(mpd, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            mpd,
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" profiles="urn:mpeg:dash:profile:isoff-main:2011" type="static" mediaPresentationDuration="{}" minBufferTime="{}">"#,
+            iso_duration(first.duration()),
+            iso_duration(min_buffer_time)
+        )?;
+        for (period, group) in first.discontinuity_groups().enumerate() {
+            writeln!(
+                mpd,
+                r#"  <Period id="{}" start="{}" duration="{}">"#,
+                group.discontinuity_sequence(),
+                iso_duration(group.start_time()),
+                iso_duration(group.end_time() - group.start_time())
+            )?;
+            for (id, set) in adaptation_sets.iter().enumerate() {
+                write!(mpd, r#"    <AdaptationSet id="{}" contentType="{}""#, id, set.content_type)?;
+                if let Some(language) = set.language {
+                    write!(mpd, r#" lang="{}""#, escape(language))?;
+                }
+                writeln!(mpd, r#" segmentAlignment="true">"#)?;
+                if let Some(label) = set.label {
+                    writeln!(mpd, "      <Label>{}</Label>", escape(label))?;
+                }
+                if set.main {
+                    writeln!(mpd, r#"      <Role schemeIdUri="urn:mpeg:dash:role:2011" value="main"/>"#)?;
+                }
+                for representation in &set.representations {
+                    let group = representation.playlist.discontinuity_group(period).expect("checked above");
+                    self.write_representation(&mut mpd, set.content_type, representation, group)?;
+                }
+                writeln!(mpd, "    </AdaptationSet>")?;
+            }
+            writeln!(mpd, "  </Period>")?;
+        }
+        writeln!(mpd, "</MPD>")?;
+        Ok(mpd)
+    }
+
+    fn media_playlist(&self, uri: &str) -> Result<&'a MediaPlaylist> {
+        self.media
+            .iter()
+            .find(|(media_uri, _)| *media_uri == uri)
+            .map(|(_, playlist)| *playlist)
+            .ok_or_else(|| anyhow!("no media playlist given for {}", uri))
+    }
+
+    fn adaptation_sets(&self) -> Result<Vec<AdaptationSet<'a>>> {
+        let master = self.master;
+        let mut sets = Vec::new();
+
+        let mut variants = Vec::new();
+        for (index, variant) in master.variants.iter().enumerate() {
+            // audio of its own goes to the AdaptationSet of the rendition
+            let separate_audio = master.renditions.iter().any(|rendition| {
+                rendition.media_type == "AUDIO" && rendition.uri.is_some() && variant.audio.as_ref() == Some(&rendition.group_id)
+            });
+            let codecs = match &variant.codecs {
+                Some(codecs) if separate_audio => {
+                    Some(codecs.split(',').map(str::trim).filter(|codec| !is_audio_codec(codec)).collect::<Vec<_>>().join(","))
+                }
+                codecs => codecs.clone(),
+            };
+            variants.push(Representation {
+                id: format!("v{}", index),
+                bandwidth: variant.bandwidth,
+                codecs: codecs.filter(|codecs| !codecs.is_empty()),
+                resolution: variant.resolution,
+                frame_rate: variant.frame_rate,
+                uri: &variant.uri,
+                playlist: self.media_playlist(&variant.uri)?,
+            });
+        }
+        let audio_only = master.variants.iter().all(|variant| {
+            variant.resolution.is_none() && variant.codecs.as_deref().is_some_and(|codecs| codecs.split(',').all(is_audio_codec))
+        });
+        if !variants.is_empty() {
+            let content_type = if audio_only { "audio" } else { "video" };
+            sets.push(AdaptationSet { content_type, language: None, label: None, main: false, representations: variants });
+        }
+
+        for (index, rendition) in master.renditions.iter().enumerate() {
+            let Some(uri) = &rendition.uri else {
+                continue;
+            };
+            let content_type = match rendition.media_type.as_str() {
+                "AUDIO" => "audio",
+                "VIDEO" => "video",
+                "SUBTITLES" => "text",
+                _ => continue,
+            };
+            // the codecs of a rendition are only listed with those of the variants using it
+            let codecs = master
+                .variants
+                .iter()
+                .filter(|variant| content_type == "audio" && variant.audio.as_deref() == Some(rendition.group_id.as_str()))
+                .find_map(|variant| variant.codecs.as_deref())
+                .map(|codecs| codecs.split(',').map(str::trim).filter(|codec| is_audio_codec(codec)).collect::<Vec<_>>().join(","))
+                .filter(|codecs| !codecs.is_empty());
+            let representation = Representation {
+                id: format!("r{}", index),
+                // the master playlist gives no bandwidth for renditions
+                bandwidth: 0,
+                codecs,
+                resolution: None,
+                frame_rate: None,
+                uri,
+                playlist: self.media_playlist(uri)?,
+            };
+            sets.push(AdaptationSet {
+                content_type,
+                language: rendition.language.as_deref(),
+                label: Some(&rendition.name),
+                main: rendition.default,
+                representations: vec![representation],
+            });
+        }
+        Ok(sets)
+    }
+
+    fn write_representation(
+        &self,
+        mpd: &mut String,
+        content_type: &str,
+        representation: &Representation<'_>,
+        group: DiscontinuityGroup<'_>,
+    ) -> Result<()> {
+        let segments = group.discontinuity_segments();
+        let Some(first) = segments.first() else {
+            bail!("{}: empty discontinuity group", representation.uri);
+        };
+        for segment in segments {
+            if segment.key.is_some() {
+                bail!("{}: encrypted segments can't be written to an MPD", representation.uri);
+            }
+            if segment.gap {
+                bail!("{}: EXT-X-GAP segments can't be written to an MPD", representation.uri);
+            }
+            if segment.map != first.map {
+                bail!("{}: the EXT-X-MAP changes without a discontinuity", representation.uri);
+            }
+        }
+
+        write!(
+            mpd,
+            r#"      <Representation id="{}" mimeType="{}" bandwidth="{}""#,
+            representation.id,
+            mime_type(content_type, first)?,
+            representation.bandwidth
+        )?;
+        if let Some(codecs) = &representation.codecs {
+            write!(mpd, r#" codecs="{}""#, escape(codecs))?;
+        }
+        if let Some((width, height)) = representation.resolution {
+            write!(mpd, r#" width="{}" height="{}""#, width, height)?;
+        }
+        if let Some(frame_rate) = representation.frame_rate {
+            write!(mpd, r#" frameRate="{}""#, dash_frame_rate(frame_rate))?;
+        }
+        writeln!(mpd, ">")?;
+        if let Some((directory, _)) = representation.uri.rsplit_once('/') {
+            writeln!(mpd, "        <BaseURL>{}/</BaseURL>", escape(directory))?;
+        }
+
+        match self.addressing {
+            SegmentAddressing::List => {
+                writeln!(mpd, r#"        <SegmentList timescale="1000">"#)?;
+                if let Some(map) = &first.map {
+                    writeln!(mpd, "          {}", initialization(map)?)?;
+                }
+                write_timeline(mpd, segments, "          ")?;
+                for segment in segments {
+                    write!(mpd, r#"          <SegmentURL media="{}""#, escape(&segment.url))?;
+                    if let Some(range) = segment.byte_range {
+                        write!(mpd, r#" mediaRange="{}""#, dash_range(range)?)?;
+                    }
+                    writeln!(mpd, "/>")?;
+                }
+                writeln!(mpd, "        </SegmentList>")?;
+            }
+            SegmentAddressing::Template => {
+                let (media, start_number) = number_template(segments)
+                    .ok_or_else(|| anyhow!("{}: the segment URLs don't follow a $Number$ pattern", representation.uri))?;
+                write!(mpd, r#"        <SegmentTemplate timescale="1000" media="{}" startNumber="{}""#, escape(&media), start_number)?;
+                if let Some(map) = &first.map {
+                    if map.byte_range.is_some() {
+                        bail!("{}: a SegmentTemplate can't have a byte range of initialization", representation.uri);
+                    }
+                    write!(mpd, r#" initialization="{}""#, escape(&map.uri.replace('$', "$$")))?;
+                }
+                writeln!(mpd, ">")?;
+                write_timeline(mpd, segments, "          ")?;
+                writeln!(mpd, "        </SegmentTemplate>")?;
+            }
+        }
+        writeln!(mpd, "      </Representation>")?;
+        Ok(())
+    }
+}
+
+// The SegmentTimeline of the segments, starting at 0 in milliseconds. Times are rounded from the
+// start of the group, so rounding errors don't add up, and repeated durations are folded.
+fn write_timeline(mpd: &mut String, segments: &[MediaSegment], indent: &str) -> Result<()> {
+    let mut runs: Vec<(u128, u64)> = Vec::new();
+    let mut end = Duration::ZERO;
+    for segment in segments {
+        let start = end.as_millis();
+        end += segment.duration;
+        let duration = end.as_millis() - start;
+        match runs.last_mut() {
+            Some((last, repeat)) if *last == duration => *repeat += 1,
+            _ => runs.push((duration, 0)),
+        }
+    }
+    writeln!(mpd, "{}<SegmentTimeline>", indent)?;
+    for (index, (duration, repeat)) in runs.into_iter().enumerate() {
+        write!(mpd, "{}  <S", indent)?;
+        if index == 0 {
+            write!(mpd, r#" t="0""#)?;
+        }
+        write!(mpd, r#" d="{}""#, duration)?;
+        if repeat > 0 {
+            write!(mpd, r#" r="{}""#, repeat)?;
+        }
+        writeln!(mpd, "/>")?;
+    }
+    writeln!(mpd, "{}</SegmentTimeline>", indent)?;
+    Ok(())
+}
+
+fn initialization(map: &Map) -> Result<String> {
+    let mut element = format!(r#"<Initialization sourceURL="{}""#, escape(&map.uri));
+    if let Some(range) = map.byte_range {
+        element += &format!(r#" range="{}""#, dash_range(range)?);
+    }
+    Ok(element + "/>")
+}
+
+// The `first-last` form of a byte range, inclusive, which can't express an empty range.
+fn dash_range(range: ByteRange) -> Result<String> {
+    let first = range.offset.unwrap_or_default();
+    match range.end() {
+        Some(end) if range.length > 0 => Ok(format!("{}-{}", first, end - 1)),
+        _ => Err(anyhow!("byte range {} can't be written as a DASH range", range)),
+    }
+}
+
+// The `$Number$` media template of the segments and the number of the first one, when their URLs
+// are the same but for a counter increasing by one, e.g. `seg_007.ts`, `seg_008.ts`.
+fn number_template(segments: &[MediaSegment]) -> Option<(String, u64)> {
+    let first = &segments.first()?.url;
+    if segments.iter().any(|segment| segment.byte_range.is_some()) {
+        return None;
+    }
+    // the counter is in the name rather than the extension, e.g. `seg_4.m4s`
+    let name_end = first.rfind('.').filter(|&dot| !first[dot..].contains('/')).unwrap_or(first.len());
+    let mut counters = Vec::new();
+    let mut digits_start = None;
+    for (index, c) in first[..name_end].char_indices().chain([(name_end, '.')]) {
+        match (c.is_ascii_digit(), digits_start) {
+            (true, None) => digits_start = Some(index),
+            (false, Some(start)) => {
+                counters.push(start..index);
+                digits_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    counters.into_iter().rev().find_map(|counter| {
+        let (prefix, digits, suffix) = (&first[..counter.start], &first[counter.clone()], &first[counter.end..]);
+        let start: u64 = digits.parse().ok()?;
+        let width = if digits.len() > 1 && digits.starts_with('0') { digits.len() } else { 0 };
+        let matches = (start..)
+            .zip(segments)
+            .all(|(number, segment)| segment.url == format!("{}{:0width$}{}", prefix, number, suffix, width = width));
+        let number = if width > 0 { format!("$Number%0{}d$", width) } else { "$Number$".to_string() };
+        matches.then(|| (format!("{}{}{}", prefix.replace('$', "$$"), number, suffix.replace('$', "$$")), start))
+    })
+}
+
+// The MIME type of the Representation, from the extension of its first segment.
+fn mime_type(content_type: &str, segment: &MediaSegment) -> Result<String> {
+    let path = segment.url.split(['?', '#']).next().unwrap_or_default();
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).unwrap_or_default();
+    let subtype = match extension.as_str() {
+        "ts" => "mp2t",
+        "mp4" | "m4s" | "m4v" | "m4a" | "cmfv" | "cmfa" | "cmft" => "mp4",
+        "aac" => "aac",
+        "vtt" | "webvtt" => return Ok("text/vtt".to_string()),
+        _ => bail!("unknown media type of {}", segment.url),
+    };
+    let kind = if content_type == "text" { "application" } else { content_type };
+    Ok(format!("{}/{}", kind, subtype))
+}
+
+fn is_audio_codec(codec: &str) -> bool {
+    let codec = codec.trim();
+    ["mp4a", "ac-3", "ec-3", "opus", "flac", "alac"].iter().any(|prefix| codec.starts_with(prefix))
+}
+
+// An xs:duration, e.g. `PT9.009S`.
+fn iso_duration(duration: Duration) -> String {
+    format!("PT{}S", duration.as_millis() as f64 / 1000.0)
+}
+
+// A frame rate as DASH wants it, an integer or a fraction: NTSC rates become `n/1001`.
+fn dash_frame_rate(frame_rate: f64) -> String {
+    let ntsc = frame_rate * 1.001;
+    if (frame_rate - frame_rate.round()).abs() < 0.001 {
+        format!("{}", frame_rate.round())
+    } else if (ntsc - ntsc.round()).abs() < 0.01 {
+        format!("{}/1001", ntsc.round() * 1000.0)
+    } else {
+        format!("{}/1000", (frame_rate * 1000.0).round())
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:6
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",DEFAULT=YES,AUTOSELECT=YES,URI="audio/en.m3u8"
+        #EXT-X-STREAM-INF:BANDWIDTH=2000000,CODECS="avc1.64001f,mp4a.40.2",RESOLUTION=1280x720,FRAME-RATE=29.970,AUDIO="aac"
+        video/720p.m3u8
+    "#};
+
+    const VIDEO: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:6
+        #EXT-X-TARGETDURATION:4
+        #EXT-X-MAP:URI="init.mp4"
+        #EXTINF:4,
+        seg_001.m4s
+        #EXTINF:4,
+        seg_002.m4s
+        #EXTINF:2.5,
+        seg_003.m4s
+        #EXT-X-DISCONTINUITY
+        #EXT-X-MAP:URI="ad-init.mp4"
+        #EXTINF:4,
+        seg_004.m4s
+        #EXT-X-ENDLIST
+    "#};
+
+    const AUDIO: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:6
+        #EXT-X-TARGETDURATION:4
+        #EXT-X-MAP:URI="audio.mp4",BYTERANGE="800@0"
+        #EXTINF:4,
+        #EXT-X-BYTERANGE:1000@800
+        audio.mp4
+        #EXTINF:4,
+        #EXT-X-BYTERANGE:1000
+        audio.mp4
+        #EXTINF:2.5,
+        #EXT-X-BYTERANGE:600
+        audio.mp4
+        #EXT-X-DISCONTINUITY
+        #EXT-X-MAP:URI="ad-audio.mp4",BYTERANGE="800@0"
+        #EXTINF:4,
+        #EXT-X-BYTERANGE:1000@800
+        ad-audio.mp4
+        #EXT-X-ENDLIST
+    "#};
+
+    fn playlists() -> (MasterPlaylist, MediaPlaylist, MediaPlaylist) {
+        (
+            MasterPlaylist::parse_ext_m3u(MASTER).unwrap(),
+            MediaPlaylist::parse_ext_m3u(VIDEO).unwrap(),
+            MediaPlaylist::parse_ext_m3u(AUDIO).unwrap(),
+        )
+    }
+
+    #[test]
+    fn writes_segment_lists() {
+        let (master, video, audio) = playlists();
+        let mpd = MpdWriter::new(&master)
+            .with_media_playlist("video/720p.m3u8", &video)
+            .with_media_playlist("audio/en.m3u8", &audio)
+            .write()
+            .unwrap();
+        assert_eq!(mpd, include_str!("../testdata/mpd/segment_list.mpd"));
+    }
+
+    #[test]
+    fn writes_segment_templates() {
+        let (master, video, _) = playlists();
+        let video_only = MasterPlaylist { renditions: Vec::new(), ..master.clone() };
+        let mpd = MpdWriter::new(&video_only)
+            .with_media_playlist("video/720p.m3u8", &video)
+            .with_addressing(SegmentAddressing::Template)
+            .write()
+            .unwrap();
+        assert_eq!(mpd, include_str!("../testdata/mpd/segment_template.mpd"));
+
+        // byte ranges have no template, and every media playlist is needed
+        let (master, video, audio) = playlists();
+        let templates = MpdWriter::new(&master)
+            .with_media_playlist("video/720p.m3u8", &video)
+            .with_media_playlist("audio/en.m3u8", &audio)
+            .with_addressing(SegmentAddressing::Template);
+        assert!(templates.write().is_err());
+        assert!(MpdWriter::new(&master).with_media_playlist("video/720p.m3u8", &video).write().is_err());
+    }
+
+    #[test]
+    fn refuses_ranges_without_a_last_byte() {
+        let (master, video, _) = playlists();
+        for range in ["0@0", "10@18446744073709551615"] {
+            let mut audio = MediaPlaylist::parse_ext_m3u(AUDIO).unwrap();
+            audio.segments[3].byte_range = Some(ByteRange::parse(range).unwrap());
+            let error = MpdWriter::new(&master)
+                .with_media_playlist("video/720p.m3u8", &video)
+                .with_media_playlist("audio/en.m3u8", &audio)
+                .write()
+                .unwrap_err();
+            assert!(format!("{:#}", error).contains("can't be written as a DASH range"), "{:#}", error);
+        }
+    }
+}
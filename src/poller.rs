@@ -0,0 +1,291 @@
+//! Polling of live media playlists, behind the `fetch` cargo feature.
+//!
+//! [`LivePoller`] reloads a playlist following
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-6.3.4>: after the target duration when
+//! it changed, after half of it when it did not. Failed reloads are retried with exponential
+//! backoff. Each reload is merged by a [`LivePlaylistTracker`] and what is new comes out as
+//! [`PollEvent`]s.
+//!
+//! Time goes through a [`Clock`] and requests through a [`Transport`], so tests can run a
+//! poller without waiting or a network.
+
+use core::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+
+use crate::fetch::{Fetcher, Transport, UreqTransport};
+use crate::live::{LivePlaylistTracker, ReloadStatus, TrackedSegment};
+
+/// Source of time for a [`LivePoller`].
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> Instant {
+        (*self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (*self).sleep(duration)
+    }
+}
+
+/// The real time, sleeping the current thread.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// Something new in a reloaded playlist, see [`LivePoller::poll`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollEvent {
+    /// A discontinuity group seen for the first time, by discontinuity sequence number. Comes
+    /// before the new segments of the group.
+    Discontinuity(u64),
+    /// A segment seen for the first time.
+    Segment(Box<TrackedSegment>),
+    /// The playlist got #EXT-X-ENDLIST, it will not be reloaded again.
+    Ended,
+}
+
+/// Reloads a live media playlist, see the [module documentation][crate::poller].
+#[derive(Debug)]
+pub struct LivePoller<T = UreqTransport, C = SystemClock> {
+    url: String,
+    fetcher: Fetcher<T>,
+    clock: C,
+    tracker: LivePlaylistTracker,
+    // when the next reload is due, None before the first one
+    next_reload: Option<Instant>,
+    // failed reloads in a row
+    failures: u32,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl LivePoller {
+    pub fn new(url: &str) -> Self {
+        Self::with_parts(url, Fetcher::new(), SystemClock)
+    }
+}
+
+impl<T: Transport, C: Clock> LivePoller<T, C> {
+    pub fn with_parts(url: &str, fetcher: Fetcher<T>, clock: C) -> Self {
+        LivePoller {
+            url: url.to_string(),
+            fetcher,
+            clock,
+            tracker: LivePlaylistTracker::new(),
+            next_reload: None,
+            failures: 0,
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the wait after a failed reload, `min` the first time and doubling on every failure
+    /// in a row up to `max`. Defaults to 1 and 30 seconds.
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Keeps at most `history` segments which left the window of the playlist, see
+    /// [`LivePlaylistTracker::with_history`].
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.tracker = self.tracker.with_history(history);
+        self
+    }
+
+    pub fn tracker(&self) -> &LivePlaylistTracker {
+        &self.tracker
+    }
+
+    /// Whether the playlist ended, after which [`poll`][Self::poll] does nothing.
+    pub fn ended(&self) -> bool {
+        self.tracker.ended()
+    }
+
+    /// When the next reload is due, `None` before the first one and after the end.
+    pub fn next_reload(&self) -> Option<Instant> {
+        self.next_reload.filter(|_| !self.ended())
+    }
+
+    /// Number of failed reloads in a row.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Sleeps until the next reload is due, reloads the playlist and returns what is new, in
+    /// playlist order. The first call reloads right away.
+    ///
+    /// On error the reload is retried after the backoff delay by the next call.
+    pub fn poll(&mut self) -> Result<Vec<PollEvent>> {
+        if self.ended() {
+            return Ok(Vec::new());
+        }
+        if let Some(due) = self.next_reload {
+            let now = self.clock.now();
+            if due > now {
+                self.clock.sleep(due - now);
+            }
+        }
+
+        // the reload timing is ours, not the cache's
+        self.fetcher.clear_cache();
+        let fetched = match self.fetcher.fetch_media(&self.url) {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                let backoff = self.min_backoff.saturating_mul(1 << self.failures.min(16)).min(self.max_backoff);
+                self.failures += 1;
+                self.next_reload = Some(self.clock.now() + backoff);
+                return Err(err);
+            }
+        };
+        self.failures = 0;
+
+        let reload = self.tracker.update(fetched.playlist);
+        self.next_reload = Some(self.clock.now() + reload.reload_after);
+
+        let mut events = Vec::new();
+        if reload.status != ReloadStatus::Regressed {
+            let mut discontinuities = reload.new_discontinuities.iter().peekable();
+            for media_sequence in reload.added {
+                let tracked = self.tracker.segment(media_sequence).expect("added segments are tracked");
+                while let Some(sequence) = discontinuities.next_if(|sequence| **sequence <= tracked.discontinuity_sequence) {
+                    events.push(PollEvent::Discontinuity(*sequence));
+                }
+                events.push(PollEvent::Segment(Box::new(tracked.clone())));
+            }
+            events.extend(discontinuities.map(|sequence| PollEvent::Discontinuity(*sequence)));
+        }
+        if self.ended() {
+            events.push(PollEvent::Ended);
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+
+    use anyhow::anyhow;
+
+    use crate::fetch::HttpResponse;
+
+    // Time only moves when the poller sleeps.
+    struct MockClock {
+        now: Cell<Instant>,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl MockClock {
+        fn new() -> Self {
+            MockClock { now: Cell::new(Instant::now()), sleeps: RefCell::new(Vec::new()) }
+        }
+
+        fn sleeps(&self) -> Vec<Duration> {
+            self.sleeps.take()
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    // Serves the given playlist bodies in order, `None` being a failed request.
+    fn fake_transport(bodies: Vec<Option<String>>) -> impl Fn(&str) -> Result<HttpResponse> {
+        let bodies = RefCell::new(VecDeque::from(bodies));
+        move |_: &str| match bodies.borrow_mut().pop_front().flatten() {
+            Some(body) => Ok(HttpResponse { status: 200, headers: Vec::new(), body: body.into_bytes() }),
+            None => Err(anyhow!("connection refused")),
+        }
+    }
+
+    fn snapshot(media_sequence: u64, body: &str) -> Option<String> {
+        Some(format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:{}\n{}",
+            media_sequence, body
+        ))
+    }
+
+    fn urls(events: &[PollEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                PollEvent::Segment(tracked) => tracked.segment.url.clone(),
+                PollEvent::Discontinuity(sequence) => format!("discontinuity {}", sequence),
+                PollEvent::Ended => "ended".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reloads_at_the_target_duration_until_the_end() {
+        let clock = MockClock::new();
+        let transport = fake_transport(vec![
+            snapshot(0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n"),
+            snapshot(0, "#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n"),
+            snapshot(1, "#EXTINF:4,\nb.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:4,\nc.ts\n#EXT-X-ENDLIST\n"),
+        ]);
+        let mut poller = LivePoller::with_parts("https://a.test/live.m3u8", Fetcher::with_transport(transport), &clock);
+
+        assert_eq!(urls(&poller.poll().unwrap()), ["discontinuity 0", "a.ts", "b.ts"]);
+        // unchanged, so the next reload comes sooner
+        assert_eq!(urls(&poller.poll().unwrap()), Vec::<String>::new());
+        assert_eq!(urls(&poller.poll().unwrap()), ["discontinuity 1", "c.ts", "ended"]);
+        assert_eq!(clock.sleeps(), [Duration::from_secs(4), Duration::from_secs(2)]);
+
+        assert!(poller.ended());
+        assert_eq!(poller.next_reload(), None);
+        assert_eq!(poller.poll().unwrap(), Vec::new());
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[test]
+    fn backs_off_exponentially_on_errors() {
+        let clock = MockClock::new();
+        let transport = fake_transport(vec![
+            snapshot(0, "#EXTINF:4,\na.ts\n"),
+            None,
+            None,
+            None,
+            snapshot(1, "#EXTINF:4,\nb.ts\n"),
+        ]);
+        let mut poller = LivePoller::with_parts("https://a.test/live.m3u8", Fetcher::with_transport(transport), &clock)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(3));
+
+        poller.poll().unwrap();
+        for failures in 1..=3 {
+            assert!(poller.poll().is_err());
+            assert_eq!(poller.failures(), failures);
+        }
+        assert_eq!(urls(&poller.poll().unwrap()), ["b.ts"]);
+        assert_eq!(poller.failures(), 0);
+        let seconds = [4, 1, 2, 3].map(Duration::from_secs);
+        assert_eq!(clock.sleeps(), seconds);
+    }
+}
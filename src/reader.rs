@@ -0,0 +1,149 @@
+//! Parsing from readers and files, with the encoding checks of
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-4.1>: playlists are UTF-8 without
+//! control characters other than CR and LF.
+//!
+//! A leading byte order mark is stripped rather than rejected, and with the `gzip` cargo
+//! feature gzip-compressed input (e.g. from a disk cache) is decompressed on the fly.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::media_playlist::{LineParser, MediaPlaylist, OwnedSegments};
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+
+// Checks a line (without its line terminator) and returns it as text. `number` is 1-based.
+fn decode_line(line: &[u8], number: usize) -> Result<&str> {
+    let text = std::str::from_utf8(line).map_err(|err| {
+        let column = String::from_utf8_lossy(&line[..err.valid_up_to()]).chars().count() + 1;
+        anyhow!("line {}, column {}: invalid UTF-8", number, column)
+    })?;
+    if let Some((column, c)) = text.chars().enumerate().find(|(_, c)| c.is_control()) {
+        return Err(anyhow!(
+            "line {}, column {}: forbidden control character U+{:04X}",
+            number,
+            column + 1,
+            u32::from(c)
+        ));
+    }
+    Ok(text)
+}
+
+impl MediaPlaylist {
+    /// Parses a playlist from `reader`, see the [module documentation][crate::reader] for the
+    /// accepted encodings. Errors give the line and column of the offending character.
+    pub fn from_reader(mut reader: impl BufRead) -> Result<Self> {
+        if reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+            return Self::from_gzip(reader);
+        }
+        Self::from_lines(reader)
+    }
+
+    /// Parses the playlist file at `path`, see [`from_reader`][MediaPlaylist::from_reader].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        Self::from_reader(BufReader::new(file)).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    // Parses the decompressed playlist text.
+    fn from_lines(mut reader: impl BufRead) -> Result<Self> {
+        let mut parser = LineParser::new(OwnedSegments::default());
+        let mut buffer = Vec::new();
+        let mut number = 0;
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+            number += 1;
+
+            let mut line = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+            if number == 1 {
+                line = line.strip_prefix(BOM).unwrap_or(line);
+            }
+            let line = decode_line(line, number)?;
+
+            if number == 1 {
+                if line != "#EXTM3U" {
+                    return Err(anyhow!("Missing #EXTM3U header"));
+                }
+            } else {
+                parser.line(line).with_context(|| format!("line {}", number))?;
+            }
+        }
+
+        if number == 0 {
+            return Err(anyhow!("Missing #EXTM3U header"));
+        }
+        parser.finish()
+    }
+
+    #[cfg(feature = "gzip")]
+    fn from_gzip(reader: impl BufRead) -> Result<Self> {
+        Self::from_lines(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    fn from_gzip(_reader: impl BufRead) -> Result<Self> {
+        Err(anyhow!("The playlist is gzip-compressed, which needs the `gzip` feature"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYLIST: &str = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nsegment.ts\n#EXT-X-ENDLIST\n";
+
+    #[test]
+    fn accepts_bom_and_crlf() {
+        let expected = MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap();
+        let source = format!("\u{FEFF}{}", PLAYLIST.replace('\n', "\r\n"));
+        assert_eq!(MediaPlaylist::from_reader(source.as_bytes()).unwrap(), expected);
+        // no line terminator at the end
+        assert_eq!(MediaPlaylist::from_reader(PLAYLIST.trim_end().as_bytes()).unwrap(), expected);
+    }
+
+    #[test]
+    fn positions_encoding_errors() {
+        let error = MediaPlaylist::from_reader(&b"#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\ns\xC3\xA9\xFFg.ts\n"[..]).unwrap_err();
+        assert_eq!(error.to_string(), "line 4, column 3: invalid UTF-8");
+
+        let error = MediaPlaylist::from_reader("#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\tx\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 3, column 11: forbidden control character U+0009");
+
+        assert!(MediaPlaylist::from_reader(&b""[..]).is_err());
+    }
+
+    #[test]
+    fn reads_files() {
+        let path = std::env::temp_dir().join(format!("hls-reader-test-{}.m3u8", std::process::id()));
+        std::fs::write(&path, PLAYLIST).unwrap();
+        let playlist = MediaPlaylist::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(playlist.unwrap(), MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap());
+
+        let error = MediaPlaylist::from_path(&path).unwrap_err();
+        assert!(error.to_string().starts_with("opening "));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decompresses_gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(PLAYLIST.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            MediaPlaylist::from_reader(&compressed[..]).unwrap(),
+            MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap()
+        );
+    }
+}
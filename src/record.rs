@@ -0,0 +1,274 @@
+//! Recording of live media playlists into a VOD playlist.
+//!
+//! A [`Recorder`] is given every reload of a live playlist. It keeps each segment once, by
+//! media sequence number, together with its discontinuity sequence number, and every
+//! EXT-X-DATERANGE seen, so the recording can be finished into a playlist with
+//! `#EXT-X-PLAYLIST-TYPE:VOD` and `#EXT-X-ENDLIST` which plays the whole presentation. See
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-6.2.1>.
+//!
+//! Segments which slid out of the window between two reloads are a hole in the recording. They
+//! are either recorded as EXT-X-GAP segments or reported as an error, see [`HolePolicy`]. A hole
+//! larger than [`MAX_HOLE`] segments is always an error, e.g. after an encoder restart which
+//! reset the media sequence number to a much larger one.
+//!
+//! An EXT-X-DATERANGE seen again with the same ID completes the recorded one: its attributes
+//! are added to the earlier ones, see
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-4.3.2.7.1>.
+
+use core::time::Duration;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+
+use crate::live::{tracked_segments, TrackedSegment};
+use crate::locate::TimeIndex;
+use crate::media_playlist::{DateRange, MediaPlaylist, MediaSegment, OwnedSegments, PlaylistType};
+
+/// Largest number of missed segments a [`Recorder`] fills with gaps.
+pub const MAX_HOLE: u64 = 10_000;
+
+/// What a [`Recorder`] does with segments missed between two reloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HolePolicy {
+    /// Records a segment with #EXT-X-GAP and the target duration for each one missed.
+    #[default]
+    Gap,
+    /// Refuses the reload with an error.
+    Error,
+}
+
+/// Records a live stream from its reloads, see the [module documentation][crate::record].
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    policy: HolePolicy,
+    segments: BTreeMap<u64, TrackedSegment>,
+    // by order of first appearance, a later version of a range completing the earlier one
+    date_ranges: Vec<DateRange>,
+    target_duration: Duration,
+    version: u64,
+    holes: Vec<Range<u64>>,
+    ended: bool,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_holes(mut self, policy: HolePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Records a reload of the live playlist and returns the number of new segments.
+    ///
+    /// Fails, recording nothing, when segments were missed since the previous reload and the
+    /// policy is [`HolePolicy::Error`].
+    pub fn record(&mut self, playlist: &MediaPlaylist) -> Result<usize> {
+        let mut added = 0;
+        if let Some((&last, previous)) = self.segments.last_key_value() {
+            if playlist.media_sequence > last + 1 {
+                let hole = last + 1..playlist.media_sequence;
+                if self.policy == HolePolicy::Error || hole.end - hole.start > MAX_HOLE {
+                    bail!("segments {} to {} were missed between two reloads", hole.start, hole.end - 1);
+                }
+                let discontinuity_sequence = previous.discontinuity_sequence;
+                let placeholder = MediaSegment {
+                    key: previous.segment.key.clone(),
+                    map: previous.segment.map.clone(),
+                    ..Default::default()
+                };
+                for media_sequence in hole.clone() {
+                    let segment = MediaSegment {
+                        duration: playlist.target_duration,
+                        url: format!("gap-{}", media_sequence),
+                        gap: true,
+                        ..placeholder.clone()
+                    };
+                    self.segments.insert(media_sequence, TrackedSegment { media_sequence, discontinuity_sequence, segment });
+                }
+                added += hole.end - hole.start;
+                self.holes.push(hole);
+            }
+        }
+
+        for tracked in tracked_segments(playlist) {
+            if let Entry::Vacant(entry) = self.segments.entry(tracked.media_sequence) {
+                entry.insert(tracked);
+                added += 1;
+            }
+        }
+        for date_range in &playlist.date_ranges {
+            match self.date_ranges.iter_mut().find(|recorded| recorded.id == date_range.id) {
+                Some(recorded) => merge(recorded, date_range),
+                None => self.date_ranges.push(date_range.clone()),
+            }
+        }
+        self.target_duration = self.target_duration.max(playlist.target_duration);
+        self.version = self.version.max(playlist.version);
+        self.ended |= playlist.ended;
+        Ok(added as usize)
+    }
+
+    /// Media sequence numbers of the segments recorded as gaps.
+    pub fn holes(&self) -> &[Range<u64>] {
+        &self.holes
+    }
+
+    /// Whether a reload contained #EXT-X-ENDLIST.
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Number of segments recorded, gaps included.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// The VOD playlist of everything recorded.
+    pub fn finish(self) -> Result<MediaPlaylist> {
+        let Some(&media_sequence) = self.segments.keys().next() else {
+            bail!("nothing was recorded");
+        };
+        let mut segments = OwnedSegments::default();
+        for tracked in self.segments.into_values() {
+            segments.push_sequenced(tracked.segment, tracked.discontinuity_sequence);
+        }
+        let discontinuity_sequence = segments.discontinuity.first().map_or(0, |group| group.discontinuity_sequence);
+
+        let mut playlist = MediaPlaylist {
+            ended: true,
+            segments: segments.segments,
+            target_duration: self.target_duration,
+            version: self.version,
+            media_sequence,
+            discontinuity_sequence,
+            start: None,
+            server_control: None,
+            part_target: None,
+            pending_parts: 0,
+            playlist_type: Some(PlaylistType::Vod),
+            date_ranges: self.date_ranges,
+            discontinuity: segments.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        playlist.reindex();
+        Ok(playlist)
+    }
+}
+
+// Adds the attributes of a later tag with the same ID, which a server may only add to.
+fn merge(recorded: &mut DateRange, later: &DateRange) {
+    recorded.class = later.class.clone().or(recorded.class.take());
+    recorded.start_date = later.start_date;
+    recorded.end_date = later.end_date.or(recorded.end_date);
+    recorded.duration = later.duration.or(recorded.duration);
+    recorded.planned_duration = later.planned_duration.or(recorded.planned_duration);
+    recorded.end_on_next |= later.end_on_next;
+    for (name, value) in &later.attributes {
+        match recorded.attributes.iter_mut().find(|(recorded, _)| recorded == name) {
+            Some((_, recorded)) => recorded.clone_from(value),
+            None => recorded.attributes.push((name.clone(), value.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reload(source: &str) -> MediaPlaylist {
+        MediaPlaylist::parse_ext_m3u(source).expect("playlist should parse")
+    }
+
+    #[test]
+    fn records_reloads_into_a_vod_playlist() {
+        let mut recorder = Recorder::new();
+        let first = reload(indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:4
+            #EXT-X-MEDIA-SEQUENCE:10
+            #EXT-X-DISCONTINUITY-SEQUENCE:3
+            #EXT-X-DATERANGE:ID="ad",START-DATE="2024-01-01T00:00:00Z",PLANNED-DURATION=30,X-AD="a"
+            #EXTINF:4,
+            a.ts
+            #EXTINF:4,
+            b.ts
+        "#});
+        let second = reload(indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:4
+            #EXT-X-MEDIA-SEQUENCE:11
+            #EXT-X-DISCONTINUITY-SEQUENCE:3
+            #EXT-X-DATERANGE:ID="ad",START-DATE="2024-01-01T00:00:00Z",DURATION=8,X-AD="b",X-END="yes"
+            #EXTINF:4,
+            b.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:4,
+            c.ts
+            #EXT-X-ENDLIST
+        "#});
+        assert_eq!(recorder.record(&first).unwrap(), 2);
+        assert_eq!(recorder.record(&second).unwrap(), 1);
+        assert!(recorder.ended());
+
+        let vod = recorder.finish().unwrap();
+        assert_eq!(
+            vod.to_string(),
+            indoc::indoc! {r#"
+                #EXTM3U
+                #EXT-X-VERSION:3
+                #EXT-X-TARGETDURATION:4
+                #EXT-X-MEDIA-SEQUENCE:10
+                #EXT-X-PLAYLIST-TYPE:VOD
+                #EXT-X-DISCONTINUITY-SEQUENCE:3
+                #EXT-X-DATERANGE:ID="ad",START-DATE="2024-01-01T00:00:00.000+00:00",DURATION=8,PLANNED-DURATION=30,X-AD="b",X-END="yes"
+                #EXTINF:4.000,
+                a.ts
+                #EXTINF:4.000,
+                b.ts
+                #EXT-X-DISCONTINUITY
+                #EXTINF:4.000,
+                c.ts
+                #EXT-X-ENDLIST
+            "#}
+        );
+        assert_eq!(reload(&vod.to_string()), vod);
+    }
+
+    #[test]
+    fn fills_holes_with_gaps_or_fails() {
+        let first = reload("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\na.ts\n");
+        let later = reload("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:3\n#EXTINF:4,\nd.ts\n");
+
+        let mut strict = Recorder::new().with_holes(HolePolicy::Error);
+        strict.record(&first).unwrap();
+        assert!(strict.record(&later).is_err());
+        assert_eq!(strict.len(), 1);
+
+        let mut recorder = Recorder::new();
+        recorder.record(&first).unwrap();
+        assert_eq!(recorder.record(&later).unwrap(), 3);
+        assert_eq!(recorder.holes(), std::slice::from_ref(&(1..3)));
+        let vod = recorder.finish().unwrap();
+        let gaps: Vec<_> = vod.segments().iter().map(|segment| (segment.url(), segment.gap())).collect();
+        assert_eq!(gaps, [("a.ts", false), ("gap-1", true), ("gap-2", true), ("d.ts", false)]);
+        assert!(vod.to_string().contains("#EXT-X-GAP\n#EXTINF:4.000,\ngap-1\n"));
+        assert_eq!(reload(&vod.to_string()), vod);
+
+        // an encoder restart far ahead is not filled with millions of gaps
+        let mut recorder = Recorder::new();
+        recorder.record(&first).unwrap();
+        let restarted = later.to_string().replace("#EXT-X-MEDIA-SEQUENCE:3", "#EXT-X-MEDIA-SEQUENCE:3000000");
+        assert!(recorder.record(&reload(&restarted)).is_err());
+        assert_eq!(recorder.len(), 1);
+    }
+}
@@ -0,0 +1,185 @@
+//! Reload URLs with the low-latency delivery directives of
+//! <https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-6.2.5>:
+//! `_HLS_msn` and `_HLS_part` for blocking reloads, `_HLS_skip` for delta updates.
+
+use std::fmt;
+
+use crate::media_playlist::MediaPlaylist;
+
+/// How much a delta update may leave out, the value of `_HLS_skip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaUpdate {
+    /// Older segments are skipped (`YES`).
+    Segments,
+    /// Older segments and EXT-X-DATERANGE tags are skipped (`v2`).
+    SegmentsAndDateRanges,
+}
+
+impl fmt::Display for DeltaUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeltaUpdate::Segments => "YES",
+            DeltaUpdate::SegmentsAndDateRanges => "v2",
+        })
+    }
+}
+
+/// The next request for a playlist, see [`MediaPlaylist::reload_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadRequest {
+    pub url: String,
+    /// Media sequence number the server is asked to wait for, when it supports blocking
+    /// reloads.
+    pub media_sequence: Option<u64>,
+    /// Partial segment of `media_sequence` the server is asked to wait for.
+    pub part: Option<usize>,
+    /// The delta update asked for, if any.
+    pub delta_update: Option<DeltaUpdate>,
+}
+
+impl MediaPlaylist {
+    /// The delta update the server allows clients to ask for, from #EXT-X-SERVER-CONTROL.
+    ///
+    /// A client should only ask for one when its copy of the playlist is younger than half of
+    /// `CAN-SKIP-UNTIL`, which is for the caller to know.
+    pub fn delta_update(&self) -> Option<DeltaUpdate> {
+        let server_control = self.server_control?;
+        server_control.can_skip_until?;
+        Some(if server_control.can_skip_dateranges {
+            DeltaUpdate::SegmentsAndDateRanges
+        } else {
+            DeltaUpdate::Segments
+        })
+    }
+
+    /// The URL to reload this playlist from, given `url` where it was loaded from.
+    ///
+    /// When the server supports blocking reloads, the request waits for the segment after the
+    /// last one, or for its next partial segment when the playlist has partial segments. A delta
+    /// update is asked for when `delta` is set and the server supports it. The directives of
+    /// `url` are replaced, its other query parameters are kept.
+    pub fn reload_request(&self, url: &str, delta: bool) -> ReloadRequest {
+        let can_block = !self.ended && self.server_control.is_some_and(|control| control.can_block_reload);
+        let media_sequence = can_block.then(|| self.media_sequence + self.segments.len() as u64);
+        let part = media_sequence.and(self.part_target).map(|_| self.pending_parts);
+        let delta_update = if delta && !self.ended { self.delta_update() } else { None };
+
+        let mut directives = Vec::new();
+        if let Some(media_sequence) = media_sequence {
+            directives.push(format!("_HLS_msn={}", media_sequence));
+        }
+        if let Some(part) = part {
+            directives.push(format!("_HLS_part={}", part));
+        }
+        if let Some(delta_update) = delta_update {
+            directives.push(format!("_HLS_skip={}", delta_update));
+        }
+
+        ReloadRequest { url: with_directives(url, &directives), media_sequence, part, delta_update }
+    }
+}
+
+// Replaces the `_HLS_` query parameters of `url` by `directives`.
+fn with_directives(url: &str, directives: &[String]) -> String {
+    let (url, fragment) = url.split_once('#').map_or((url, None), |(url, fragment)| (url, Some(fragment)));
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    let parameters: Vec<&str> = query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty() && !parameter.starts_with("_HLS_"))
+        .chain(directives.iter().map(String::as_str))
+        .collect();
+
+    let mut reload = path.to_string();
+    if !parameters.is_empty() {
+        reload = format!("{}?{}", reload, parameters.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        reload = format!("{}#{}", reload, fragment);
+    }
+    reload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOW_LATENCY: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:9
+        #EXT-X-TARGETDURATION:4
+        #EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=24,CAN-SKIP-DATERANGES=YES,PART-HOLD-BACK=3.012,CAN-BLOCK-RELOAD=YES
+        #EXT-X-PART-INF:PART-TARGET=1.004
+        #EXT-X-MEDIA-SEQUENCE:266
+        #EXTINF:4.000,
+        fileSequence266.mp4
+        #EXT-X-PART:DURATION=1.004,URI="filePart267.0.mp4",INDEPENDENT=YES
+        #EXT-X-PART:DURATION=1.004,URI="filePart267.1.mp4"
+        #EXTINF:4.000,
+        fileSequence267.mp4
+        #EXT-X-PART:DURATION=1.004,URI="filePart268.0.mp4",INDEPENDENT=YES
+        #EXT-X-PART:DURATION=1.004,URI="filePart268.1.mp4"
+        #EXT-X-PRELOAD-HINT:TYPE=PART,URI="filePart268.2.mp4"
+    "#};
+
+    #[test]
+    fn asks_for_the_next_part_and_a_delta_update() {
+        let playlist = MediaPlaylist::parse_ext_m3u(LOW_LATENCY).unwrap();
+        let server_control = playlist.server_control().unwrap();
+        assert!(server_control.can_block_reload);
+        assert_eq!(server_control.part_hold_back, Some(core::time::Duration::from_millis(3012)));
+        assert_eq!(playlist.pending_parts(), 2);
+        assert_eq!(playlist.delta_update(), Some(DeltaUpdate::SegmentsAndDateRanges));
+
+        let request = playlist.reload_request("https://a.test/live.m3u8?token=abc&_HLS_msn=1#t", true);
+        assert_eq!(request.url, "https://a.test/live.m3u8?token=abc&_HLS_msn=268&_HLS_part=2&_HLS_skip=v2#t");
+        assert_eq!((request.media_sequence, request.part), (Some(268), Some(2)));
+
+        let request = playlist.reload_request("https://a.test/live.m3u8", false);
+        assert_eq!(request.url, "https://a.test/live.m3u8?_HLS_msn=268&_HLS_part=2");
+        assert_eq!(request.delta_update, None);
+
+        // the tags are written back, the partial segments are not
+        let written = MediaPlaylist::parse_ext_m3u(&playlist.to_string()).unwrap();
+        assert_eq!(written.server_control(), playlist.server_control());
+        assert_eq!(written.part_target(), playlist.part_target());
+    }
+
+    #[test]
+    fn counts_the_segments_a_delta_update_skipped() {
+        let delta = indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:9
+            #EXT-X-TARGETDURATION:4
+            #EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=24,CAN-BLOCK-RELOAD=YES
+            #EXT-X-MEDIA-SEQUENCE:266
+            #EXT-X-SKIP:SKIPPED-SEGMENTS=3
+            #EXTINF:4.000,
+            fileSequence269.mp4
+            #EXTINF:4.000,
+            fileSequence270.mp4
+        "#};
+        let playlist = MediaPlaylist::parse_ext_m3u(delta).unwrap();
+        assert_eq!(playlist.media_sequence(), 269);
+        let request = playlist.reload_request("https://a.test/live.m3u8?_HLS_msn=269&_HLS_skip=YES", true);
+        assert_eq!(request.url, "https://a.test/live.m3u8?_HLS_msn=271&_HLS_skip=YES");
+
+        let late_skip = delta.replace("#EXT-X-SKIP:SKIPPED-SEGMENTS=3\n", "") + "#EXT-X-SKIP:SKIPPED-SEGMENTS=3\n";
+        assert!(MediaPlaylist::parse_ext_m3u(&late_skip).is_err());
+    }
+
+    #[test]
+    fn leaves_out_what_the_server_does_not_support() {
+        let playlist = MediaPlaylist::parse_ext_m3u(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=24\n#EXTINF:4,\na.ts\n",
+        )
+        .unwrap();
+        let request = playlist.reload_request("live.m3u8?_HLS_msn=3&_HLS_part=1", true);
+        assert_eq!(request.url, "live.m3u8?_HLS_skip=YES");
+        assert_eq!(request.media_sequence, None);
+
+        let plain = MediaPlaylist::parse_ext_m3u("#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\na.ts\n").unwrap();
+        assert_eq!(plain.delta_update(), None);
+        assert_eq!(plain.reload_request("live.m3u8?a=1", true).url, "live.m3u8?a=1");
+    }
+}
@@ -0,0 +1,419 @@
+//! `Serialize` and `Deserialize` implementations, enabled by the `serde` cargo feature.
+//!
+//! The serialized shape is part of the public API and only changes with a major version. Field
+//! names are snake_case, durations are decimal seconds (e.g. `9.5`) and dates RFC 3339
+//! strings. Optional fields are left out when unset.
+//!
+//! A media playlist is its header and its discontinuity groups, each holding its segments:
+//!
+//! ```json
+//! {
+//!   "version": 3,
+//!   "target_duration": 10,
+//!   "media_sequence": 0,
+//!   "discontinuity_sequence": 0,
+//!   "ended": true,
+//!   "start": { "time_offset": -12.0, "precise": false },
+//!   "discontinuities": [
+//!     {
+//!       "discontinuity_sequence": 0,
+//!       "media_sequence": 0,
+//!       "start_time": 0,
+//!       "end_time": 9.5,
+//!       "duration": 9.5,
+//!       "program_date_time": "2020-01-01T12:00:00Z",
+//!       "segments": [
+//!         {
+//!           "duration": 9.5,
+//!           "url": "a.ts",
+//!           "program_date_time": "2020-01-01T12:00:00Z",
+//!           "key": { "method": "AES-128", "uri": "key.bin" },
+//!           "map": { "uri": "init.mp4", "byte_range": { "length": 720, "offset": 0 } },
+//!           "byte_range": { "length": 1024, "offset": 4096 },
+//!           "resolved_url": "https://cdn.example/a.ts"
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! When deserializing a media playlist, the flat segment list and the timeline fields of the
+//! groups (`media_sequence`, `start_time`, `end_time`, `program_date_time`) are rebuilt from the
+//! segments, so only `discontinuity_sequence` and `segments` are needed for every group.
+//! Low-latency playlists also have `server_control` (e.g. `{ "can_skip_until": 24,
+//! "can_skip_dateranges": false, "can_block_reload": true }`), `part_target` and a non-zero
+//! `pending_parts`. `playlist_type` is `"EVENT"` or `"VOD"`, `date_ranges` lists the
+//! EXT-X-DATERANGE tags (e.g. `{ "id": "ad1", "start_date": "2020-01-01T12:00:00Z", "duration":
+//! 30, "attributes": [["X-AD-ID", "\"42\""]] }`) and missing segments have `"gap": true`.
+//! Master playlists serialize their `version`, `variants` and `renditions` the same way.
+//!
+//! A [`DiscontinuitySegment`] on its own has no segments, only their indices in the playlist:
+//! its `segments` array is replaced by `"segment_range": { "start": 0, "end": 2 }`.
+
+use core::time::Duration;
+use std::ops::Range;
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{
+    ByteRange, DateRange, DiscontinuityGroup, DiscontinuitySegment, Key, Map, MediaPlaylist, MediaSegment, OwnedSegments,
+    PlaylistType, ServerControl, StartPoint,
+};
+
+// `with` module writing a `Duration` as decimal seconds.
+pub(crate) mod seconds {
+    use core::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if duration.subsec_nanos() == 0 {
+            serializer.serialize_u64(duration.as_secs())
+        } else {
+            serializer.serialize_f64(duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let seconds = f64::deserialize(deserializer)?;
+        if !seconds.is_finite() || seconds < 0.0 || seconds > u64::MAX as f64 / 1e9 {
+            return Err(D::Error::custom(format!("invalid duration {}", seconds)));
+        }
+        // rounded to the nanosecond, `Duration::from_secs_f64` truncates which doesn't round trip
+        Ok(Duration::from_nanos((seconds * 1e9).round() as u64))
+    }
+}
+
+// `with` module for an optional `Duration`, see `seconds`.
+pub(crate) mod optional_seconds {
+    use core::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::seconds::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        #[derive(Deserialize)]
+        struct Seconds(#[serde(with = "super::seconds")] Duration);
+
+        Ok(Option::<Seconds>::deserialize(deserializer)?.map(|Seconds(duration)| duration))
+    }
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Serialize)]
+struct SegmentRepr<'a> {
+    #[serde(with = "seconds")]
+    duration: Duration,
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program_date_time: Option<DateTime<FixedOffset>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a Key>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    map: Option<&'a Map>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    byte_range: Option<ByteRange>,
+    #[serde(skip_serializing_if = "is_false")]
+    gap: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_url: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct MediaSegmentData {
+    #[serde(with = "seconds")]
+    duration: Duration,
+    url: String,
+    #[serde(default)]
+    program_date_time: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    key: Option<Key>,
+    #[serde(default)]
+    map: Option<Map>,
+    #[serde(default)]
+    byte_range: Option<ByteRange>,
+    #[serde(default)]
+    gap: bool,
+    #[serde(default)]
+    resolved_url: Option<String>,
+}
+
+impl Serialize for MediaSegment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SegmentRepr {
+            duration: self.duration,
+            url: &self.url,
+            program_date_time: self.program_date_time,
+            key: self.key.as_ref(),
+            map: self.map.as_ref(),
+            byte_range: self.byte_range,
+            gap: self.gap,
+            resolved_url: self.resolved_url.as_deref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaSegment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = MediaSegmentData::deserialize(deserializer)?;
+        Ok(MediaSegment {
+            duration: data.duration,
+            url: data.url,
+            program_date_time: data.program_date_time,
+            key: data.key,
+            map: data.map,
+            byte_range: data.byte_range,
+            gap: data.gap,
+            resolved_url: data.resolved_url,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct DiscontinuityGroupRef<'a> {
+    discontinuity_sequence: u64,
+    media_sequence: u64,
+    #[serde(with = "seconds")]
+    start_time: Duration,
+    #[serde(with = "seconds")]
+    end_time: Duration,
+    #[serde(with = "seconds")]
+    duration: Duration,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program_date_time: Option<DateTime<FixedOffset>>,
+    segments: &'a [MediaSegment],
+}
+
+impl Serialize for DiscontinuityGroup<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DiscontinuityGroupRef {
+            discontinuity_sequence: self.discontinuity_sequence,
+            media_sequence: self.media_sequence,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            duration: self.discontinuity_duration,
+            program_date_time: self.program_date_time,
+            segments: self.discontinuity_segments(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiscontinuitySegmentData {
+    discontinuity_sequence: u64,
+    media_sequence: u64,
+    #[serde(with = "seconds")]
+    start_time: Duration,
+    #[serde(with = "seconds")]
+    end_time: Duration,
+    #[serde(with = "seconds")]
+    duration: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    program_date_time: Option<DateTime<FixedOffset>>,
+    segment_range: Range<usize>,
+}
+
+impl Serialize for DiscontinuitySegment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DiscontinuitySegmentData {
+            discontinuity_sequence: self.discontinuity_sequence,
+            media_sequence: self.media_sequence,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            duration: self.discontinuity_duration,
+            program_date_time: self.program_date_time,
+            segment_range: self.segments.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DiscontinuitySegment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = DiscontinuitySegmentData::deserialize(deserializer)?;
+        Ok(DiscontinuitySegment {
+            discontinuity_duration: data.duration,
+            segments: data.segment_range,
+            discontinuity_sequence: data.discontinuity_sequence,
+            start_time: data.start_time,
+            end_time: data.end_time,
+            media_sequence: data.media_sequence,
+            program_date_time: data.program_date_time,
+        })
+    }
+}
+
+// A group inside a serialized playlist, only what is needed to rebuild it.
+#[derive(Deserialize)]
+struct GroupData {
+    discontinuity_sequence: u64,
+    segments: Vec<MediaSegment>,
+}
+
+#[derive(Serialize)]
+struct PlaylistRepr<'a> {
+    version: u64,
+    #[serde(with = "seconds")]
+    target_duration: Duration,
+    media_sequence: u64,
+    discontinuity_sequence: u64,
+    ended: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<StartPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_control: Option<ServerControl>,
+    #[serde(with = "optional_seconds", skip_serializing_if = "Option::is_none")]
+    part_target: Option<Duration>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pending_parts: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    playlist_type: Option<PlaylistType>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    date_ranges: &'a [DateRange],
+    discontinuities: Vec<DiscontinuityGroup<'a>>,
+}
+
+#[derive(Deserialize)]
+struct MediaPlaylistData {
+    version: u64,
+    #[serde(with = "seconds")]
+    target_duration: Duration,
+    #[serde(default)]
+    media_sequence: u64,
+    #[serde(default)]
+    discontinuity_sequence: u64,
+    #[serde(default)]
+    ended: bool,
+    #[serde(default)]
+    start: Option<StartPoint>,
+    #[serde(default)]
+    server_control: Option<ServerControl>,
+    #[serde(default, with = "optional_seconds")]
+    part_target: Option<Duration>,
+    #[serde(default)]
+    pending_parts: usize,
+    #[serde(default)]
+    playlist_type: Option<PlaylistType>,
+    #[serde(default)]
+    date_ranges: Vec<DateRange>,
+    discontinuities: Vec<GroupData>,
+}
+
+impl Serialize for MediaPlaylist {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PlaylistRepr {
+            version: self.version,
+            target_duration: self.target_duration,
+            media_sequence: self.media_sequence,
+            discontinuity_sequence: self.discontinuity_sequence,
+            ended: self.ended,
+            start: self.start,
+            server_control: self.server_control,
+            part_target: self.part_target,
+            pending_parts: self.pending_parts,
+            playlist_type: self.playlist_type,
+            date_ranges: &self.date_ranges,
+            discontinuities: self.discontinuity_groups().collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaPlaylist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = MediaPlaylistData::deserialize(deserializer)?;
+        let mut groups = OwnedSegments::default();
+        for group in data.discontinuities {
+            for (index, segment) in group.segments.into_iter().enumerate() {
+                groups.push_segment(segment, group.discontinuity_sequence, index == 0);
+            }
+        }
+        let mut playlist = MediaPlaylist {
+            ended: data.ended,
+            segments: groups.segments,
+            target_duration: data.target_duration,
+            version: data.version,
+            media_sequence: data.media_sequence,
+            discontinuity_sequence: data.discontinuity_sequence,
+            start: data.start,
+            server_control: data.server_control,
+            part_target: data.part_target,
+            pending_parts: data.pending_parts,
+            playlist_type: data.playlist_type,
+            date_ranges: data.date_ranges,
+            discontinuity: groups.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        playlist.reindex();
+        Ok(playlist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYLIST: &str = indoc::indoc! {r#"
+        #EXTM3U
+        #EXT-X-VERSION:6
+        #EXT-X-TARGETDURATION:10
+        #EXT-X-MEDIA-SEQUENCE:7
+        #EXT-X-DISCONTINUITY-SEQUENCE:2
+        #EXT-X-START:TIME-OFFSET=-12.5
+        #EXT-X-KEY:METHOD=AES-128,URI="key.bin"
+        #EXT-X-MAP:URI="init.mp4",BYTERANGE="720@0"
+        #EXT-X-PROGRAM-DATE-TIME:2020-01-01T12:00:00.000Z
+        #EXTINF:9.009,
+        a.ts
+        #EXT-X-DISCONTINUITY
+        #EXTINF:4,
+        b.ts
+        #EXT-X-ENDLIST
+    "#};
+
+    #[test]
+    fn round_trips_through_json() {
+        let playlist = MediaPlaylist::parse_ext_m3u_with_base(PLAYLIST, "https://cdn.example/index.m3u8").unwrap();
+        let json = serde_json::to_string(&playlist).unwrap();
+        assert_eq!(serde_json::from_str::<MediaPlaylist>(&json).unwrap(), playlist);
+    }
+
+    #[test]
+    fn writes_durations_as_seconds() {
+        let playlist = MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap();
+        let json = serde_json::to_value(&playlist).unwrap();
+        assert_eq!(json["target_duration"], serde_json::json!(10));
+        assert_eq!(json["start"], serde_json::json!({ "time_offset": -12.5, "precise": false }));
+
+        let group = &json["discontinuities"][1];
+        assert_eq!(group["discontinuity_sequence"], 3);
+        assert_eq!(group["media_sequence"], 8);
+        assert_eq!(group["duration"], serde_json::json!(4));
+        assert_eq!(group["segments"][0]["duration"], serde_json::json!(4));
+        assert_eq!(group["segments"][0]["url"], "b.ts");
+        assert!(group["segments"][0].get("program_date_time").is_none());
+
+        let first = &json["discontinuities"][0]["segments"][0];
+        assert!((first["duration"].as_f64().unwrap() - 9.009).abs() < 1e-6);
+        assert_eq!(first["program_date_time"], "2020-01-01T12:00:00Z");
+        assert_eq!(first["map"]["byte_range"], serde_json::json!({ "length": 720, "offset": 0 }));
+    }
+}
@@ -0,0 +1,489 @@
+//! A local HLS origin for development and tests.
+//!
+//! [`Server`] serves the files of a directory over HTTP/1.1, answering `Range` requests with 206.
+//! A [`ServeConfig`] adds live streams, simulated from a VOD playlist of the directory by a
+//! [`LiveSimulator`], and faults: error statuses, slow responses and stale playlists.
+//!
+//! The config file has one directive per line, `#` starting a comment:
+//!
+//! ```text
+//! # /live.m3u8 shows 3 segments of vod/index.m3u8 at a time
+//! live /live.m3u8 vod/index.m3u8 window=3
+//! # starting over at the end of the VOD
+//! live /channel.m3u8 vod/index.m3u8 window=5 loop
+//! # the first 2 requests for a .ts file are answered after 1.5 seconds
+//! fault *.ts delay=1.5 times=2
+//! fault /vod/segment5.ts status=404
+//! # the live playlist lags 8 seconds behind
+//! fault /live.m3u8 stale=8
+//! ```
+//!
+//! A fault path may contain one `*`, matching any characters. The first fault matching a request
+//! applies to it.
+
+use core::time::Duration;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::media_playlist::{parse_seconds, MediaPlaylist};
+use crate::simulator::LiveSimulator;
+
+/// A live stream simulated by a [`Server`], from a `live` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveStream {
+    /// Request path of the live playlist, e.g. `/live.m3u8`.
+    pub path: String,
+    /// The VOD playlist to reveal, relative to the served directory.
+    pub source: String,
+    /// Number of segments in the live playlist.
+    pub window: usize,
+    /// Whether the VOD starts over at its end, see [`LiveSimulator::with_looping`].
+    pub looping: bool,
+}
+
+/// A fault injected by a [`Server`], from a `fault` directive.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Fault {
+    /// Request paths the fault applies to, with at most one `*` wildcard.
+    pub path: String,
+    /// Status to answer with instead of the resource, e.g. 404 or 503.
+    pub status: Option<u16>,
+    /// How long to wait before answering.
+    pub delay: Duration,
+    /// How far behind a live playlist is served.
+    pub stale: Duration,
+    /// Number of requests the fault applies to, all of them when `None`.
+    pub times: Option<usize>,
+}
+
+/// What a [`Server`] serves besides the files, see the [module documentation][crate::serve] for
+/// the file format.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ServeConfig {
+    pub live: Vec<LiveStream>,
+    pub faults: Vec<Fault>,
+}
+
+impl ServeConfig {
+    pub fn parse(config: &str) -> Result<Self> {
+        let mut parsed = ServeConfig::default();
+        for (index, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else { continue };
+            let directive = parse_directive(directive, &mut words, &mut parsed);
+            directive.with_context(|| format!("line {}", index + 1))?;
+        }
+        Ok(parsed)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&config).with_context(|| format!("parsing {}", path.display()))
+    }
+}
+
+fn parse_directive<'a>(directive: &str, words: &mut impl Iterator<Item = &'a str>, config: &mut ServeConfig) -> Result<()> {
+    let path = words.next().ok_or_else(|| anyhow!("{}: missing path", directive))?.to_string();
+    if !path.starts_with('/') && !path.starts_with('*') {
+        return Err(anyhow!("{}: the path must start with '/'", directive));
+    }
+    let seconds = |value: &str| {
+        parse_seconds(value).ok_or_else(|| anyhow!("{}: expecting a number of seconds, got '{}'", directive, value))
+    };
+    match directive {
+        "live" => {
+            let source = words.next().ok_or_else(|| anyhow!("live: missing VOD playlist"))?.to_string();
+            let mut live = LiveStream { path, source, window: 3, looping: false };
+            for option in words {
+                match option.split_once('=') {
+                    None if option == "loop" => live.looping = true,
+                    Some(("window", value)) => {
+                        live.window = value.parse().ok().filter(|window| *window > 0).ok_or_else(|| anyhow!("live: invalid window '{}'", value))?
+                    }
+                    _ => return Err(anyhow!("live: unknown option '{}'", option)),
+                }
+            }
+            config.live.push(live);
+        }
+        "fault" => {
+            let mut fault = Fault { path, ..Fault::default() };
+            for option in words {
+                match option.split_once('=') {
+                    Some(("status", value)) => fault.status = Some(value.parse().map_err(|_| anyhow!("fault: invalid status '{}'", value))?),
+                    Some(("delay", value)) => fault.delay = seconds(value)?,
+                    Some(("stale", value)) => fault.stale = seconds(value)?,
+                    Some(("times", value)) => fault.times = Some(value.parse().map_err(|_| anyhow!("fault: invalid times '{}'", value))?),
+                    _ => return Err(anyhow!("fault: unknown option '{}'", option)),
+                }
+            }
+            config.faults.push(fault);
+        }
+        other => return Err(anyhow!("unknown directive '{}'", other)),
+    }
+    Ok(())
+}
+
+/// An answer of a [`Server`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// How long to wait before sending the response.
+    pub delay: Duration,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response { status, ..Default::default() }
+    }
+
+    fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves a directory, see the [module documentation][crate::serve].
+#[derive(Debug)]
+pub struct Server {
+    root: PathBuf,
+    // the streams with the simulator of their VOD playlist, its URIs made absolute paths
+    live: Vec<(LiveStream, LiveSimulator)>,
+    // the faults with the number of requests they matched
+    faults: Vec<(Fault, AtomicUsize)>,
+}
+
+impl Server {
+    /// Serves the files of `root`, nothing more.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Server { root: root.into(), live: Vec::new(), faults: Vec::new() }
+    }
+
+    /// Serves the files of `root` and what `config` describes. The VOD playlists of the live
+    /// streams are read right away.
+    pub fn with_config(root: impl Into<PathBuf>, config: ServeConfig) -> Result<Self> {
+        let mut server = Self::new(root);
+        for stream in config.live {
+            let mut vod = MediaPlaylist::from_path(server.root.join(&stream.source))?;
+            // the live playlist may be served from another directory than the VOD one
+            vod.resolve_uris(&format!("/{}", stream.source.trim_start_matches('/')));
+            for segment in &mut vod.segments {
+                segment.url = segment.resolved_url.take().unwrap_or_default();
+                if let Some(key) = &mut segment.key {
+                    key.uri = key.resolved_uri.take();
+                }
+                if let Some(map) = &mut segment.map {
+                    map.uri = map.resolved_uri.take().unwrap_or_default();
+                }
+            }
+            let simulator = LiveSimulator::new(vod, stream.window).with_looping(stream.looping);
+            server.live.push((stream, simulator));
+        }
+        server.faults = config.faults.into_iter().map(|fault| (fault, AtomicUsize::new(0))).collect();
+        Ok(server)
+    }
+
+    /// The answer to a GET request for `path`, `elapsed` after the server started. `range` is the
+    /// value of the Range header, only single ranges are supported.
+    pub fn respond(&self, path: &str, range: Option<&str>, elapsed: Duration) -> Response {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let fault = self
+            .faults
+            .iter()
+            .find(|(fault, requests)| {
+                matches(&fault.path, path) && fault.times.is_none_or(|times| requests.fetch_add(1, Ordering::Relaxed) < times)
+            })
+            .map(|(fault, _)| fault);
+
+        let mut response = match (fault.and_then(|fault| fault.status), self.live.iter().find(|(stream, _)| stream.path == path)) {
+            (Some(status), _) => Response::new(status),
+            (None, Some((_, simulator))) => {
+                let elapsed = elapsed.saturating_sub(fault.map_or(Duration::ZERO, |fault| fault.stale));
+                let playlist = simulator.snapshot_at(elapsed);
+                let mut response = Response::new(200).with_header("Content-Type", "application/vnd.apple.mpegurl");
+                response.body = playlist.to_string().into_bytes();
+                response
+            }
+            (None, None) => self.file(path, range),
+        };
+        response.delay = fault.map_or(Duration::ZERO, |fault| fault.delay);
+        response
+    }
+
+    fn file(&self, path: &str, range: Option<&str>) -> Response {
+        let relative = path.trim_start_matches('/');
+        if !path.starts_with('/') || relative.split('/').any(|part| part == "..") {
+            return Response::new(404);
+        }
+        let file = self.root.join(relative);
+        let Some(body) = fs::read(&file).ok().filter(|_| file.is_file()) else {
+            return Response::new(404);
+        };
+        let content_type = match file.extension().and_then(|extension| extension.to_str()) {
+            Some("m3u8") => "application/vnd.apple.mpegurl",
+            Some("ts") => "video/mp2t",
+            Some("mp4" | "m4s") => "video/mp4",
+            Some("aac") => "audio/aac",
+            Some("vtt") => "text/vtt",
+            _ => "application/octet-stream",
+        };
+        let response = Response::new(200).with_header("Content-Type", content_type).with_header("Accept-Ranges", "bytes");
+        let length = body.len() as u64;
+        match range.map(|range| parse_range(range, length)) {
+            None => Response { body, ..response },
+            Some(Some((first, last))) => Response {
+                status: 206,
+                body: body[first as usize..=last as usize].to_vec(),
+                ..response.with_header("Content-Range", format!("bytes {}-{}/{}", first, last, length))
+            },
+            Some(None) => Response::new(416).with_header("Content-Range", format!("bytes */{}", length)),
+        }
+    }
+
+    /// Starts answering on `addr` in a background thread, each connection in its own thread.
+    /// Live streams start from their first window now.
+    pub fn bind(self, addr: impl ToSocketAddrs) -> Result<RunningServer> {
+        let listener = TcpListener::bind(addr).context("binding the server")?;
+        let local_addr = listener.local_addr()?;
+        let server = Arc::new(self);
+        let started = Instant::now();
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                std::thread::spawn(move || {
+                    // the client went away, nothing to do about it
+                    let _ = server.answer(stream, started);
+                });
+            }
+        });
+        Ok(RunningServer { local_addr, thread })
+    }
+
+    // Reads one request from `stream` and answers it, closing the connection.
+    fn answer(&self, mut stream: TcpStream, started: Instant) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut range = None;
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("range") {
+                    range = Some(value.trim().to_string());
+                }
+            }
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+        let mut response = match method {
+            "GET" | "HEAD" => self.respond(path, range.as_deref(), started.elapsed()),
+            _ => Response::new(405).with_header("Allow", "GET, HEAD"),
+        };
+        std::thread::sleep(response.delay);
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+        head += &format!("Content-Length: {}\r\nConnection: close\r\n", response.body.len());
+        for (name, value) in &response.headers {
+            head += &format!("{}: {}\r\n", name, value);
+        }
+        if method == "HEAD" {
+            response.body.clear();
+        }
+        stream.write_all(format!("{}\r\n", head).as_bytes())?;
+        stream.write_all(&response.body)?;
+        Ok(())
+    }
+}
+
+/// A [`Server`] answering in the background, see [`Server::bind`].
+#[derive(Debug)]
+pub struct RunningServer {
+    local_addr: SocketAddr,
+    thread: JoinHandle<()>,
+}
+
+impl RunningServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// `http://<address>`, to which request paths are appended.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.local_addr)
+    }
+
+    /// Blocks the current thread for as long as the server runs.
+    pub fn wait(self) {
+        let _ = self.thread.join();
+    }
+}
+
+// Whether `path` matches `pattern`, which has at most one `*`.
+fn matches(pattern: &str, path: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => path.len() >= prefix.len() + suffix.len() && path.starts_with(prefix) && path.ends_with(suffix),
+        None => pattern == path,
+    }
+}
+
+// First and last byte of `bytes=<first>-[<last>]` or `bytes=-<suffix length>` in a resource of
+// `length` bytes, `None` when not satisfiable.
+fn parse_range(range: &str, length: u64) -> Option<(u64, u64)> {
+    let (first, last) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (first, last) = match (first.trim(), last.trim()) {
+        ("", suffix) => (length.checked_sub(suffix.parse().ok()?)?, length.checked_sub(1)?),
+        (first, "") => (first.parse().ok()?, length.checked_sub(1)?),
+        (first, last) => (first.parse().ok()?, last.parse::<u64>().ok()?.min(length.checked_sub(1)?)),
+    };
+    (first <= last).then_some((first, last))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    const VOD: &str = indoc::indoc! {"
+        #EXTM3U
+        #EXT-X-VERSION:3
+        #EXT-X-TARGETDURATION:4
+        #EXTINF:4,
+        a.ts
+        #EXTINF:4,
+        b.ts
+        #EXT-X-DISCONTINUITY
+        #EXTINF:4,
+        c.ts
+        #EXTINF:4,
+        d.ts
+        #EXT-X-ENDLIST
+    "};
+
+    fn served_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hls-serve-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("vod")).unwrap();
+        fs::write(dir.join("vod/index.m3u8"), VOD).unwrap();
+        fs::write(dir.join("vod/a.ts"), "0123456789").unwrap();
+        dir
+    }
+
+    fn live_playlist(server: &Server, seconds: u64) -> MediaPlaylist {
+        let response = server.respond("/live.m3u8", None, Duration::from_secs(seconds));
+        MediaPlaylist::parse_ext_m3u(std::str::from_utf8(&response.body).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serves_files_and_byte_ranges_over_http() {
+        let dir = served_dir("files");
+        let server = Server::new(&dir).bind("127.0.0.1:0").unwrap();
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("GET /vod/a.ts?token=1 HTTP/1.1\r\nRange: bytes=2-5\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"), "{}", response);
+        assert!(response.contains("Content-Range: bytes 2-5/10\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\n2345"), "{}", response);
+        assert!(get("GET /vod/a.ts HTTP/1.1\r\n\r\n").ends_with("\r\n\r\n0123456789"));
+        assert!(get("GET /vod/../../etc/passwd HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+
+        let server = Server::new(&dir);
+        assert_eq!(server.respond("/vod/a.ts", Some("bytes=-3"), Duration::ZERO).body, b"789");
+        assert_eq!(server.respond("/vod/a.ts", Some("bytes=10-"), Duration::ZERO).status, 416);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reveals_a_vod_playlist_in_a_sliding_window() {
+        let dir = served_dir("live");
+        let config = ServeConfig::parse("live /live.m3u8 vod/index.m3u8 window=2\nlive /loop.m3u8 vod/index.m3u8 loop\n").unwrap();
+        assert!(config.live[1].looping);
+        let server = Server::with_config(&dir, config).unwrap();
+
+        let start = live_playlist(&server, 3);
+        let urls: Vec<_> = start.segments().iter().map(|segment| segment.url()).collect();
+        assert_eq!(urls, ["/vod/a.ts", "/vod/b.ts"]);
+        assert!(!start.ended());
+
+        let later = live_playlist(&server, 4);
+        assert_eq!((later.media_sequence(), later.discontinuity_sequence()), (1, 0));
+        assert_eq!(later.discontinuity().len(), 2);
+
+        let end = live_playlist(&server, 9);
+        assert_eq!((end.media_sequence(), end.discontinuity_sequence()), (2, 1));
+        assert_eq!(end.segments()[0].url(), "/vod/c.ts");
+        assert!(end.ended());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn injects_faults() {
+        let dir = served_dir("faults");
+        let config = ServeConfig::parse(indoc::indoc! {"
+            live /live.m3u8 vod/index.m3u8 window=2
+            fault /live.m3u8 stale=8  # two segments behind
+            fault /vod/*.ts status=503 delay=0.5 times=1
+        "})
+        .unwrap();
+        assert_eq!(config.faults[1], Fault {
+            path: "/vod/*.ts".to_string(),
+            status: Some(503),
+            delay: Duration::from_millis(500),
+            stale: Duration::ZERO,
+            times: Some(1),
+        });
+        let server = Server::with_config(&dir, config).unwrap();
+
+        assert_eq!(live_playlist(&server, 9).media_sequence(), 0);
+        let failed = server.respond("/vod/a.ts", None, Duration::ZERO);
+        assert_eq!((failed.status, failed.delay), (503, Duration::from_millis(500)));
+        assert_eq!(server.respond("/vod/a.ts", None, Duration::ZERO).status, 200);
+        assert_eq!(server.respond("/vod/index.m3u8", None, Duration::ZERO).status, 200);
+
+        let error = ServeConfig::parse("fault /a.ts\nfault /b.ts speed=2\n").unwrap_err();
+        assert_eq!(format!("{:#}", error), "line 2: fault: unknown option 'speed=2'");
+        let error = ServeConfig::parse("fault /a.ts delay=-1\n").unwrap_err();
+        assert_eq!(format!("{:#}", error), "line 1: fault: expecting a number of seconds, got '-1'");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
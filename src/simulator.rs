@@ -0,0 +1,229 @@
+//! Live playlists simulated from a VOD playlist.
+//!
+//! [`LiveSimulator`] plays a VOD playlist as a live stream would publish it: the first `window`
+//! segments are there from the start, then each next segment is added once its duration went
+//! by, and the oldest one slides out. MEDIA-SEQUENCE counts the segments gone, and
+//! DISCONTINUITY-SEQUENCE the EXT-X-DISCONTINUITY tags gone with them, see
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-6.2.2>.
+//!
+//! A looping simulator starts the VOD over when it reaches its end, with a discontinuity at each
+//! wrap, and never ends.
+
+use core::time::Duration;
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{MediaPlaylist, OwnedSegments};
+
+/// A playlist published by a [`LiveSimulator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveSnapshot {
+    /// When the playlist was published, from the start of the simulation.
+    pub time: Duration,
+    pub playlist: MediaPlaylist,
+}
+
+/// Simulates a live stream from a VOD playlist, see the [module documentation][crate::simulator].
+///
+/// As an iterator it yields every playlist published, ending with the one that has
+/// #EXT-X-ENDLIST. A looping simulator never ends.
+#[derive(Debug, Clone)]
+pub struct LiveSimulator {
+    vod: MediaPlaylist,
+    window: usize,
+    looping: bool,
+    // index of the discontinuity group of every VOD segment
+    groups: Vec<u64>,
+    // state of the iterator: segments published so far, counting those of previous loops, and
+    // when the next playlist is
+    published: usize,
+    time: Duration,
+    done: bool,
+}
+
+impl LiveSimulator {
+    /// Simulates `vod` with `window` segments (at least one) in each playlist.
+    pub fn new(vod: MediaPlaylist, window: usize) -> Self {
+        let mut groups = Vec::with_capacity(vod.segments.len());
+        for (index, group) in vod.discontinuity.iter().enumerate() {
+            groups.extend(group.segments.clone().map(|_| index as u64));
+        }
+        let window = window.max(1);
+        let published = window.min(vod.segments.len());
+        LiveSimulator { vod, window, looping: false, groups, published, time: Duration::ZERO, done: false }
+    }
+
+    /// Starts the VOD over at its end, with a discontinuity. A VOD without segments, or whose
+    /// segments add up to no time, does not loop: a lap would never end.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping && !self.vod.duration().is_zero();
+        self.published = if self.looping { self.window } else { self.window.min(self.vod.segments.len()) };
+        self
+    }
+
+    pub fn vod(&self) -> &MediaPlaylist {
+        &self.vod
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The playlist published last at `elapsed` from the start of the simulation.
+    pub fn snapshot_at(&self, elapsed: Duration) -> MediaPlaylist {
+        let mut published = if self.looping { self.window } else { self.window.min(self.vod.segments.len()) };
+        let mut time = Duration::ZERO;
+        while !self.is_complete(published) {
+            time += self.vod.segments[published % self.vod.segments.len()].duration;
+            if time > elapsed {
+                break;
+            }
+            published += 1;
+        }
+        self.playlist(published)
+    }
+
+    fn is_complete(&self, published: usize) -> bool {
+        !self.looping && published == self.vod.segments.len()
+    }
+
+    // The playlist once `published` segments were published.
+    fn playlist(&self, published: usize) -> MediaPlaylist {
+        let vod = &self.vod;
+        let first = published.saturating_sub(self.window);
+        let loop_groups = vod.discontinuity.len() as u64;
+        let loop_duration = chrono::Duration::from_std(vod.duration()).unwrap_or_default();
+
+        let mut segments = OwnedSegments::default();
+        for position in first..published {
+            let (lap, index) = (position / vod.segments.len(), position % vod.segments.len());
+            let mut segment = vod.segments[index].clone();
+            // the clock keeps going from one loop to the next
+            segment.program_date_time = segment.program_date_time.map(|date| date + loop_duration * lap as i32);
+            let discontinuity_sequence = vod.discontinuity_sequence + lap as u64 * loop_groups + self.groups[index];
+            segments.push_sequenced(segment, discontinuity_sequence);
+        }
+        let discontinuity_sequence = segments
+            .discontinuity
+            .first()
+            .map_or(vod.discontinuity_sequence, |group| group.discontinuity_sequence);
+
+        let mut playlist = MediaPlaylist {
+            ended: self.is_complete(published),
+            segments: segments.segments,
+            target_duration: vod.target_duration,
+            version: vod.version,
+            media_sequence: vod.media_sequence + first as u64,
+            discontinuity_sequence,
+            start: None,
+            server_control: None,
+            part_target: None,
+            pending_parts: 0,
+            // a live playlist has no type, and keeps every date range
+            playlist_type: None,
+            date_ranges: vod.date_ranges.clone(),
+            discontinuity: segments.discontinuity,
+            time_index: TimeIndex::default(),
+        };
+        playlist.reindex();
+        playlist
+    }
+}
+
+impl Iterator for LiveSimulator {
+    type Item = LiveSnapshot;
+
+    fn next(&mut self) -> Option<LiveSnapshot> {
+        if self.done {
+            return None;
+        }
+        let snapshot = LiveSnapshot { time: self.time, playlist: self.playlist(self.published) };
+        if self.is_complete(self.published) {
+            self.done = true;
+        } else {
+            self.time += self.vod.segments[self.published % self.vod.segments.len()].duration;
+            self.published += 1;
+        }
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VOD: &str = indoc::indoc! {"
+        #EXTM3U
+        #EXT-X-VERSION:3
+        #EXT-X-TARGETDURATION:4
+        #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:00Z
+        #EXTINF:4,
+        a.ts
+        #EXTINF:2,
+        b.ts
+        #EXT-X-DISCONTINUITY
+        #EXTINF:4,
+        c.ts
+        #EXT-X-ENDLIST
+    "};
+
+    fn urls(playlist: &MediaPlaylist) -> Vec<&str> {
+        playlist.segments().iter().map(|segment| segment.url()).collect()
+    }
+
+    #[test]
+    fn slides_the_window_until_the_end() {
+        let vod = MediaPlaylist::parse_ext_m3u(VOD).unwrap();
+        let snapshots: Vec<_> = LiveSimulator::new(vod, 2).collect();
+        let summary: Vec<_> = snapshots
+            .iter()
+            .map(|snapshot| {
+                let playlist = &snapshot.playlist;
+                (snapshot.time.as_secs(), urls(playlist), playlist.media_sequence(), playlist.discontinuity_sequence(), playlist.ended())
+            })
+            .collect();
+        assert_eq!(summary, [(0, vec!["a.ts", "b.ts"], 0, 0, false), (4, vec!["b.ts", "c.ts"], 1, 0, true)]);
+
+        // the written playlist keeps the discontinuity and reads back the same
+        let last = &snapshots[1].playlist;
+        assert!(last.to_string().contains("#EXT-X-DISCONTINUITY\n"));
+        assert_eq!(&MediaPlaylist::parse_ext_m3u(&last.to_string()).unwrap(), last);
+
+        let single = LiveSimulator::new(MediaPlaylist::parse_ext_m3u(VOD).unwrap(), 1);
+        let last = single.last().unwrap();
+        assert_eq!((last.time.as_secs(), last.playlist.media_sequence(), last.playlist.discontinuity_sequence()), (6, 2, 1));
+    }
+
+    #[test]
+    fn loops_with_a_discontinuity_at_each_wrap() {
+        let vod = MediaPlaylist::parse_ext_m3u(VOD).unwrap();
+        let simulator = LiveSimulator::new(vod, 2).with_looping(true);
+        let snapshots: Vec<_> = simulator.clone().take(5).collect();
+
+        let wrapped = &snapshots[2].playlist;
+        assert_eq!(urls(wrapped), ["c.ts", "a.ts"]);
+        assert_eq!((wrapped.media_sequence(), wrapped.discontinuity_sequence()), (2, 1));
+        assert_eq!(wrapped.discontinuity().len(), 2);
+        assert!(!wrapped.ended());
+        let program_date_time = wrapped.segments()[1].program_date_time().unwrap();
+        assert_eq!(program_date_time.to_rfc3339(), "2024-01-01T00:00:10+00:00");
+
+        // two loops later, the first discontinuity of the second loop slid out
+        let later = &snapshots[4].playlist;
+        assert_eq!(urls(later), ["b.ts", "c.ts"]);
+        assert_eq!((later.media_sequence(), later.discontinuity_sequence()), (4, 2));
+
+        for snapshot in &snapshots {
+            assert_eq!(simulator.snapshot_at(snapshot.time), snapshot.playlist);
+        }
+    }
+
+    #[test]
+    fn does_not_loop_without_duration() {
+        let vod = MediaPlaylist::parse_ext_m3u("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:1\n#EXTINF:0,\na.ts\n#EXT-X-ENDLIST\n").unwrap();
+        let simulator = LiveSimulator::new(vod, 2).with_looping(true);
+        let playlist = simulator.snapshot_at(Duration::from_secs(10));
+        assert_eq!(urls(&playlist), ["a.ts"]);
+        assert!(playlist.ended());
+        assert_eq!(simulator.count(), 1);
+    }
+}
@@ -0,0 +1,175 @@
+//! Push-based parsing of media playlists arriving in chunks, e.g. from a socket.
+//!
+//! [`StreamingParser`] takes the bytes as they come, whatever their boundaries, and hands back
+//! an event for every tag and segment as soon as its line is complete. It runs the same line
+//! parser as [`MediaPlaylist::parse_ext_m3u`], so [`finish`][StreamingParser::finish] gives
+//! exactly the playlist the batch parser would.
+
+use anyhow::{anyhow, Result};
+
+use crate::media_playlist::{LineKind, LineParser, MediaPlaylist, MediaSegment, OwnedSegments};
+
+/// A complete line of the playlist, see [`StreamingParser::feed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseEvent {
+    /// A tag line other than #EXTINF, e.g. `#EXT-X-DISCONTINUITY`, once it has been applied.
+    Tag(String),
+    /// A media segment, complete with the URI line after its #EXTINF.
+    Segment {
+        segment: Box<MediaSegment>,
+        media_sequence: u64,
+        discontinuity_sequence: u64,
+    },
+}
+
+/// Incremental media playlist parser, see the [module documentation][self].
+///
+/// After `feed` returns an error the playlist is invalid and the parser should be dropped.
+#[derive(Debug)]
+pub struct StreamingParser {
+    // bytes of the line being received, decoded once the line is complete so characters split
+    // across chunks are fine
+    pending: Vec<u8>,
+    // number of complete lines so far
+    lines: usize,
+    parser: LineParser<OwnedSegments>,
+}
+
+impl Default for StreamingParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingParser {
+    pub fn new() -> Self {
+        StreamingParser { pending: Vec::new(), lines: 0, parser: LineParser::new(OwnedSegments::default()) }
+    }
+
+    /// Parses the complete lines in `bytes`, along with the end of the line left over by the
+    /// previous call, and returns their events in playlist order. Lines end with LF or CRLF.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<ParseEvent>> {
+        let mut events = Vec::new();
+        let mut rest = bytes;
+        while let Some(newline) = rest.iter().position(|byte| *byte == b'\n') {
+            let event = if self.pending.is_empty() {
+                self.line(&rest[..newline])?
+            } else {
+                let mut line = std::mem::take(&mut self.pending);
+                line.extend_from_slice(&rest[..newline]);
+                self.line(&line)?
+            };
+            events.extend(event);
+            rest = &rest[newline + 1..];
+        }
+        self.pending.extend_from_slice(rest);
+        Ok(events)
+    }
+
+    /// Parses the last line, if it has no line terminator, and builds the playlist. Events of
+    /// that last line are not reported.
+    pub fn finish(mut self) -> Result<MediaPlaylist> {
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.line(&line)?;
+        }
+        if self.lines == 0 {
+            return Err(anyhow!("Missing #EXTM3U header"));
+        }
+        self.parser.finish()
+    }
+
+    fn line(&mut self, line: &[u8]) -> Result<Option<ParseEvent>> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.lines += 1;
+        let line = std::str::from_utf8(line).map_err(|_| anyhow!("Invalid UTF-8 on line {}", self.lines))?;
+
+        if self.lines == 1 {
+            return match line {
+                "#EXTM3U" => Ok(None),
+                _ => Err(anyhow!("Missing #EXTM3U header")),
+            };
+        }
+
+        Ok(match self.parser.line(line)? {
+            LineKind::Tag => Some(ParseEvent::Tag(line.to_string())),
+            LineKind::Segment => {
+                let index = self.parser.segment_count - 1;
+                Some(ParseEvent::Segment {
+                    segment: Box::new(self.parser.store.segments[index].clone()),
+                    media_sequence: self.parser.media_sequence + index as u64,
+                    // only an EXT-X-DISCONTINUITY after the segment bumps it
+                    discontinuity_sequence: self.parser.discontinuity_number,
+                })
+            }
+            LineKind::Other => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYLIST: &str = indoc::indoc! {"
+        #EXTM3U
+        #EXT-X-VERSION:3
+        #EXT-X-TARGETDURATION:10
+        #EXT-X-MEDIA-SEQUENCE:4
+        #EXT-X-PROGRAM-DATE-TIME:2020-01-01T12:00:00.000Z
+        #EXTINF:9.5,
+        première.ts
+        #EXT-X-DISCONTINUITY
+        #EXTINF:4,
+        b.ts
+        #EXT-X-ENDLIST
+    "};
+
+    #[test]
+    fn matches_the_batch_parser_whatever_the_chunks() {
+        let expected = MediaPlaylist::parse_ext_m3u(PLAYLIST).unwrap();
+        let crlf = PLAYLIST.replace('\n', "\r\n");
+        for source in [PLAYLIST, crlf.as_str()] {
+            for chunk_size in [1, 2, 3, 7, 64, source.len()] {
+                let mut parser = StreamingParser::new();
+                for chunk in source.as_bytes().chunks(chunk_size) {
+                    parser.feed(chunk).unwrap();
+                }
+                assert_eq!(parser.finish().unwrap(), expected, "chunks of {} bytes", chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn emits_events_as_lines_complete() {
+        let mut parser = StreamingParser::new();
+        let (head, tail) = PLAYLIST.split_at(PLAYLIST.find("b.ts").unwrap() + 2);
+        let events = parser.feed(head.as_bytes()).unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], ParseEvent::Tag("#EXT-X-VERSION:3".to_string()));
+        match &events[4] {
+            ParseEvent::Segment { segment, media_sequence, discontinuity_sequence } => {
+                assert_eq!(segment.url(), "première.ts");
+                assert!(segment.program_date_time().is_some());
+                assert_eq!((*media_sequence, *discontinuity_sequence), (4, 0));
+            }
+            event => panic!("expected a segment, got {:?}", event),
+        }
+        assert_eq!(events[5], ParseEvent::Tag("#EXT-X-DISCONTINUITY".to_string()));
+
+        let events = parser.feed(tail.as_bytes()).unwrap();
+        assert!(matches!(&events[0], ParseEvent::Segment { media_sequence: 5, discontinuity_sequence: 1, .. }));
+        assert_eq!(events[1], ParseEvent::Tag("#EXT-X-ENDLIST".to_string()));
+        assert!(parser.finish().unwrap().ended());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(StreamingParser::new().feed(b"#EXTM3X\n").is_err());
+        assert!(StreamingParser::new().finish().is_err());
+
+        let mut parser = StreamingParser::new();
+        parser.feed(b"#EXTM3U\n#EXT-X-VERSION:3\n#EXTINF:4,\n").unwrap();
+        assert!(parser.feed(b"\xff.ts\n").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! A minimal HTTP/1.1 origin on a local port for the tests of the HTTP client code, serving
+//! canned responses by path. A `Range: bytes=a-b` request to a 200 route is answered with 206
+//! and the slice of the body.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use crate::fetch::HttpResponse;
+
+/// A request received by a [`StubServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StubRequest {
+    pub(crate) path: String,
+    /// Value of the Range header.
+    pub(crate) range: Option<String>,
+}
+
+pub(crate) struct StubServer {
+    pub(crate) url: String,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+    pub(crate) fn start(routes: Vec<(&'static str, HttpResponse)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut range = None;
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("range") {
+                            range = Some(value.trim().to_string());
+                        }
+                    }
+                    header.clear();
+                }
+
+                let path = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                let not_found = HttpResponse { status: 404, ..Default::default() };
+                let route = routes.iter().find(|(route, _)| *route == path).map_or(&not_found, |(_, r)| r);
+                let response = match &range {
+                    Some(range) if route.status == 200 => slice(route, range),
+                    _ => route.clone(),
+                };
+                log.lock().unwrap().push(StubRequest { path, range });
+
+                let mut head = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in &response.headers {
+                    head += &format!("{}: {}\r\n", name, value);
+                }
+                stream.write_all(format!("{}\r\n", head).as_bytes()).unwrap();
+                stream.write_all(&response.body).unwrap();
+            }
+        });
+        StubServer { url, requests }
+    }
+
+    pub(crate) fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub(crate) fn ok(body: impl Into<Vec<u8>>, headers: &[(&str, &str)]) -> HttpResponse {
+    HttpResponse {
+        status: 200,
+        headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        body: body.into(),
+    }
+}
+
+// The 206 answer to `bytes=<first>-[<last>]`.
+fn slice(response: &HttpResponse, range: &str) -> HttpResponse {
+    let (first, last) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+    let first: usize = first.parse().unwrap();
+    let last: usize = last.parse().unwrap_or(response.body.len() - 1).min(response.body.len() - 1);
+    let mut partial = response.clone();
+    partial.status = 206;
+    partial.body = response.body[first..=last].to_vec();
+    let content_range = format!("bytes {}-{}/{}", first, last, response.body.len());
+    partial.headers.push(("Content-Range".to_string(), content_range));
+    partial
+}
@@ -0,0 +1,115 @@
+//! Position of every discontinuity group on the presentation timeline.
+//!
+//! Media timestamps restart (or jump) at every EXT-X-DISCONTINUITY, so a player needs to know
+//! where each group begins on the presentation timeline to offset the timestamps of its
+//! samples: the first sample of a group plays at [`DiscontinuitySegment::start_time`]. See
+//! <https://datatracker.ietf.org/doc/html/rfc8216#section-6.3.3>.
+
+use core::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::locate::TimeIndex;
+use crate::media_playlist::{DiscontinuityGroup, DiscontinuitySegment, MediaPlaylist, MediaSegment};
+
+impl DiscontinuitySegment {
+    /// Time at which the group starts, measured from the start of the playlist.
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// Time at which the group ends. Unlike `discontinuity_duration` this is not rounded to
+    /// milliseconds, so it is exactly the start time of the next group.
+    pub fn end_time(&self) -> Duration {
+        self.end_time
+    }
+
+    /// Media sequence number of the first segment of the group.
+    pub fn first_media_sequence(&self) -> u64 {
+        self.media_sequence
+    }
+
+    /// Media sequence number of the last segment of the group.
+    pub fn last_media_sequence(&self) -> u64 {
+        self.media_sequence + self.segments.len().saturating_sub(1) as u64
+    }
+
+    /// Wall clock time of the start of the group. Taken from the first EXT-X-PROGRAM-DATE-TIME
+    /// inside the group, moved back to the first segment; `None` when the group has none since
+    /// a date from another group doesn't carry over a discontinuity.
+    pub fn program_date_time(&self) -> Option<DateTime<FixedOffset>> {
+        self.program_date_time
+    }
+}
+
+impl MediaPlaylist {
+    /// The discontinuity group playing at `time`, measured from the start of the playlist.
+    pub fn discontinuity_at(&self, time: Duration) -> Option<DiscontinuityGroup<'_>> {
+        self.locate(time)
+            .and_then(|position| self.discontinuity_group(position.discontinuity_index))
+    }
+
+    // Rebuilds everything derived from the segments: the seek index and the timeline of the
+    // discontinuity groups. Called whenever a playlist is built or its segments change.
+    pub(crate) fn reindex(&mut self) {
+        index_groups(&mut self.discontinuity, &self.segments, self.media_sequence);
+        self.time_index = TimeIndex::new(&self.segments, &self.discontinuity);
+    }
+}
+
+// Fills in the timeline fields of consecutive groups of `segments`, the first one starting at time
+// zero with `media_sequence`.
+pub(crate) fn index_groups(groups: &mut [DiscontinuitySegment], segments: &[MediaSegment], media_sequence: u64) {
+    let mut time = Duration::ZERO;
+    let mut media_sequence = media_sequence;
+    for group in groups {
+        group.start_time = time;
+        group.media_sequence = media_sequence;
+        group.program_date_time = None;
+
+        for segment in &segments[group.segments.clone()] {
+            if let (None, Some(program_date_time)) = (group.program_date_time, segment.program_date_time) {
+                let offset = chrono::Duration::from_std(time - group.start_time).unwrap_or_default();
+                group.program_date_time = Some(program_date_time - offset);
+            }
+            time += segment.duration;
+        }
+
+        group.end_time = time;
+        media_sequence += group.segments.len() as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchors_groups_on_their_program_date_time() {
+        let playlist = MediaPlaylist::parse_ext_m3u(indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:20
+            #EXTINF:10,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXTINF:4,
+            b.ts
+            #EXT-X-PROGRAM-DATE-TIME:2020-01-01T12:00:04.000Z
+            #EXTINF:6,
+            c.ts
+        "})
+        .unwrap();
+
+        let group = playlist.discontinuity_at(Duration::from_secs(15)).unwrap();
+        assert_eq!(group.start_time(), Duration::from_secs(10));
+        assert_eq!(group.end_time(), Duration::from_secs(20));
+        assert_eq!(group.first_media_sequence(), 21);
+        assert_eq!(group.last_media_sequence(), 22);
+        assert_eq!(group.program_date_time().unwrap().to_rfc3339(), "2020-01-01T12:00:00+00:00");
+
+        assert_eq!(playlist.discontinuity[0].program_date_time(), None);
+        assert!(playlist.discontinuity_at(Duration::from_secs(20)).is_none());
+    }
+}
@@ -0,0 +1,447 @@
+//! Reading of MPEG-TS segments, to measure how long they really play.
+//!
+//! [`demux`] walks the 188-byte packets of a transport stream (ISO/IEC 13818-1): the PAT gives
+//! the PIDs of the PMTs, which give the elementary streams, and the PES headers at the start of
+//! each PES packet carry the presentation and decoding timestamps. Only the headers are read,
+//! the media data is skipped, as are the streams of sections such as SCTE-35 cues.
+//!
+//! [`verify_segments`] compares the #EXTINF duration of the segments of a playlist with the
+//! duration measured from their timestamps, since a wrong #EXTINF makes players drift.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::media_playlist::MediaPlaylist;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+/// Timestamps count ticks of a 90 kHz clock.
+pub const CLOCK_RATE: u64 = 90_000;
+// timestamps are 33-bit and wrap around
+const TIMESTAMP_WRAP: u64 = 1 << 33;
+
+/// The timestamps of an elementary stream of a transport stream, in [`CLOCK_RATE`] ticks.
+/// Timestamps go on past 2^33 rather than wrapping around.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StreamTimestamps {
+    pub pid: u16,
+    /// From the PMT, e.g. 0x1B for H.264 video and 0x0F for AAC audio.
+    pub stream_type: u8,
+    pub first_pts: Option<u64>,
+    pub last_pts: Option<u64>,
+    pub first_dts: Option<u64>,
+    pub last_dts: Option<u64>,
+    // every PTS, to find the earliest and latest ones when frames are reordered
+    pts: Vec<u64>,
+}
+
+impl StreamTimestamps {
+    /// Whether the stream is video, from its stream type.
+    pub fn is_video(&self) -> bool {
+        matches!(self.stream_type, 0x01 | 0x02 | 0x10 | 0x1B | 0x24 | 0x42)
+    }
+
+    // Whether the stream is made of sections rather than PES packets, e.g. SCTE-35 cues (0x86),
+    // which have no timestamps to read.
+    fn carries_sections(&self) -> bool {
+        matches!(self.stream_type, 0x05 | 0x0A..=0x0D | 0x86)
+    }
+
+    /// How long the stream plays: from its earliest to its latest PTS, plus the duration of the
+    /// last frame, taken as the smallest step between two timestamps.
+    pub fn duration(&self) -> Option<Duration> {
+        let mut pts = self.pts.clone();
+        pts.sort_unstable();
+        pts.dedup();
+        let (first, last) = (*pts.first()?, *pts.last()?);
+        let frame = pts.windows(2).map(|pair| pair[1] - pair[0]).min().unwrap_or_default();
+        Some(ticks(last - first + frame))
+    }
+
+    fn push(&mut self, pts: Option<u64>, dts: Option<u64>) {
+        if let Some(pts) = pts {
+            let pts = unwrap_timestamp(self.last_pts, pts);
+            self.first_pts.get_or_insert(pts);
+            self.last_pts = Some(pts);
+            self.pts.push(pts);
+        }
+        if let Some(dts) = dts {
+            let dts = unwrap_timestamp(self.last_dts, dts);
+            self.first_dts.get_or_insert(dts);
+            self.last_dts = Some(dts);
+        }
+    }
+}
+
+/// The elementary streams of a transport stream, see [`demux`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TransportStream {
+    /// By PID.
+    pub streams: Vec<StreamTimestamps>,
+}
+
+impl TransportStream {
+    /// How long the segment plays: the duration of its video stream, or of its longest stream
+    /// when it has no video.
+    pub fn duration(&self) -> Option<Duration> {
+        match self.streams.iter().find(|stream| stream.is_video()) {
+            Some(video) => video.duration(),
+            None => self.streams.iter().filter_map(StreamTimestamps::duration).max(),
+        }
+    }
+}
+
+/// Reads the PAT, the PMTs and the PES headers of a transport stream.
+pub fn demux(bytes: &[u8]) -> Result<TransportStream> {
+    if !bytes.len().is_multiple_of(PACKET_SIZE) {
+        bail!("{} bytes is not a whole number of {}-byte packets", bytes.len(), PACKET_SIZE);
+    }
+    let mut pmt_pids = Vec::new();
+    let mut streams: BTreeMap<u16, StreamTimestamps> = BTreeMap::new();
+
+    for (index, packet) in bytes.chunks_exact(PACKET_SIZE).enumerate() {
+        if packet[0] != SYNC_BYTE {
+            bail!("lost sync at packet {}", index);
+        }
+        let payload_start = packet[1] & 0x40 != 0;
+        let pid = u16::from_be_bytes([packet[1] & 0x1F, packet[2]]);
+        let adaptation_field = packet[3] & 0x20 != 0;
+        let has_payload = packet[3] & 0x10 != 0;
+        let offset = if adaptation_field { 5 + packet[4] as usize } else { 4 };
+        if !has_payload || !payload_start || offset >= PACKET_SIZE {
+            continue;
+        }
+        let payload = &packet[offset..];
+        let context = || format!("packet {}, PID {:#x}", index, pid);
+
+        if pid == 0 {
+            pmt_pids = parse_pat(payload).with_context(context)?;
+        } else if pmt_pids.contains(&pid) {
+            for (stream_pid, stream_type) in parse_pmt(payload).with_context(context)? {
+                let stream = streams.entry(stream_pid).or_default();
+                stream.pid = stream_pid;
+                stream.stream_type = stream_type;
+            }
+        } else if let Some(stream) = streams.get_mut(&pid).filter(|stream| !stream.carries_sections()) {
+            let (pts, dts) = parse_pes_header(payload).with_context(context)?;
+            stream.push(pts, dts);
+        }
+    }
+    Ok(TransportStream { streams: streams.into_values().collect() })
+}
+
+/// Same as [`demux`] for the file at `path`.
+pub fn demux_file(path: impl AsRef<Path>) -> Result<TransportStream> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    demux(&bytes).with_context(|| format!("demuxing {}", path.display()))
+}
+
+/// A segment whose #EXTINF duration is off, see [`verify_segments`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentDeviation {
+    pub media_sequence: u64,
+    pub url: String,
+    pub path: PathBuf,
+    /// From #EXTINF.
+    pub declared: Duration,
+    /// From the timestamps of the segment.
+    pub measured: Duration,
+}
+
+impl SegmentDeviation {
+    pub fn deviation(&self) -> Duration {
+        self.declared.abs_diff(self.measured)
+    }
+}
+
+/// Measures the segments of `playlist`, saved in `segment_dir`, and lists those whose #EXTINF
+/// duration differs from the measured one by more than `tolerance`.
+///
+/// A segment is looked up at its URL relative to `segment_dir`, or by its file name when the
+/// URL is absolute. The byte range of a segment is read from its file when it has one.
+pub fn verify_segments(
+    playlist: &MediaPlaylist,
+    segment_dir: impl AsRef<Path>,
+    tolerance: Duration,
+) -> Result<Vec<SegmentDeviation>> {
+    let segment_dir = segment_dir.as_ref();
+    let mut deviations = Vec::new();
+    for (segment, media_sequence) in playlist.segments.iter().zip(playlist.media_sequence..) {
+        if segment.gap {
+            continue;
+        }
+        let path = local_path(segment_dir, &segment.url);
+        let bytes = fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        let bytes = match segment.byte_range {
+            Some(range) => {
+                let start = range.offset.unwrap_or_default();
+                range
+                    .end()
+                    .and_then(|end| bytes.get(usize::try_from(start).ok()?..usize::try_from(end).ok()?))
+                    .ok_or_else(|| anyhow!("{} is shorter than the byte range {}", path.display(), range))?
+            }
+            None => &bytes[..],
+        };
+        let stream = demux(bytes).with_context(|| format!("demuxing {}", path.display()))?;
+        let measured = stream.duration().ok_or_else(|| anyhow!("{} has no timestamp", path.display()))?;
+
+        let declared = segment.duration;
+        let deviation = SegmentDeviation { media_sequence, url: segment.url.clone(), path, declared, measured };
+        if deviation.deviation() > tolerance {
+            deviations.push(deviation);
+        }
+    }
+    Ok(deviations)
+}
+
+// Where a segment is saved in `segment_dir`.
+fn local_path(segment_dir: &Path, url: &str) -> PathBuf {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    if path.contains("://") {
+        segment_dir.join(path.rsplit('/').next().unwrap_or_default())
+    } else {
+        segment_dir.join(path)
+    }
+}
+
+// The section of a PSI payload starting a section, after the pointer field, checked for
+// `table_id` and cut to its length without the CRC.
+fn section(payload: &[u8], table_id: u8) -> Result<&[u8]> {
+    let pointer = *payload.first().ok_or_else(|| anyhow!("empty section"))? as usize;
+    let section =
+        payload.get(1 + pointer..).filter(|section| section.len() >= 3).ok_or_else(|| anyhow!("truncated section"))?;
+    if section[0] != table_id {
+        bail!("expecting table {:#x}, found {:#x}", table_id, section[0]);
+    }
+    let length = u16::from_be_bytes([section[1] & 0x0F, section[2]]) as usize;
+    if length < 9 || 3 + length > section.len() {
+        bail!("section of {} bytes doesn't fit in a packet", length);
+    }
+    Ok(&section[..3 + length - 4])
+}
+
+// The PIDs of the PMTs, from the program association table.
+fn parse_pat(payload: &[u8]) -> Result<Vec<u16>> {
+    let section = section(payload, 0x00)?;
+    Ok(section[8..]
+        .chunks_exact(4)
+        // program 0 is the network information table
+        .filter(|program| program[0] != 0 || program[1] != 0)
+        .map(|program| u16::from_be_bytes([program[2] & 0x1F, program[3]]))
+        .collect())
+}
+
+// The PID and stream type of each elementary stream, from a program map table.
+fn parse_pmt(payload: &[u8]) -> Result<Vec<(u16, u8)>> {
+    let section = section(payload, 0x02)?;
+    if section.len() < 12 {
+        bail!("truncated PMT");
+    }
+    let program_info_length = u16::from_be_bytes([section[10] & 0x0F, section[11]]) as usize;
+    let mut rest = section.get(12 + program_info_length..).ok_or_else(|| anyhow!("truncated PMT"))?;
+    let mut streams = Vec::new();
+    while rest.len() >= 5 {
+        let stream_type = rest[0];
+        let pid = u16::from_be_bytes([rest[1] & 0x1F, rest[2]]);
+        let es_info_length = u16::from_be_bytes([rest[3] & 0x0F, rest[4]]) as usize;
+        streams.push((pid, stream_type));
+        rest = rest.get(5 + es_info_length..).ok_or_else(|| anyhow!("truncated PMT"))?;
+    }
+    Ok(streams)
+}
+
+// The PTS and DTS of a PES packet header.
+fn parse_pes_header(payload: &[u8]) -> Result<(Option<u64>, Option<u64>)> {
+    if !payload.starts_with(&[0x00, 0x00, 0x01]) {
+        bail!("missing PES start code");
+    }
+    // stream ids without the optional header, e.g. padding_stream
+    if payload.len() < 9 || matches!(payload[3], 0xBC | 0xBE | 0xBF | 0xF0 | 0xF1 | 0xF2 | 0xF8 | 0xFF) {
+        return Ok((None, None));
+    }
+    let flags = payload[7] >> 6;
+    let timestamp = |offset: usize| {
+        payload.get(offset..offset + 5).map(read_timestamp).ok_or_else(|| anyhow!("truncated PES header"))
+    };
+    let pts = if flags & 0b10 != 0 { Some(timestamp(9)?) } else { None };
+    let dts = if flags == 0b11 { Some(timestamp(14)?) } else { None };
+    Ok((pts, dts))
+}
+
+// A 33-bit timestamp spread over 5 bytes with marker bits.
+fn read_timestamp(bytes: &[u8]) -> u64 {
+    (u64::from((bytes[0] >> 1) & 0x07) << 30)
+        | (u64::from(bytes[1]) << 22)
+        | (u64::from(bytes[2] >> 1) << 15)
+        | (u64::from(bytes[3]) << 7)
+        | u64::from(bytes[4] >> 1)
+}
+
+// Carries `timestamp` over to the wrap count of `previous` or the next one, whichever puts it
+// closest to `previous`.
+fn unwrap_timestamp(previous: Option<u64>, timestamp: u64) -> u64 {
+    let Some(previous) = previous else {
+        return timestamp;
+    };
+    let base = previous - previous % TIMESTAMP_WRAP;
+    [base.saturating_sub(TIMESTAMP_WRAP), base, base + TIMESTAMP_WRAP]
+        .into_iter()
+        .map(|base| base + timestamp)
+        .min_by_key(|candidate| candidate.abs_diff(previous))
+        .unwrap_or(timestamp)
+}
+
+fn ticks(ticks: u64) -> Duration {
+    Duration::from_nanos((ticks as u128 * 1_000_000_000 / CLOCK_RATE as u128) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media_playlist::ByteRange;
+
+    const VIDEO_PID: u16 = 0x100;
+    const AUDIO_PID: u16 = 0x101;
+
+    fn packet(pid: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![SYNC_BYTE, 0x40 | (pid >> 8) as u8, pid as u8, 0x10];
+        packet.extend_from_slice(payload);
+        packet.resize(PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    fn psi(table_id: u8, header: [u8; 5], body: &[u8]) -> Vec<u8> {
+        let length = 5 + body.len() + 4;
+        let mut payload = vec![0, table_id, 0xB0 | (length >> 8) as u8, length as u8];
+        payload.extend_from_slice(&header);
+        payload.extend_from_slice(body);
+        // the CRC isn't checked
+        payload.extend_from_slice(&[0; 4]);
+        payload
+    }
+
+    fn timestamp(marker: u8, value: u64) -> [u8; 5] {
+        [
+            marker << 4 | ((value >> 30) as u8 & 0x07) << 1 | 1,
+            (value >> 22) as u8,
+            ((value >> 15) as u8) << 1 | 1,
+            (value >> 7) as u8,
+            (value as u8) << 1 | 1,
+        ]
+    }
+
+    fn pes(stream_id: u8, pts: u64, dts: Option<u64>) -> Vec<u8> {
+        let mut header = vec![0, 0, 1, stream_id, 0, 0, 0x80];
+        match dts {
+            Some(dts) => {
+                header.extend_from_slice(&[0xC0, 10]);
+                header.extend_from_slice(&timestamp(0b0011, pts));
+                header.extend_from_slice(&timestamp(0b0001, dts));
+            }
+            None => {
+                header.extend_from_slice(&[0x80, 5]);
+                header.extend_from_slice(&timestamp(0b0010, pts));
+            }
+        }
+        header
+    }
+
+    // A segment of `frames` video frames at 29.97 fps, reordered as I P B, and AAC audio.
+    fn segment(first_pts: u64, frames: u64) -> Vec<u8> {
+        let mut bytes = packet(0, &psi(0x00, [0, 1, 0xC1, 0, 0], &[0, 1, 0xF0, 0x00]));
+        let streams = [0x1B, 0xE1, 0x00, 0xF0, 0x00, 0x0F, 0xE1, 0x01, 0xF0, 0x00];
+        bytes.extend(packet(0x1000, &psi(0x02, [0, 1, 0xC1, 0, 0], &[[0xE1, 0x00, 0xF0, 0x00].as_slice(), &streams].concat())));
+        let frame = 3003;
+        for index in 0..frames {
+            // decoding order 0, 2, 1, 4, 3...
+            let shown = match index {
+                0 => 0,
+                index if index % 2 == 1 => (index + 1).min(frames - 1),
+                index => index - 1,
+            };
+            let pts = (first_pts + shown * frame) % TIMESTAMP_WRAP;
+            let dts = (first_pts + TIMESTAMP_WRAP + index * frame - frame) % TIMESTAMP_WRAP;
+            bytes.extend(packet(VIDEO_PID, &pes(0xE0, pts, Some(dts))));
+        }
+        for index in 0..frames * frame / 1920 {
+            bytes.extend(packet(AUDIO_PID, &pes(0xC0, (first_pts + index * 1920) % TIMESTAMP_WRAP, None)));
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_timestamps_of_each_stream() {
+        let stream = demux(&segment(900_000, 90)).unwrap();
+        let summary: Vec<_> = stream.streams.iter().map(|stream| (stream.pid, stream.stream_type)).collect();
+        assert_eq!(summary, [(VIDEO_PID, 0x1B), (AUDIO_PID, 0x0F)]);
+        let video = &stream.streams[0];
+        assert_eq!((video.first_pts, video.first_dts), (Some(900_000), Some(900_000 - 3003)));
+        assert_eq!(video.last_dts, Some(900_000 + 88 * 3003));
+        // 90 frames at 29.97 fps
+        assert_eq!(stream.duration(), Some(Duration::from_nanos(3_003_000_000)));
+
+        // timestamps carry on across the 33-bit wrap
+        let wrapped = demux(&segment(TIMESTAMP_WRAP - 30 * 3003, 90)).unwrap();
+        assert_eq!(wrapped.duration(), Some(Duration::from_nanos(3_003_000_000)));
+        assert_eq!(wrapped.streams[0].last_dts, Some(TIMESTAMP_WRAP + 58 * 3003));
+
+        let mut corrupt = segment(0, 2);
+        corrupt[PACKET_SIZE] = 0;
+        assert!(demux(&corrupt).is_err());
+
+        // a PMT section with the shortest length a section may have, 9 bytes
+        let error = parse_pmt(&[0x00, 0x02, 0xB0, 0x09, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err();
+        assert_eq!(error.to_string(), "truncated PMT");
+    }
+
+    #[test]
+    fn skips_streams_of_sections() {
+        // the PMT of `segment` with a SCTE-35 stream on PID 0x102, and a splice_null cue on it
+        let mut bytes = packet(0, &psi(0x00, [0, 1, 0xC1, 0, 0], &[0, 1, 0xF0, 0x00]));
+        let streams = [0x1B, 0xE1, 0x00, 0xF0, 0x00, 0x0F, 0xE1, 0x01, 0xF0, 0x00, 0x86, 0xE1, 0x02, 0xF0, 0x00];
+        bytes.extend(packet(0x1000, &psi(0x02, [0, 1, 0xC1, 0, 0], &[[0xE1, 0x00, 0xF0, 0x00].as_slice(), &streams].concat())));
+        bytes.extend(packet(0x102, &psi(0xFC, [0, 0, 0, 0, 0], &[0, 0xFF, 0xF0, 0x00, 0x00])));
+        bytes.extend_from_slice(&segment(0, 90)[2 * PACKET_SIZE..]);
+
+        let stream = demux(&bytes).unwrap();
+        let summary: Vec<_> = stream.streams.iter().map(|stream| (stream.pid, stream.stream_type)).collect();
+        assert_eq!(summary, [(VIDEO_PID, 0x1B), (AUDIO_PID, 0x0F), (0x102, 0x86)]);
+        assert_eq!(stream.streams[2].first_pts, None);
+        assert_eq!(stream.duration(), Some(Duration::from_nanos(3_003_000_000)));
+    }
+
+    #[test]
+    fn lists_segments_whose_extinf_is_off() {
+        let dir = std::env::temp_dir().join(format!("hls-ts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.ts"), segment(0, 90)).unwrap();
+        fs::write(dir.join("b.ts"), segment(90 * 3003, 60)).unwrap();
+        let playlist = MediaPlaylist::parse_ext_m3u(indoc::indoc! {"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:3
+            #EXTINF:3.003,
+            a.ts
+            #EXTINF:3.003,
+            https://cdn.example/b.ts?token=1
+            #EXT-X-ENDLIST
+        "})
+        .unwrap();
+
+        let deviations = verify_segments(&playlist, &dir, Duration::from_millis(50)).unwrap();
+        let summary: Vec<_> = deviations.iter().map(|deviation| (deviation.media_sequence, deviation.measured)).collect();
+        assert_eq!(summary, [(1, Duration::from_nanos(2_002_000_000))]);
+        assert_eq!(deviations[0].path, dir.join("b.ts"));
+        assert!(verify_segments(&playlist, dir.join("missing"), Duration::from_millis(50)).is_err());
+
+        let mut overflowing = playlist.clone();
+        overflowing.segments[0].byte_range = Some(ByteRange { length: u64::MAX, offset: Some(1) });
+        assert!(verify_segments(&overflowing, &dir, Duration::from_millis(50)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! URI reference resolution following [RFC 3986 section 5.2][rfc], used to turn the relative
+//! URIs of a playlist into absolute ones given the location of the playlist. No network
+//! access is involved, this is string manipulation only.
+//!
+//! [rfc]: https://datatracker.ietf.org/doc/html/rfc3986#section-5.2
+
+// The five components of a URI reference, see
+// <https://datatracker.ietf.org/doc/html/rfc3986#appendix-B>. Undefined and empty components
+// are different things for the resolution algorithm, hence the options.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Components<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> Components<'a> {
+    fn parse(reference: &'a str) -> Self {
+        let (rest, fragment) = match reference.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (reference, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+
+        // a scheme is letters/digits/+-. before the first ':', which must come before any '/'
+        let (scheme, rest) = match rest.find(':') {
+            Some(colon)
+                if colon > 0
+                    && rest[..colon].starts_with(|c: char| c.is_ascii_alphabetic())
+                    && rest[..colon]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) =>
+            {
+                (Some(&rest[..colon]), &rest[colon + 1..])
+            }
+            _ => (None, rest),
+        };
+
+        let (authority, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let end = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..end]), &rest[end..])
+            }
+            None => (None, rest),
+        };
+
+        Components { scheme, authority, path, query, fragment }
+    }
+}
+
+// <https://datatracker.ietf.org/doc/html/rfc3986#section-5.2.4>
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if let Some(rest) = input.strip_prefix("../").or_else(|| input.strip_prefix("./")) {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            // move the first segment, with its leading '/' if any, to the output
+            let start = usize::from(input.starts_with('/'));
+            let end = input[start..].find('/').map_or(input.len(), |index| index + start);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
+
+// Relative paths (e.g. playlists referenced by a local path) have nothing to climb out of, so
+// unlike `remove_dot_segments` their leading `..` segments are kept.
+fn normalize_relative(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    while let Some(segment) = parts.next() {
+        let last = parts.peek().is_none();
+        match segment {
+            "." => {}
+            ".." if segments.last().is_some_and(|previous| *previous != "..") => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+        if last && matches!(segment, "." | "..") {
+            segments.push("");
+        }
+    }
+    segments.join("/")
+}
+
+// <https://datatracker.ietf.org/doc/html/rfc3986#section-5.2.3>
+fn merge(base: &Components, reference_path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        return format!("/{}", reference_path);
+    }
+    let directory = base.path.rfind('/').map_or("", |index| &base.path[..=index]);
+    format!("{}{}", directory, reference_path)
+}
+
+/// Resolves `reference` (e.g. a segment URI) against `base` (e.g. the URL the playlist was
+/// loaded from), following <https://datatracker.ietf.org/doc/html/rfc3986#section-5.2.2>.
+///
+/// `base` should be absolute, but a relative path such as `videos/index.m3u8` is accepted too,
+/// which gives a path relative to the same place as `base`.
+pub fn resolve(base: &str, reference: &str) -> String {
+    let base = Components::parse(base);
+    let reference = Components::parse(reference);
+
+    let target = if reference.scheme.is_some() {
+        Components { path: "", ..reference.clone() }
+    } else if reference.authority.is_some() {
+        Components { scheme: base.scheme, path: "", ..reference.clone() }
+    } else if reference.path.is_empty() {
+        Components {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: "",
+            query: reference.query.or(base.query),
+            fragment: reference.fragment,
+        }
+    } else {
+        Components { scheme: base.scheme, authority: base.authority, path: "", ..reference.clone() }
+    };
+
+    let path = if reference.scheme.is_some() || reference.authority.is_some() || reference.path.starts_with('/') {
+        remove_dot_segments(reference.path)
+    } else if reference.path.is_empty() {
+        base.path.to_string()
+    } else {
+        let merged = merge(&base, reference.path);
+        if merged.starts_with('/') {
+            remove_dot_segments(&merged)
+        } else {
+            normalize_relative(&merged)
+        }
+    };
+
+    // <https://datatracker.ietf.org/doc/html/rfc3986#section-5.3>
+    let mut resolved = String::new();
+    if let Some(scheme) = target.scheme {
+        resolved.push_str(scheme);
+        resolved.push(':');
+    }
+    if let Some(authority) = target.authority {
+        resolved.push_str("//");
+        resolved.push_str(authority);
+    }
+    resolved.push_str(&path);
+    if let Some(query) = target.query {
+        resolved.push('?');
+        resolved.push_str(query);
+    }
+    if let Some(fragment) = target.fragment {
+        resolved.push('#');
+        resolved.push_str(fragment);
+    }
+    resolved
+}
+
+// Splits `uri` into its origin (`scheme://authority`, empty for plain paths) and path.
+fn split_origin(uri: &str) -> (&str, &str) {
+    match uri.find("://") {
+        Some(scheme_end) => {
+            let authority_end = uri[scheme_end + 3..]
+                .find('/')
+                .map_or(uri.len(), |index| scheme_end + 3 + index);
+            uri.split_at(authority_end)
+        }
+        None => ("", uri),
+    }
+}
+
+// Directory part of a path, including the trailing `/`.
+fn directory(path: &str) -> &str {
+    path.rfind('/').map_or("", |index| &path[..=index])
+}
+
+/// Expresses `target` relative to the playlist at `base`. Falls back to `target` itself when
+/// both live on different origins.
+pub(crate) fn relative(base: &str, target: &str) -> String {
+    let (base_origin, base_path) = split_origin(base);
+    let (target_origin, target_path) = split_origin(target);
+    if base_origin != target_origin || base_path.starts_with('/') != target_path.starts_with('/') {
+        return target.to_string();
+    }
+
+    let base_directories: Vec<&str> = directory(base_path).split('/').filter(|s| !s.is_empty()).collect();
+    let mut target_segments: Vec<&str> = target_path.split('/').filter(|s| !s.is_empty()).collect();
+    let file = target_segments.pop().unwrap_or_default();
+
+    let common = base_directories
+        .iter()
+        .zip(&target_segments)
+        .take_while(|(base, target)| base == target)
+        .count();
+    // a relative base climbing out with `..` can't be walked back down
+    if base_directories[common..].contains(&"..") {
+        return target.to_string();
+    }
+
+    let mut relative: Vec<&str> = vec![".."; base_directories.len() - common];
+    relative.extend(&target_segments[common..]);
+    relative.push(file);
+    relative.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // <https://datatracker.ietf.org/doc/html/rfc3986#section-5.4>
+    #[test]
+    fn resolves_rfc_examples() {
+        let base = "http://a/b/c/d;p?q";
+        let examples = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"),
+            ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../", "http://a/"),
+            ("../../g", "http://a/g"),
+            // abnormal examples
+            ("../../../g", "http://a/g"),
+            ("../../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            (".g", "http://a/b/c/.g"),
+            ("g..", "http://a/b/c/g.."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+            ("http:g", "http:g"),
+        ];
+        for (reference, expected) in examples {
+            assert_eq!(resolve(base, reference), expected, "resolving '{}'", reference);
+        }
+    }
+
+    #[test]
+    fn resolves_against_relative_paths() {
+        assert_eq!(resolve("preroll/index.m3u8", "seg.ts"), "preroll/seg.ts");
+        assert_eq!(resolve("../ads/index.m3u8", "../seg.ts"), "../seg.ts");
+        assert_eq!(resolve("index.m3u8", "http://other/seg.ts"), "http://other/seg.ts");
+    }
+
+    #[test]
+    fn makes_relative_paths() {
+        assert_eq!(relative("https://cdn.example/out/index.m3u8", "https://cdn.example/pre/seg.ts"), "../pre/seg.ts");
+        assert_eq!(relative("out/index.m3u8", "out/hd/seg.ts"), "hd/seg.ts");
+        assert_eq!(relative("index.m3u8", "pre/seg.ts"), "pre/seg.ts");
+        assert_eq!(relative("https://a.example/index.m3u8", "https://b.example/seg.ts"), "https://b.example/seg.ts");
+    }
+}
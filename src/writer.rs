@@ -0,0 +1,230 @@
+//! Serialization of a [`MediaPlaylist`] back to `ext-m3u` text.
+//!
+//! The output only contains the tags the parser understands, so
+//! `MediaPlaylist::parse_ext_m3u(&playlist.to_string())` gives back an equal playlist. The
+//! exceptions are partial segments, which are only counted (see
+//! [`MediaPlaylist::pending_parts`]) and so cannot be written back, and a segment without
+//! EXT-X-MAP after one with it, which reads back with the previous map: no tag takes a map
+//! back. [`MediaPlaylist::concat`] refuses to build such a playlist.
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+
+use crate::media_playlist::{ByteRange, DateRange, Key, Map, MediaPlaylist, PlaylistType, ServerControl, StartPoint};
+
+impl fmt::Display for MediaPlaylist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#EXTM3U")?;
+        writeln!(f, "#EXT-X-VERSION:{}", self.version)?;
+        writeln!(f, "#EXT-X-TARGETDURATION:{}", self.target_duration.as_secs())?;
+        writeln!(f, "#EXT-X-MEDIA-SEQUENCE:{}", self.media_sequence)?;
+        if let Some(playlist_type) = self.playlist_type {
+            writeln!(f, "#EXT-X-PLAYLIST-TYPE:{}", playlist_type)?;
+        }
+        if self.discontinuity_sequence != 0 {
+            writeln!(f, "#EXT-X-DISCONTINUITY-SEQUENCE:{}", self.discontinuity_sequence)?;
+        }
+        if let Some(start) = self.start {
+            writeln!(f, "#EXT-X-START:{}", start)?;
+        }
+        if let Some(server_control) = self.server_control {
+            writeln!(f, "#EXT-X-SERVER-CONTROL:{}", server_control)?;
+        }
+        if let Some(part_target) = self.part_target {
+            writeln!(f, "#EXT-X-PART-INF:PART-TARGET={}", part_target.as_secs_f64())?;
+        }
+        for date_range in &self.date_ranges {
+            writeln!(f, "#EXT-X-DATERANGE:{}", date_range)?;
+        }
+
+        // every step of the discontinuity sequence number is one EXT-X-DISCONTINUITY tag
+        let mut discontinuity_sequence = self.discontinuity_sequence;
+        // EXT-X-KEY and EXT-X-MAP are only written when they change
+        let mut key = None;
+        let mut map = None;
+        for group in self.discontinuity_groups() {
+            for _ in discontinuity_sequence..group.discontinuity_sequence {
+                writeln!(f, "#EXT-X-DISCONTINUITY")?;
+            }
+            discontinuity_sequence = group.discontinuity_sequence;
+
+            for segment in group.discontinuity_segments() {
+                if segment.key.as_ref() != key {
+                    match &segment.key {
+                        Some(segment_key) => writeln!(f, "#EXT-X-KEY:{}", segment_key)?,
+                        None => writeln!(f, "#EXT-X-KEY:METHOD=NONE")?,
+                    }
+                    key = segment.key.as_ref();
+                }
+                if segment.map.as_ref() != map {
+                    if let Some(segment_map) = &segment.map {
+                        writeln!(f, "#EXT-X-MAP:{}", segment_map)?;
+                    }
+                    map = segment.map.as_ref();
+                }
+                if let Some(program_date_time) = segment.program_date_time {
+                    let program_date_time = program_date_time.to_rfc3339_opts(SecondsFormat::Millis, false);
+                    writeln!(f, "#EXT-X-PROGRAM-DATE-TIME:{}", program_date_time)?;
+                }
+                if segment.gap {
+                    writeln!(f, "#EXT-X-GAP")?;
+                }
+                // millisecond precision is what the parser keeps for discontinuity durations
+                writeln!(f, "#EXTINF:{:.3},", segment.duration.as_secs_f64())?;
+                if let Some(byte_range) = segment.byte_range {
+                    writeln!(f, "#EXT-X-BYTERANGE:{}", byte_range)?;
+                }
+                writeln!(f, "{}", segment.url)?;
+            }
+        }
+
+        if self.ended {
+            writeln!(f, "#EXT-X-ENDLIST")?;
+        }
+        Ok(())
+    }
+}
+
+// The tags of a playlist and its segments are written with the attribute lists below, which
+// the diff renders too.
+
+impl fmt::Display for StartPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TIME-OFFSET={}", self.time_offset)?;
+        if self.precise {
+            write!(f, ",PRECISE=YES")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlaylistType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaylistType::Event => write!(f, "EVENT"),
+            PlaylistType::Vod => write!(f, "VOD"),
+        }
+    }
+}
+
+impl fmt::Display for ServerControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut attributes = Vec::new();
+        if let Some(can_skip_until) = self.can_skip_until {
+            attributes.push(format!("CAN-SKIP-UNTIL={}", can_skip_until.as_secs_f64()));
+        }
+        if self.can_skip_dateranges {
+            attributes.push("CAN-SKIP-DATERANGES=YES".to_string());
+        }
+        if let Some(hold_back) = self.hold_back {
+            attributes.push(format!("HOLD-BACK={}", hold_back.as_secs_f64()));
+        }
+        if let Some(part_hold_back) = self.part_hold_back {
+            attributes.push(format!("PART-HOLD-BACK={}", part_hold_back.as_secs_f64()));
+        }
+        if self.can_block_reload {
+            attributes.push("CAN-BLOCK-RELOAD=YES".to_string());
+        }
+        write!(f, "{}", attributes.join(","))
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = |date: &DateTime<FixedOffset>| date.to_rfc3339_opts(SecondsFormat::Millis, false);
+        write!(f, "ID=\"{}\"", self.id)?;
+        if let Some(class) = &self.class {
+            write!(f, ",CLASS=\"{}\"", class)?;
+        }
+        write!(f, ",START-DATE=\"{}\"", date(&self.start_date))?;
+        if let Some(end_date) = &self.end_date {
+            write!(f, ",END-DATE=\"{}\"", date(end_date))?;
+        }
+        if let Some(duration) = self.duration {
+            write!(f, ",DURATION={}", duration.as_secs_f64())?;
+        }
+        if let Some(planned_duration) = self.planned_duration {
+            write!(f, ",PLANNED-DURATION={}", planned_duration.as_secs_f64())?;
+        }
+        for (name, value) in &self.attributes {
+            write!(f, ",{}={}", name, value)?;
+        }
+        if self.end_on_next {
+            write!(f, ",END-ON-NEXT=YES")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "METHOD={}", self.method)?;
+        if let Some(uri) = &self.uri {
+            write!(f, ",URI=\"{}\"", uri)?;
+        }
+        if let Some(iv) = &self.iv {
+            write!(f, ",IV={}", iv)?;
+        }
+        if let Some(key_format) = &self.key_format {
+            write!(f, ",KEYFORMAT=\"{}\"", key_format)?;
+        }
+        if let Some(key_format_versions) = &self.key_format_versions {
+            write!(f, ",KEYFORMATVERSIONS=\"{}\"", key_format_versions)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "URI=\"{}\"", self.uri)?;
+        if let Some(byte_range) = self.byte_range {
+            write!(f, ",BYTERANGE=\"{}\"", byte_range)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.length)?;
+        if let Some(offset) = self.offset {
+            write!(f, "@{}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_parser() {
+        let source = indoc::indoc! {r#"
+            #EXTM3U
+            #EXT-X-VERSION:3
+            #EXT-X-TARGETDURATION:10
+            #EXT-X-MEDIA-SEQUENCE:7
+            #EXT-X-DISCONTINUITY-SEQUENCE:2
+            #EXT-X-START:TIME-OFFSET=-2.5,PRECISE=YES
+            #EXT-X-DISCONTINUITY
+            #EXT-X-PROGRAM-DATE-TIME:2015-08-25T01:59:23.708+00:00
+            #EXTINF:9.009,
+            a.ts
+            #EXT-X-DISCONTINUITY
+            #EXT-X-KEY:METHOD=AES-128,URI="key.bin",IV=0x0F
+            #EXT-X-MAP:URI="init.mp4",BYTERANGE="720@0"
+            #EXTINF:4.500,
+            b.m4s
+            #EXT-X-KEY:METHOD=NONE
+            #EXTINF:4.500,
+            c.m4s
+            #EXT-X-ENDLIST
+        "#};
+        let playlist = MediaPlaylist::parse_ext_m3u(source).expect("playlist should parse");
+        assert_eq!(playlist.to_string(), source);
+        assert_eq!(MediaPlaylist::parse_ext_m3u(&playlist.to_string()).unwrap(), playlist);
+    }
+}